@@ -0,0 +1,128 @@
+//! Benchmarks for the core alignment functions.
+//!
+//! Run with `cargo bench`, or e.g. `cargo bench -- align_pair` for some of them.  Criterion
+//! reports each time with its change since the last run.  Sequences are generated from a fixed
+//! seed, so runs are comparable across commits.
+
+use align::cli::EmptyPid;
+use align::{alignment_length, count_identities, percent_identity, show_alignment_ops};
+use bio::alignment::pairwise::Aligner;
+use bio::alignment::Alignment;
+use bio::scores::{blosum62, pam250};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const AMINO_ACIDS: &[u8] = b"ACDEFGHIKLMNPQRSTVWY";
+
+/// Intein-ish lengths: mini-inteins are ~150 residues, full inteins with an endonuclease domain
+/// are ~450.
+const LENGTHS: [usize; 3] = [150, 300, 450];
+
+const GAP_OPEN: i32 = -10;
+const GAP_EXTEND: i32 = -1;
+
+/// A substitution matrix, as rust-bio's aligner takes it.
+type MatchFn = fn(u8, u8) -> i32;
+
+/// A tiny LCG so the benchmarks don't need a random number crate.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+}
+
+fn random_seq(rng: &mut Lcg, len: usize) -> Vec<u8> {
+    (0..len)
+        .map(|_| AMINO_ACIDS[rng.next() as usize % AMINO_ACIDS.len()])
+        .collect()
+}
+
+/// Mutate roughly `frac` of the residues so the pair is related but not identical.
+fn mutate(rng: &mut Lcg, seq: &[u8], frac: f64) -> Vec<u8> {
+    let cutoff = (frac * 1000.0) as u64;
+
+    seq.iter()
+        .map(|&c| {
+            if rng.next() % 1000 < cutoff {
+                AMINO_ACIDS[rng.next() as usize % AMINO_ACIDS.len()]
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// A related pair of sequences of each of the `LENGTHS`, the same every run.
+fn test_pairs() -> Vec<(usize, Vec<u8>, Vec<u8>)> {
+    let mut rng = Lcg(42);
+
+    LENGTHS
+        .iter()
+        .map(|&len| {
+            let x = random_seq(&mut rng, len);
+            let y = mutate(&mut rng, &x, 0.4);
+            (len, x, y)
+        })
+        .collect()
+}
+
+fn alignment_stats(c: &mut Criterion) {
+    let mut aligner = Aligner::new(GAP_OPEN, GAP_EXTEND, &blosum62);
+    let alignments: Vec<(usize, Alignment)> = test_pairs()
+        .iter()
+        .map(|(len, x, y)| (*len, aligner.global(x, y)))
+        .collect();
+
+    let mut group = c.benchmark_group("count_identities");
+    for (len, alignment) in &alignments {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(len),
+            alignment,
+            |b, alignment| b.iter(|| count_identities(black_box(alignment))),
+        );
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("show_alignment_ops");
+    for (len, alignment) in &alignments {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(len),
+            alignment,
+            |b, alignment| b.iter(|| show_alignment_ops(black_box(alignment))),
+        );
+    }
+    group.finish();
+}
+
+fn align_pair(c: &mut Criterion) {
+    let pairs = test_pairs();
+    let matrices: [(&str, MatchFn); 2] = [("blosum62", blosum62), ("pam250", pam250)];
+
+    let mut group = c.benchmark_group("align_pair");
+    // A pair of the longest sequences takes milliseconds, so don't wait for the default 100.
+    group.sample_size(20);
+    for (name, matrix) in matrices {
+        for (len, x, y) in &pairs {
+            let mut aligner = Aligner::new(GAP_OPEN, GAP_EXTEND, matrix);
+            group.bench_function(BenchmarkId::new(name, len), |b| {
+                b.iter(|| {
+                    let alignment = aligner.global(black_box(x), black_box(y));
+                    percent_identity(
+                        alignment_length(&alignment),
+                        count_identities(&alignment),
+                        EmptyPid::Zero,
+                    )
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, alignment_stats, align_pair);
+criterion_main!(benches);
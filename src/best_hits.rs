@@ -0,0 +1,143 @@
+//! The best non-self hit of each record, for reciprocal-best-hit style analyses.
+
+use bio::io::fasta::Record;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// The best partner found so far for one record.
+#[derive(Copy, Clone)]
+struct Hit {
+    partner: usize,
+    aln_len: i32,
+    num_matches: i32,
+    percent_identity: f64,
+    /// How many other partners had the same percent identity.
+    ties: usize,
+}
+
+/// Best hits built up as alignments finish.
+///
+/// Ties are broken in favor of the partner that comes first in the input, so the result doesn't
+/// depend on the order in which alignments finished.
+pub struct BestHits {
+    hits: Mutex<Vec<Option<Hit>>>,
+}
+
+impl BestHits {
+    pub fn new(num_records: usize) -> Self {
+        Self {
+            hits: Mutex::new(vec![None; num_records]),
+        }
+    }
+
+    /// Record the alignment of `x_index` and `y_index` as a candidate hit for both.
+    pub fn add(
+        &self,
+        x_index: usize,
+        y_index: usize,
+        aln_len: i32,
+        num_matches: i32,
+        percent_identity: f64,
+    ) {
+        if x_index == y_index {
+            return;
+        }
+
+        let mut hits = self.hits.lock().unwrap();
+        for (query, partner) in [(x_index, y_index), (y_index, x_index)] {
+            let hit = Hit {
+                partner,
+                aln_len,
+                num_matches,
+                percent_identity,
+                ties: 0,
+            };
+
+            hits[query] = match hits[query] {
+                None => Some(hit),
+                Some(best) if percent_identity > best.percent_identity => Some(hit),
+                Some(best) if percent_identity == best.percent_identity => {
+                    let ties = best.ties + 1;
+                    if partner < best.partner {
+                        Some(Hit { ties, ..hit })
+                    } else {
+                        Some(Hit { ties, ..best })
+                    }
+                }
+                best => best,
+            };
+        }
+    }
+
+    /// Write the `query<TAB>hit<TAB>alnlen<TAB>matches<TAB>pid<TAB>ties` table.
+    ///
+    /// Records without any hits (e.g., every pair was skipped) are left out.
+    pub fn write(&self, path: &Path, records: &[Record], precision: usize) {
+        let mut out = BufWriter::new(File::create(path).unwrap());
+        let hits = self.hits.lock().unwrap();
+
+        writeln!(out, "query\thit\talnlen\tmatches\tpid\tties").unwrap();
+        records.iter().zip(hits.iter()).for_each(|(record, hit)| {
+            if let Some(hit) = hit {
+                writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}\t{:.precision$}\t{}",
+                    record.id(),
+                    records[hit.partner].id(),
+                    hit.aln_len,
+                    hit.num_matches,
+                    hit.percent_identity,
+                    hit.ties
+                )
+                .unwrap();
+            }
+        });
+    }
+
+    /// Write the pairs that are each other's best hit, once per pair, as
+    /// `x<TAB>y<TAB>alnlen<TAB>matches<TAB>pid`.
+    ///
+    /// Since ties are broken by input order, a pair tied with another hit only counts if the
+    /// tie-break picks it in both directions.
+    pub fn write_reciprocal(&self, path: &Path, records: &[Record], precision: usize) {
+        let mut out = BufWriter::new(File::create(path).unwrap());
+        let hits = self.hits.lock().unwrap();
+
+        writeln!(out, "x\ty\talnlen\tmatches\tpid").unwrap();
+        hits.iter().enumerate().for_each(|(i, hit)| {
+            if let Some(hit) = hit {
+                let j = hit.partner;
+                let reciprocal = hits[j].is_some_and(|back| back.partner == i);
+                if i < j && reciprocal {
+                    writeln!(
+                        out,
+                        "{}\t{}\t{}\t{}\t{:.precision$}",
+                        records[i].id(),
+                        records[j].id(),
+                        hit.aln_len,
+                        hit.num_matches,
+                        hit.percent_identity,
+                    )
+                    .unwrap();
+                }
+            }
+        });
+    }
+}
+
+#[test]
+fn ties_go_to_the_first_partner() {
+    let best_hits = BestHits::new(4);
+
+    best_hits.add(0, 3, 10, 8, 0.8);
+    best_hits.add(0, 0, 10, 10, 1.0);
+    best_hits.add(0, 2, 10, 8, 0.8);
+    best_hits.add(0, 1, 10, 5, 0.5);
+
+    let hits = best_hits.hits.lock().unwrap();
+    let hit = hits[0].unwrap();
+    assert_eq!((hit.partner, hit.ties), (2, 1));
+    assert_eq!(hits[1].unwrap().partner, 0);
+}
@@ -0,0 +1,64 @@
+//! Extra output files that split the rows by pid.
+
+use crate::error::{Error, Result};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// One output file per pid range.
+///
+/// With breakpoints `b1 < b2 < ... < bn`, the ranges are `[0, b1)`, `[b1, b2)`, ..., `[bn, 1]`.
+pub struct Bins {
+    breakpoints: Vec<f64>,
+    writers: Vec<Mutex<BufWriter<File>>>,
+}
+
+impl Bins {
+    /// Create `dir` and an empty file in it for each bin.
+    pub fn new(dir: &Path, breakpoints: &[f64]) -> Result<Self> {
+        if breakpoints.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(Error::parse("bin breakpoints must be increasing"));
+        }
+
+        fs::create_dir_all(dir).map_err(|source| Error::Io {
+            path: dir.to_path_buf(),
+            source,
+        })?;
+
+        let bounds = std::iter::once(0.0)
+            .chain(breakpoints.iter().copied())
+            .chain(std::iter::once(1.0))
+            .collect::<Vec<_>>();
+
+        let writers = bounds
+            .windows(2)
+            .map(|w| {
+                let path = dir.join(format!("pid_{}-{}.tsv", w[0], w[1]));
+                File::create(&path)
+                    .map(|file| Mutex::new(BufWriter::new(file)))
+                    .map_err(|source| Error::Io { path, source })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            breakpoints: breakpoints.to_vec(),
+            writers,
+        })
+    }
+
+    /// The writer for the bin containing `percent_identity`.
+    pub fn writer(&self, percent_identity: f64) -> &Mutex<BufWriter<File>> {
+        let i = self
+            .breakpoints
+            .iter()
+            .take_while(|&&b| percent_identity >= b)
+            .count();
+
+        &self.writers[i]
+    }
+
+    pub fn writers(&self) -> impl Iterator<Item = &Mutex<BufWriter<File>>> {
+        self.writers.iter()
+    }
+}
@@ -0,0 +1,65 @@
+//! A global limit on the DP cells of the pairs in flight, to bound peak memory.
+
+use std::sync::{Arc, Condvar, Mutex};
+
+/// A semaphore weighted by DP cell count.
+pub struct CellBudget {
+    limit: usize,
+    in_use: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl CellBudget {
+    pub fn new(limit: usize) -> Arc<Self> {
+        Arc::new(Self {
+            limit,
+            in_use: Mutex::new(0),
+            freed: Condvar::new(),
+        })
+    }
+
+    /// Block until `cells` fit in the budget, then reserve them until the reservation drops.
+    ///
+    /// A pair bigger than the whole budget is let through once nothing else is in flight, so that
+    /// it can't block forever.
+    pub fn reserve(self: &Arc<Self>, cells: usize) -> Reservation {
+        let mut in_use = self.in_use.lock().unwrap();
+        while *in_use > 0 && *in_use + cells > self.limit {
+            in_use = self.freed.wait(in_use).unwrap();
+        }
+        *in_use += cells;
+
+        Reservation {
+            budget: Arc::clone(self),
+            cells,
+        }
+    }
+}
+
+/// Cells reserved for one pair.  They are given back when this is dropped.
+pub struct Reservation {
+    budget: Arc<CellBudget>,
+    cells: usize,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        *self.budget.in_use.lock().unwrap() -= self.cells;
+        self.budget.freed.notify_all();
+    }
+}
+
+#[test]
+fn reservations_are_given_back() {
+    let budget = CellBudget::new(100);
+
+    let a = budget.reserve(60);
+    let b = budget.reserve(40);
+    assert_eq!(*budget.in_use.lock().unwrap(), 100);
+
+    drop(a);
+    drop(b);
+    // Bigger than the budget, but nothing else is in flight.
+    let _c = budget.reserve(500);
+    assert_eq!(*budget.in_use.lock().unwrap(), 500);
+}
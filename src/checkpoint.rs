@@ -0,0 +1,95 @@
+//! Periodic checkpoints of how far the output has gotten, for `--checkpoint-interval`.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Tracks which pairs are finished, and writes the checkpoint file every `interval` of them.
+///
+/// Pairs are numbered in the order they're sent to the workers, but finish in any order.  The
+/// checkpoint only covers the run of pairs from the first one that are all finished, so every
+/// pair before the recorded one has had its rows written.
+pub struct Checkpoint {
+    path: PathBuf,
+    interval: usize,
+    /// Pairs finished since the last checkpoint.
+    since_last: usize,
+    /// Every pair before this one is finished.
+    next: usize,
+    /// Finished pairs after `next`.
+    pending: BTreeSet<usize>,
+}
+
+impl Checkpoint {
+    /// A checkpoint for the output file `out_file`, written next to it.
+    pub fn new(out_file: &Path, interval: usize) -> Self {
+        let mut path = out_file.as_os_str().to_owned();
+        path.push(".checkpoint");
+
+        Self {
+            path: PathBuf::from(path),
+            interval,
+            since_last: 0,
+            next: 0,
+            pending: BTreeSet::new(),
+        }
+    }
+
+    /// Mark pair `index` finished.  Returns true if it's time to write a checkpoint.
+    pub fn finish(&mut self, index: usize) -> bool {
+        self.pending.insert(index);
+        while self.pending.remove(&self.next) {
+            self.next += 1;
+        }
+
+        self.since_last += 1;
+        if self.since_last >= self.interval {
+            self.since_last = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Write the index of the last pair of the finished run, if there is one.
+    ///
+    /// The file is written under a temporary name and then renamed, so it's never seen half
+    /// written.  The outputs should be flushed first.
+    pub fn write(&self) -> io::Result<()> {
+        let Some(last) = self.next.checked_sub(1) else {
+            return Ok(());
+        };
+
+        let mut tmp = self.path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        let mut file = fs::File::create(&tmp)?;
+        writeln!(file, "last_completed_pair\t{last}")?;
+        file.sync_all()?;
+
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+#[test]
+fn checkpoint_covers_the_finished_run() {
+    let dir = std::env::temp_dir().join(format!("align-checkpoint-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut checkpoint = Checkpoint::new(&dir.join("out.tsv"), 2);
+
+    assert!(!checkpoint.finish(1));
+    // Pair 0 isn't done yet, so there's nothing to record.
+    assert!(checkpoint.finish(2));
+    checkpoint.write().unwrap();
+    assert!(!dir.join("out.tsv.checkpoint").exists());
+
+    assert!(!checkpoint.finish(0));
+    assert!(checkpoint.finish(4));
+    checkpoint.write().unwrap();
+    let contents = fs::read_to_string(dir.join("out.tsv.checkpoint")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(contents, "last_completed_pair\t2\n");
+}
@@ -0,0 +1,280 @@
+//! Scoring that depends on the structural class (e.g., helix, sheet, or coil) of each residue,
+//! for `--class-file`.
+//!
+//! rust-bio's aligner only passes the two residues to the scoring function, not their
+//! positions.  So each residue is encoded, along with its class, as a single byte, and the
+//! encoded sequences are aligned with a matrix over those bytes.  Afterwards the matches are
+//! worked out again from the original residues.
+
+use crate::error::{Error, Result};
+use crate::scoring::Matrix;
+use bio::alignment::{Alignment, AlignmentOperation};
+use bio::io::fasta::Record;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// A table of `id<TAB>classes` lines, with one class character per residue.
+#[derive(Clone)]
+pub struct ClassTable {
+    classes: HashMap<String, Vec<u8>>,
+}
+
+impl ClassTable {
+    pub fn from_text(text: &str) -> Result<Self> {
+        let classes = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| match line.split('\t').collect::<Vec<_>>()[..] {
+                [id, classes] => Ok((id.trim().to_string(), classes.trim().as_bytes().to_vec())),
+                _ => Err(Error::parse(format!(
+                    "line {} should have an id and its classes, separated by a tab",
+                    i + 1
+                ))),
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { classes })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::from_text(&text).map_err(|e| Error::InvalidFile {
+            kind: "class table",
+            path: path.to_path_buf(),
+            source: Box::new(e),
+        })
+    }
+}
+
+/// The encoded alphabet and its matrix, and the classes of each record.
+pub struct ClassScoring {
+    /// The matrix over encoded residues.
+    pub matrix: Matrix,
+    /// The index of each residue in the alphabet.
+    residue_index: [u8; 256],
+    num_residues: usize,
+    /// The class index of each residue of each record.  `None` if the record isn't in the table,
+    /// in which case all its residues are in the unknown class, 0.
+    classes: Vec<Option<Vec<u8>>>,
+}
+
+impl ClassScoring {
+    /// Residues of the same class score `bonus` more than in `base`, and of different classes
+    /// `penalty` less.  The unknown class scores neither.
+    pub fn new(
+        table: &ClassTable,
+        records: &[Record],
+        base: &Matrix,
+        bonus: i32,
+        penalty: i32,
+    ) -> Result<Self> {
+        let classes = records
+            .iter()
+            .map(|record| table.classes.get(record.id()).cloned())
+            .collect::<Vec<_>>();
+
+        let num_missing = classes.iter().filter(|c| c.is_none()).count();
+        if num_missing > 0 {
+            eprintln!("warning: {num_missing} records aren't in the class table");
+        }
+
+        Self::from_classes(classes, records, base, bonus, penalty, None)
+    }
+
+    /// Like `new`, but with the class characters of each record given directly.
+    ///
+    /// Residues with the `unclassed` character, if any, are in the unknown class, like those of
+    /// records without classes.
+    pub fn from_classes(
+        classes: Vec<Option<Vec<u8>>>,
+        records: &[Record],
+        base: &Matrix,
+        bonus: i32,
+        penalty: i32,
+        unclassed: Option<u8>,
+    ) -> Result<Self> {
+        let alphabet = records
+            .iter()
+            .flat_map(|r| r.seq().iter().copied())
+            .collect::<BTreeSet<u8>>();
+        let class_names = classes
+            .iter()
+            .flatten()
+            .flatten()
+            .copied()
+            .filter(|&c| Some(c) != unclassed)
+            .collect::<BTreeSet<u8>>();
+        // Class 0 is for records that aren't in the table.
+        let num_classes = class_names.len() + 1;
+
+        if alphabet.len() * num_classes > 256 {
+            return Err(Error::parse(format!(
+                "{} residues in {} classes are too many to encode",
+                alphabet.len(),
+                num_classes - 1
+            )));
+        }
+
+        let mut residue_index = [0; 256];
+        alphabet
+            .iter()
+            .enumerate()
+            .for_each(|(i, &residue)| residue_index[usize::from(residue)] = i as u8);
+        // Anything not in the class names, e.g., `unclassed`, stays in class 0.
+        let mut class_index = [0; 256];
+        class_names
+            .iter()
+            .enumerate()
+            .for_each(|(i, &class)| class_index[usize::from(class)] = i as u8 + 1);
+
+        let mismatched = records
+            .iter()
+            .zip(&classes)
+            .find(|(r, c)| c.as_ref().is_some_and(|c| c.len() != r.seq().len()));
+        if let Some((record, _)) = mismatched {
+            return Err(Error::parse(format!(
+                "the classes of {} don't match its length",
+                record.id()
+            )));
+        }
+
+        let classes = classes
+            .into_iter()
+            .map(|classes| {
+                classes.map(|classes| {
+                    classes
+                        .iter()
+                        .map(|&c| class_index[usize::from(c)])
+                        .collect()
+                })
+            })
+            .collect::<Vec<Option<Vec<u8>>>>();
+
+        let alphabet = alphabet.into_iter().collect::<Vec<_>>();
+        let num_residues = alphabet.len();
+        let codes = (0..num_residues * num_classes)
+            .map(|code| code as u8)
+            .collect::<Vec<_>>();
+        let matrix = Matrix::from_fn(&codes, |a, b| {
+            let (a, b) = (usize::from(a), usize::from(b));
+            let (a_class, b_class) = (a / num_residues, b / num_residues);
+            let score = base.score(alphabet[a % num_residues], alphabet[b % num_residues]);
+
+            match (a_class, b_class) {
+                (0, _) | (_, 0) => score,
+                _ if a_class == b_class => score + bonus,
+                _ => score - penalty,
+            }
+        });
+
+        Ok(Self {
+            matrix,
+            residue_index,
+            num_residues,
+            classes,
+        })
+    }
+
+    /// Encode `seq`, the sequence of record `index` (reversed if `reversed`), with its classes.
+    pub fn encode(&self, index: usize, seq: &[u8], reversed: bool) -> Vec<u8> {
+        let class = |i: usize| match &self.classes[index] {
+            Some(classes) if reversed => classes[classes.len() - 1 - i],
+            Some(classes) => classes[i],
+            None => 0,
+        };
+
+        seq.iter()
+            .enumerate()
+            .map(|(i, &residue)| {
+                let code = usize::from(class(i)) * self.num_residues
+                    + usize::from(self.residue_index[usize::from(residue)]);
+                code as u8
+            })
+            .collect()
+    }
+
+    /// Do records `i` and `j` have the same classes (or are both missing from the table)?
+    pub fn same_classes(&self, i: usize, j: usize) -> bool {
+        self.classes[i] == self.classes[j]
+    }
+
+    /// The score of record `index` against itself.
+    pub fn self_score(&self, index: usize, seq: &[u8]) -> i32 {
+        self.encode(index, seq, false)
+            .iter()
+            .map(|&code| self.matrix.score(code, code))
+            .sum()
+    }
+}
+
+/// Work out which columns of an alignment of encoded sequences are matches of the original
+/// residues `x` and `y`, since residues of different classes encode differently.
+pub fn restore_matches(alignment: &mut Alignment, x: &[u8], y: &[u8]) {
+    mark_matches(alignment, x, y, |a, b| a == b);
+}
+
+/// Mark each aligned column of residues `x` and `y` as a match if `same` says they are, or as a
+/// substitution otherwise.
+pub fn mark_matches(alignment: &mut Alignment, x: &[u8], y: &[u8], same: impl Fn(u8, u8) -> bool) {
+    let (mut i, mut j) = (0, 0);
+
+    for op in alignment.operations.iter_mut() {
+        match op {
+            AlignmentOperation::Match | AlignmentOperation::Subst => {
+                *op = if same(x[i], y[j]) {
+                    AlignmentOperation::Match
+                } else {
+                    AlignmentOperation::Subst
+                };
+                i += 1;
+                j += 1;
+            }
+            AlignmentOperation::Ins => i += 1,
+            AlignmentOperation::Del => j += 1,
+            AlignmentOperation::Xclip(n) => i += *n,
+            AlignmentOperation::Yclip(n) => j += *n,
+        }
+    }
+}
+
+#[test]
+fn classes_add_to_the_matrix_scores() {
+    let records = vec![
+        Record::with_attrs("a", None, b"AC"),
+        Record::with_attrs("b", None, b"AC"),
+        Record::with_attrs("c", None, b"AC"),
+    ];
+    let table = ClassTable::from_text("a\tHE\nb\tHH\n").unwrap();
+    assert!(ClassTable::from_text("a HE\n").is_err());
+
+    let base = Matrix::blosum62();
+    let scoring = ClassScoring::new(&table, &records, &base, 2, 3).unwrap();
+    let (a, b, c) = (
+        scoring.encode(0, b"AC", false),
+        scoring.encode(1, b"AC", false),
+        scoring.encode(2, b"AC", false),
+    );
+    let score = |x: &[u8], y: &[u8]| {
+        x.iter()
+            .zip(y)
+            .map(|(&x, &y)| scoring.matrix.score(x, y))
+            .sum::<i32>()
+    };
+
+    // A is H in both, and C is E vs. H.
+    assert_eq!(score(&a, &b), 4 + 2 + 9 - 3);
+    // c isn't in the table, so its residues score as usual.
+    assert_eq!(score(&a, &c), 4 + 9);
+    assert_eq!(scoring.self_score(0, b"AC"), 4 + 2 + 9 + 2);
+    assert!(!scoring.same_classes(0, 1));
+    assert_eq!(scoring.encode(0, b"CA", true), vec![a[1], a[0]]);
+
+    let bad = ClassTable::from_text("a\tH\n").unwrap();
+    assert!(ClassScoring::new(&bad, &records, &base, 2, 3).is_err());
+}
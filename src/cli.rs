@@ -1,41 +1,365 @@
-use std::path::PathBuf;
+use std::cmp::Ordering;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
-use clap::Parser;
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use regex::Regex;
+
+use crate::classes::ClassTable;
+use crate::domains::Domains;
+use crate::error::{Error, Result};
+use crate::expr::ComputedColumn;
+use crate::gap_profile::GapProfile;
+use crate::groups::GroupTable;
+use crate::input::RelabelTable;
+use crate::output::is_named_pipe;
+use crate::pair_list::PairList;
+use crate::scoring::{Matrix, ScoreTable};
+use crate::shard::Shard;
+use crate::weights::WeightTable;
+use crate::PairResult;
 
 /// Returns Ok if the `file_name` is for a existing file.
-fn exists(file_name: &str) -> Result<PathBuf, String> {
+fn exists(file_name: &str) -> Result<PathBuf> {
     let path = PathBuf::from(file_name);
 
     if path.exists() {
         Ok(path)
     } else {
-        Err(format!("file {file_name} does not exist"))
+        Err(Error::FileNotFound(path))
+    }
+}
+
+/// Like `exists`, but `-` is also Ok, for reading stdin.
+fn input(file_name: &str) -> Result<PathBuf> {
+    if file_name == "-" {
+        Ok(PathBuf::from(file_name))
+    } else {
+        exists(file_name)
     }
 }
 
 /// Returns Ok if the `file_name` is NOT an existing file.
-fn doesnt_exist(file_name: &str) -> Result<PathBuf, String> {
+fn doesnt_exist(file_name: &str) -> Result<PathBuf> {
     let path = PathBuf::from(file_name);
 
     if path.exists() {
-        Err(format!("file {file_name} already exists"))
+        Err(Error::OutputExists(path))
     } else {
         Ok(path)
     }
 }
 
+/// Like `doesnt_exist`, but an existing named pipe is fine too, for streaming the rows.
+fn output_file(file_name: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(file_name);
+
+    if is_named_pipe(&path) {
+        Ok(path)
+    } else {
+        doesnt_exist(file_name)
+    }
+}
+
+/// Returns Ok if `s` is a number between 0 and 1 (inclusive).
+fn fraction(s: &str) -> Result<f64> {
+    let x: f64 = s
+        .parse()
+        .map_err(|_| Error::parse(format!("{s} is not a number")))?;
+
+    if (0.0..=1.0).contains(&x) {
+        Ok(x)
+    } else {
+        Err(Error::parse(format!("{s} is not between 0 and 1")))
+    }
+}
+
+/// Returns Ok if `s` is a number greater than 0.
+fn positive(s: &str) -> Result<f64> {
+    let x: f64 = s
+        .parse()
+        .map_err(|_| Error::parse(format!("{s} is not a number")))?;
+
+    if x > 0.0 {
+        Ok(x)
+    } else {
+        Err(Error::parse(format!("{s} is not greater than 0")))
+    }
+}
+
+/// How many pairs are held at a time to write the rows in input order, without --stable-blocks.
+const DEFAULT_STABLE_BLOCK_SIZE: usize = 1000;
+
+/// Scores are multiplied by this when a gap penalty has a fraction, so they stay integers.
+pub const FRACTIONAL_SCORE_SCALE: i32 = 10;
+
+/// Returns Ok if `s` is a gap penalty, a number of at least 0 with at most one decimal place,
+/// since that's all that `FRACTIONAL_SCORE_SCALE` can represent.
+fn gap_penalty(s: &str) -> Result<f64> {
+    let x: f64 = s
+        .parse()
+        .map_err(|_| Error::parse(format!("{s} is not a number")))?;
+    let scaled = x * f64::from(FRACTIONAL_SCORE_SCALE);
+
+    if !(0.0..=255.0).contains(&x) {
+        Err(Error::parse(format!("{s} is not between 0 and 255")))
+    } else if (scaled - scaled.round()).abs() > 1e-9 {
+        Err(Error::parse(format!(
+            "{s} has more than one decimal place, which can't be represented"
+        )))
+    } else {
+        Ok(x)
+    }
+}
+
+/// Parses a `--matrix`, a built-in matrix or a matrix file.
+fn matrix(s: &str) -> Result<Matrix> {
+    Matrix::from_name_or_file(s)
+}
+
+/// Parses a `name:weight,name:weight` matrix blend, where each name is a built-in matrix or a
+/// matrix file.
+fn matrix_blend(s: &str) -> Result<Matrix> {
+    let weighted = s
+        .split(',')
+        .map(|part| {
+            let (name, weight) = part
+                .rsplit_once(':')
+                .ok_or_else(|| Error::parse(format!("'{part}' should look like name:weight")))?;
+
+            let weight: f64 = weight
+                .parse()
+                .map_err(|_| Error::parse(format!("weight '{weight}' is not a number")))?;
+
+            if weight <= 0.0 {
+                return Err(Error::parse(format!("weight for {name} must be positive")));
+            }
+
+            Ok((Matrix::from_name_or_file(name)?, weight))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if weighted.len() < 2 {
+        return Err(Error::parse("a blend needs at least two matrices"));
+    }
+
+    Ok(Matrix::blend(&weighted))
+}
+
+/// Parses a regular expression.
+fn regex(s: &str) -> Result<Regex> {
+    Regex::new(s).map_err(|e| Error::parse(e.to_string()))
+}
+
+/// Parses a `--shard` of the form `I/N`.
+fn shard(s: &str) -> Result<Shard> {
+    Shard::parse(s)
+}
+
+/// Parses a `--groups` file.
+fn group_table(file_name: &str) -> Result<GroupTable> {
+    GroupTable::from_file(Path::new(file_name))
+}
+
+/// Parses a `--relabel` file.
+fn relabel_table(file_name: &str) -> Result<RelabelTable> {
+    RelabelTable::from_file(Path::new(file_name))
+}
+
+/// Parses a `--weights` file.
+fn weight_table(file_name: &str) -> Result<WeightTable> {
+    WeightTable::from_file(Path::new(file_name))
+}
+
+/// Parses a `--ref-domains` file.
+fn domain_table(file_name: &str) -> Result<Domains> {
+    Domains::from_file(Path::new(file_name))
+}
+
+/// Parses an `--msa-gap-profile` alignment.
+fn gap_profile(file_name: &str) -> Result<GapProfile> {
+    GapProfile::from_file(Path::new(file_name))
+}
+
+/// Parses an `--exclude-pairs` file.
+fn pair_list(file_name: &str) -> Result<PairList> {
+    PairList::from_file(Path::new(file_name))
+}
+
+/// Parses a `--compute` column.
+fn computed_column(s: &str) -> Result<ComputedColumn> {
+    ComputedColumn::parse(s)
+}
+
+/// Parses a `--class-file` file.
+fn class_table(file_name: &str) -> Result<ClassTable> {
+    ClassTable::from_file(Path::new(file_name))
+}
+
+/// Parses a `--score-table` file.
+fn score_table(file_name: &str) -> Result<ScoreTable> {
+    ScoreTable::from_file(Path::new(file_name))
+}
+
 #[derive(Parser)]
-#[command(version)]
+#[command(version, args_conflicts_with_subcommands = true)]
 /// Perform all-vs-all global alignments for the input sequences
+///
+/// If no subcommand is given, `align` is assumed, so `align seqs.faa out.tsv` and
+/// `align align seqs.faa out.tsv` do the same thing.
 pub struct Cli {
-    /// FASTA file input
-    #[arg(value_parser = exists)]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    align: Option<AlignArgs>,
+}
+
+impl Cli {
+    /// The subcommand to run, falling back to `align` if none was given.
+    pub fn into_command(self) -> Command {
+        match (self.command, self.align) {
+            (Some(command), _) => command,
+            (None, Some(args)) => Command::Align(args),
+            // Clap requires the align args when there is no subcommand.
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+// Only one of these is ever made, so the size difference doesn't matter.
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand)]
+pub enum Command {
+    /// Perform all-vs-all global alignments for the input sequences (default)
+    Align(AlignArgs),
+    /// Run the all-vs-all on random sequences and report throughput and correctness
+    ///
+    /// Useful for checking a build and tuning --threads on the target machine, without needing
+    /// any input files.
+    Selftest(SelftestArgs),
+    /// Check the input sequences for problems, without aligning them
+    ///
+    /// Reports empty sequences, duplicate ids, nonstandard residues, sequences that look like
+    /// DNA or RNA, and FASTA files that can't be read.  Exits with an error if there are any.
+    Validate(ValidateArgs),
+}
+
+#[derive(Args)]
+pub struct SelftestArgs {
+    /// Number of random sequences to generate
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(2..))]
+    num_seqs: u32,
+
+    /// Length of each random sequence
+    #[arg(long, default_value_t = 300, value_parser = clap::value_parser!(u32).range(1..))]
+    seq_len: u32,
+
+    /// Number of worker threads for aligning
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
+    pub threads: u8,
+
+    /// Seed for the random sequences
+    #[arg(long, default_value_t = 1)]
+    pub seed: u64,
+}
+
+#[derive(Args)]
+pub struct ValidateArgs {
+    /// FASTA file (or directory of FASTA files) to check, or - for stdin
+    #[arg(value_parser = input)]
+    pub in_file: PathBuf,
+
+    /// When the input is a directory, also check FASTA files in its subdirectories
+    #[arg(short, long, default_value_t = false)]
+    pub recursive: bool,
+}
+
+impl SelftestArgs {
+    pub fn num_seqs(&self) -> usize {
+        self.num_seqs as usize
+    }
+    pub fn seq_len(&self) -> usize {
+        self.seq_len as usize
+    }
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("grouping").args(["group_by_desc", "pair_by_id_pattern", "groups"])))]
+pub struct AlignArgs {
+    /// FASTA file input, or - for stdin
+    ///
+    /// If this is a directory, every .fasta and .fa file in it is read (see --recursive).  Gzipped
+    /// input is decompressed, and in a directory, .fasta.gz and .fa.gz files are read too.
+    #[arg(value_parser = input)]
     pub in_file: PathBuf,
 
     /// Output file name
-    #[arg(value_parser = doesnt_exist)]
+    ///
+    /// This can also be an existing named pipe (FIFO), to read the rows as they're written.
+    #[arg(value_parser = output_file)]
     pub out_file: PathBuf,
 
+    /// Create the directories of the output files if they don't exist
+    ///
+    /// Without this, a missing directory is an error before anything is aligned.
+    #[arg(long, default_value_t = false)]
+    pub mkdir: bool,
+
+    /// When the input is a directory, also read FASTA files in its subdirectories
+    #[arg(short, long, default_value_t = false)]
+    pub recursive: bool,
+
+    /// How to handle selenocysteine (U) and pyrrolysine (O), which BLOSUM62 doesn't really score
+    #[arg(long, value_enum, default_value_t = NonstandardAa::Substitute)]
+    pub nonstandard_aa: NonstandardAa,
+
+    /// Only read the first N sequences
+    ///
+    /// Handy for trying out a pipeline on part of a big file.
+    #[arg(long, value_name = "N")]
+    pub max_records: Option<usize>,
+
+    /// Print more about what's going on to stderr
+    #[arg(short, long, default_value_t = false)]
+    pub verbose: bool,
+
+    /// Skip sequences shorter than this
+    #[arg(long)]
+    pub min_seq_len: Option<usize>,
+
+    /// Skip sequences longer than this
+    #[arg(long)]
+    pub max_seq_len: Option<usize>,
+
+    /// Keep only the first K residues of longer sequences
+    ///
+    /// Unlike --max-seq-len, which drops long sequences, this keeps them but aligns only their
+    /// start, e.g., the N-terminal domain.  The length filters see the untruncated lengths, and
+    /// xlen and ylen in the output are the truncated ones.
+    #[arg(long, value_name = "K", value_parser = clap::value_parser!(u64).range(1..))]
+    pub truncate: Option<u64>,
+
+    /// Drop sequences that are exact substrings of other sequences
+    ///
+    /// Of a set of identical sequences, only the first is kept.  This happens after the length
+    /// filters and --truncate.  Use --verbose to see which sequence contained each dropped one.
+    #[arg(long, default_value_t = false)]
+    pub drop_substrings: bool,
+
+    /// Mask low-complexity regions with X before aligning
+    ///
+    /// Regions like poly-Q tracts and short repeats are found SEG-style, with windows of 12
+    /// residues under 2.2 bits of entropy, after --drop-substrings.  X scores about -1 against
+    /// everything in BLOSUM62, so masked regions add little to the score, and X vs. X columns
+    /// (including any X's already in the input) are counted as mismatches, not identities.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["profile", "reference", "alignment_free", "ungapped", "score_only"]
+    )]
+    pub mask_low_complexity: bool,
+
     /// Number of worker threads for aligning
     ///
     /// The total number of threads used by the program will be threads + 1.
@@ -44,30 +368,1651 @@ pub struct Cli {
     #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
     threads: u8,
 
-    /// Gap open penalty
-    #[arg(long, default_value_t = 10)]
-    gap_open: u8,
+    /// At the end, print how many pairs and residues each worker aligned to stderr
+    ///
+    /// Workers take the next pair from a shared queue whatever its length, so this shows how
+    /// evenly the residues were spread across them.  Self-hits that aren't aligned aren't
+    /// counted.
+    #[arg(long, default_value_t = false, conflicts_with_all = ["profile", "reference"])]
+    pub report_worker_load: bool,
 
-    /// Gap extend penalty
+    /// Show how many pairs have been aligned, out of how many, on stderr while aligning
     ///
-    /// EMBOSS needle uses 0.5 as gap_extend, but we only take integers.
-    #[arg(long, default_value_t = 1)]
-    gap_extend: u8,
+    /// The line is rewritten twice a second.  Until every pair has been sent to the workers,
+    /// the total counts the pairs the filters (e.g., --group-by-desc) will skip, so it can drop
+    /// before the end.  Self-hits that aren't aligned aren't counted.
+    #[arg(long, default_value_t = false, conflicts_with_all = ["profile", "reference"])]
+    pub progress: bool,
+
+    /// Time a sample of pairs, print how long the whole run and how big its output would be,
+    /// and exit
+    ///
+    /// A few hundred random pairs are aligned on one thread, and the time per pair is scaled up
+    /// to every pair, spread over --threads (assuming a core for each).  The size is of the rows of the main output, with
+    /// its columns and --direction.  Filters that skip pairs aren't counted, so the estimates are
+    /// for the whole N x N set.  Nothing is written to OUT_FILE.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["profile", "reference", "alignment_free", "ungapped", "score_only"]
+    )]
+    pub estimate: bool,
+
+    /// Read the number of worker threads to keep running from this file, while aligning
+    ///
+    /// The file is checked every second.  Writing a smaller number into it, e.g., with
+    /// `echo 2 > PATH`, pauses workers once they finish their current pair, and a larger one
+    /// (up to --threads) resumes them.  0 pauses the whole run.  Until the file exists, all the
+    /// workers run.
+    #[arg(long, value_name = "PATH", conflicts_with = "profile")]
+    pub control_file: Option<PathBuf>,
+
+    /// Gap open penalty [default: 10]
+    ///
+    /// Like --gap-extend, this can have one decimal place.
+    #[arg(long, value_parser = gap_penalty)]
+    gap_open: Option<f64>,
+
+    /// Gap extend penalty [default: 1]
+    ///
+    /// EMBOSS needle uses 0.5 as gap_extend.  The aligner only takes integer scores, so if
+    /// either penalty has a fraction, the penalties and the matrix are all multiplied by 10,
+    /// and the scores are divided by 10 again when they're written.  That's why only one
+    /// decimal place can be given.  Fractional penalties can't be used with --profile.
+    #[arg(long, value_parser = gap_penalty)]
+    gap_extend: Option<f64>,
+
+    /// Gap open penalty as a fraction of the matrix's average self-match score
+    ///
+    /// E.g., BLOSUM62's average score for a residue against itself is 5.8, so 1.7 gives a
+    /// gap open penalty of 10.  This keeps gap settings comparable across matrices (see
+    /// --matrix-blend).  The penalty is rounded to the nearest integer, and --gap-open wins if
+    /// both are given.
+    #[arg(long, value_name = "FRACTION", value_parser = positive)]
+    gap_open_frac: Option<f64>,
+
+    /// Gap extend penalty as a fraction of the matrix's average self-match score
+    ///
+    /// See --gap-open-frac.  --gap-extend wins if both are given.
+    #[arg(long, value_name = "FRACTION", value_parser = positive)]
+    gap_extend_frac: Option<f64>,
 
     /// Show the alignment operations
+    ///
+    /// When several alignments score equally well, the traceback always breaks the tie the same
+    /// way, so the same input and options give the same ops on every run and with any --threads.
     #[arg(long, default_value_t = false)]
     pub show_aln_ops: bool,
+
+    /// Add a low_confidence column, true if more than this fraction (0 to 1) of the alignment
+    /// columns have a nonstandard residue
+    ///
+    /// The matrix says little about residues other than the 20 standard amino acids, e.g., X,
+    /// so the pid and score of a pair with many of them can't be trusted.  The fraction is of
+    /// alnlen, so gap columns count as standard.
+    #[arg(
+        long,
+        value_name = "FRACTION",
+        value_parser = fraction,
+        conflicts_with_all = ["alignment_free", "ungapped", "score_only"]
+    )]
+    pub low_confidence: Option<f64>,
+
+    /// Add x_entropy and y_entropy columns, the Shannon entropy (in bits) of each sequence's
+    /// residues
+    ///
+    /// Low-complexity sequences, e.g., repeats, have low entropy and can reach a high pid
+    /// without being related, so a high pid between low-entropy sequences is often an artifact.
+    /// The 20 standard amino acids each count as a symbol and everything else as one more, so
+    /// the most is log2(21), about 4.39.
+    #[arg(long, default_value_t = false)]
+    pub entropy: bool,
+
+    /// Show the alignment operations from each sequence's side, in x_ops and y_ops columns
+    ///
+    /// Both strings have a character per alignment column: M for a match, S for a substitution,
+    /// I where the sequence has a residue and the other has a gap, - where the sequence has a
+    /// gap, and C for a clipped residue.  So the gaps of each sequence are in its own string.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["alignment_free", "ungapped", "score_only"]
+    )]
+    pub dual_ops: bool,
+
+    /// Show the alignment start and end coordinates
+    ///
+    /// Adds the xstart, xend, ystart, and yend columns.  Global alignments span both sequences
+    /// end to end, but with --repeat-aware the y columns give the region of y that was aligned,
+    /// and --score-norm adds the score of that alignment.
+    #[arg(long, default_value_t = false)]
+    pub show_coords: bool,
+
+    /// Coordinate convention for the xstart, xend, ystart, and yend columns
+    ///
+    /// With 0, coordinates are 0-based and half-open, exactly as rust-bio reports them: a
+    /// sequence of length 10 aligned end to end spans 0 to 10.
+    ///
+    /// With 1, coordinates are 1-based and closed (inclusive on both ends), like BLAST, GFF, or
+    /// SAM: the same alignment spans 1 to 10.  Only the start positions differ between the two
+    /// conventions (start + 1); the end positions are the same numbers.
+    #[arg(long, value_enum, default_value_t = CoordBase::Zero)]
+    pub coord_base: CoordBase,
+
+    /// Count clipped (X/Y) alignment operations toward alnlen
+    ///
+    /// By default, the clipped ends of local and semiglobal alignments are left out of alnlen, so
+    /// pid is matches over the aligned region only, as most tools report it.  With this flag,
+    /// each clip counts as one position of alnlen, which lowers pid for clipped alignments.
+    /// Global alignments have no clips, so their numbers are the same either way.
+    #[arg(long, default_value_t = false)]
+    pub clip_counts_as_length: bool,
+
+    /// Show percent identity relative to each sequence's length
+    ///
+    /// Adds the pid_over_x (matches / xlen) and pid_over_y (matches / ylen) columns.  Unlike pid,
+    /// these differ between the two rows written for each pair.
+    #[arg(long, default_value_t = false)]
+    pub extra_pid: bool,
+
+    /// Adds the score and score_norm (score / alnlen) columns.
+    ///
+    /// score_norm is the mean score per alignment column, gap penalties included, so it works as
+    /// a per-column quality signal and as an edge weight comparable across pairs of different
+    /// lengths.  It is not the self-normalized score (score / self-score); for that, see
+    /// --max-score.
+    #[arg(long, visible_alias = "mean-column-score", default_value_t = false)]
+    pub score_norm: bool,
+
+    /// Adds the max_score (the lower of the two self-scores) and frac_max_score (score /
+    /// max_score) columns
+    ///
+    /// A sequence's self-score is the score of aligning it to itself, which is the most any
+    /// alignment with it can score.  So frac_max_score is a similarity that doesn't depend on
+    /// length, and is at most 1 (1 for identical sequences).
+    #[arg(long, default_value_t = false)]
+    pub max_score: bool,
+
+    /// Adds the edit_distance column: the number of substitutions, insertions, and deletions
+    ///
+    /// Unlike pid, this doesn't depend on the scoring matrix (other than through the alignment).
+    #[arg(long, default_value_t = false)]
+    pub edit_distance: bool,
+
+    /// Adds the internal_alnlen and internal_pid columns, which ignore terminal gaps
+    ///
+    /// internal_alnlen is the number of alignment columns from the first to the last column where
+    /// neither sequence has a gap, and internal_pid is matches / internal_alnlen.  So overhanging
+    /// ends of a shorter sequence don't lower the pid.
+    #[arg(long, default_value_t = false)]
+    pub internal_aln_len: bool,
+
+    /// Adds the pid_trimmed column: matches / (alnlen - leading and trailing gap columns)
+    ///
+    /// This is the "identity over the aligned region, excluding terminal gaps" definition, under
+    /// an explicit name for reporting.  It's the same value as internal_pid, without the extra
+    /// length column.
+    #[arg(long, default_value_t = false)]
+    pub pid_trimmed: bool,
+
+    /// Adds the annotation column, with a character for each residue of x (or y)
+    ///
+    /// Each residue is `.` if it's aligned to the same residue, `x` if it's aligned to a
+    /// different one, or `-` if it's aligned to a gap, so the column is as long as that sequence.
+    /// Handy for plotting conservation along a reference.  On the reciprocal y vs. x row, the
+    /// annotation is of that row's x (or y).
+    #[arg(
+        long,
+        value_enum,
+        conflicts_with_all = ["alignment_free", "ungapped", "reversed"]
+    )]
+    pub annotate_on: Option<AnnotateOn>,
+
+    /// Adds the len_diff (|xlen - ylen|) and longer (x, y, or equal) columns
+    #[arg(long, default_value_t = false)]
+    pub len_diff: bool,
+
+    /// Adds the full_length column, which is true if the alignment covers both sequences
+    ///
+    /// Terminal gaps (and --repeat-aware clips) don't count as covered, so a global alignment of
+    /// a domain to a whole protein isn't full length.  Each sequence has to be covered to within
+    /// --full-length-tol of its length.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["alignment_free", "score_only"]
+    )]
+    pub full_length: bool,
+
+    /// Fraction (0 to 1) of each sequence that --full-length allows to be uncovered
+    #[arg(
+        long,
+        value_name = "FRACTION",
+        default_value_t = 0.05,
+        value_parser = fraction,
+        requires = "full_length"
+    )]
+    pub full_length_tol: f64,
+
+    /// Adds a column computed from the others, e.g., cov=matches/ylen (repeatable)
+    ///
+    /// The expression can use xlen, ylen, alnlen, matches, mismatches, gaps (the gap opens),
+    /// score, and pid, with numbers, + - * /, and parentheses.  The columns are added at the end,
+    /// before the alignment ops, in the order given.  The names can't be the same as another
+    /// column's.  Division by 0 gives NA in the tsv output and null in the jsonl output.
+    #[arg(
+        long,
+        value_name = "NAME=EXPR",
+        value_parser = computed_column,
+        conflicts_with = "alignment_free"
+    )]
+    pub compute: Vec<ComputedColumn>,
+
+    /// Adds the is_self column, which is true for self-hits (each sequence against itself)
+    ///
+    /// Safer than checking x == y, since ids can repeat.
+    #[arg(long, default_value_t = false)]
+    pub mark_self: bool,
+
+    /// Don't write self-hits (each sequence against itself) at all
+    ///
+    /// Their pid is 1, so they pass any --min-pid, but they're rarely of interest.  Self-hits
+    /// that would be aligned, e.g., with --reversed, aren't aligned either.
+    #[arg(long, default_value_t = false)]
+    pub no_self_hits: bool,
+
+    /// Cluster the sequences at this percent identity (0 to 1)
+    ///
+    /// Uses single-linkage clustering: any pair with pid at or above the threshold puts its two
+    /// sequences in the same cluster.  Clusters are written to --cluster-out.
+    #[arg(long, value_parser = fraction, requires = "cluster_out")]
+    pub cluster: Option<f64>,
+
+    /// Output file for the id-to-cluster table (see --cluster)
+    #[arg(long, value_parser = doesnt_exist, requires = "cluster")]
+    pub cluster_out: Option<PathBuf>,
+
+    /// How to number the clusters (see --cluster)
+    ///
+    /// Either way, the numbers don't depend on the order in which alignments finished.
+    #[arg(long, value_enum, default_value_t = ClusterNumbering::InputOrder, requires = "cluster")]
+    pub cluster_numbering: ClusterNumbering,
+
+    /// Only write the rows of pairs in the same cluster, for clusters of at least K sequences
+    ///
+    /// This drops singletons and small clusters from the rows.  Since the clusters aren't known
+    /// until every pair is done, all rows are held in memory (like --sort-by).  The other outputs,
+    /// e.g., --best-hits and the matrix formats, still see every pair.
+    #[arg(
+        long,
+        value_name = "K",
+        value_parser = clap::value_parser!(u64).range(2..),
+        requires = "cluster",
+        conflicts_with_all = ["stable_blocks", "checkpoint_interval", "bin_output"]
+    )]
+    pub min_cluster_size: Option<u64>,
+
+    /// Also write the best non-self hit (highest pid) of each sequence to this file
+    ///
+    /// Ties go to the hit that comes first in the input, and the ties column counts the other
+    /// hits with the same pid.  Like clustering, this sees every pair, even if it's filtered
+    /// from the main output.
+    #[arg(long, value_parser = doesnt_exist)]
+    pub best_hits: Option<PathBuf>,
+
+    /// Also write the pairs that are each other's best hit (reciprocal best hits) to this file
+    ///
+    /// Each pair is written once.  Best hits are chosen as for --best-hits.
+    #[arg(long, value_parser = doesnt_exist)]
+    pub rbh: Option<PathBuf>,
+
+    /// Also write the rows to one file per pid range, in this new directory (see --bins)
+    ///
+    /// Files are named for their range, e.g., `pid_0.5-0.7.tsv` holds rows with 0.5 <= pid < 0.7.
+    /// The last range includes 1.
+    #[arg(long, value_parser = doesnt_exist)]
+    pub bin_output: Option<PathBuf>,
+
+    /// Increasing pid breakpoints between the --bin-output ranges
+    #[arg(
+        long,
+        value_parser = fraction,
+        value_delimiter = ',',
+        default_value = "0.5,0.7,0.9",
+        requires = "bin_output"
+    )]
+    pub bins: Vec<f64>,
+
+    /// Also write the matched positions of each pair, for dotplots, to files in this new directory
+    ///
+    /// Each pair written to the rows gets a file `X_vs_Y.tsv` with an x_pos and a y_pos column
+    /// (1-based), and a line for every column of the alignment where the residues are the same.
+    /// Self-hits are skipped.  With --reversed, y_pos is a position in the reversed y.
+    #[arg(
+        long,
+        value_name = "DIR",
+        value_parser = doesnt_exist,
+        conflicts_with_all = ["alignment_free", "ungapped", "score_only"]
+    )]
+    pub dotplot: Option<PathBuf>,
+
+    /// Rename sequences using this two-column (old id, new id) table
+    ///
+    /// Ids not in the table are kept as they are (see --strict-relabel).  The new ids are used in
+    /// every output file.
+    #[arg(long, value_parser = relabel_table)]
+    pub relabel: Option<RelabelTable>,
+
+    /// Exit with an error if an id isn't in the --relabel table
+    #[arg(long, default_value_t = false, requires = "relabel")]
+    pub strict_relabel: bool,
+
+    /// Also write a rough multiple sequence alignment (aligned FASTA) to this file
+    ///
+    /// Uses the center-star method: the sequence with the highest total pid to the others is the
+    /// center, every sequence is aligned to it, and the pairwise alignments are merged.  Only
+    /// sensible for closely related sequences.
+    #[arg(long, value_parser = doesnt_exist, conflicts_with = "reversed")]
+    pub guide_msa: Option<PathBuf>,
+
+    /// Split FASTA ids on this delimiter and use one field as the id (see --id-index)
+    ///
+    /// E.g., with `--id-delimiter '|' --id-index 2`, `sp|P00452|RIR1_ECOLI` becomes `P00452`.
+    #[arg(long, requires = "id_index")]
+    pub id_delimiter: Option<String>,
+
+    /// Which field (starting from 1) to use as the id (see --id-delimiter)
+    ///
+    /// If an id has too few fields, a warning is printed and the full id is used.
+    #[arg(long, requires = "id_delimiter", value_parser = clap::value_parser!(u16).range(1..))]
+    id_index: Option<u16>,
+
+    /// Align each sequence against the reverse of the other (experimental)
+    ///
+    /// The residues of y are reversed (not reverse-complemented) before aligning, which can
+    /// reveal inverted repeats.  Self-hits are aligned too (each sequence against its own
+    /// reverse).  Adds a reversed column.  Coordinates on y refer to the reversed sequence.
+    #[arg(long, default_value_t = false)]
+    pub reversed: bool,
+
+    /// Translate nucleotide sequences and align them as proteins, in the best pair of frames
+    ///
+    /// Each sequence is translated in the three forward frames (and the three reverse ones with
+    /// --six-frame) with the standard code, every frame of x is aligned to every frame of y, and
+    /// the pair of frames with the best score is written.  Adds a frame column, e.g., +1/-2 for
+    /// x's frame and y's.  Lengths and coordinates are of the translated frames.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = [
+            "alignment_free",
+            "ungapped",
+            "score_only",
+            "reversed",
+            "class_file",
+            "intein_motifs",
+            "max_score",
+            "guide_msa",
+            "profile",
+            "conservation",
+        ]
+    )]
+    pub translate: bool,
+
+    /// Also translate the reverse complement (see --translate)
+    #[arg(long, default_value_t = false, requires = "translate")]
+    pub six_frame: bool,
+
+    /// Score with this substitution matrix instead of BLOSUM62
+    ///
+    /// Either a built-in matrix (blosum62, pam40, pam120, pam200, pam250) or a matrix file in
+    /// the NCBI format, e.g., BLOSUM45 or BLOSUM50 from NCBI for more divergent proteins.
+    #[arg(long = "matrix", value_name = "NAME_OR_FILE", value_parser = matrix, conflicts_with = "matrix_blend")]
+    pub matrix_name: Option<Matrix>,
+
+    /// Score nucleotides with this for the same base, instead of using a substitution matrix
+    ///
+    /// Give --mismatch too, e.g., `--match 2 --mismatch -3` as in BLASTN.  U is the same base as
+    /// T, and anything else, like N, is a mismatch.  The input is checked to look like
+    /// nucleotides.  The gaps are still --gap-open and --gap-extend.
+    #[arg(
+        long = "match",
+        value_name = "INT",
+        value_parser = clap::value_parser!(i32).range(1..),
+        requires = "mismatch",
+        conflicts_with_all = ["matrix_name", "matrix_blend", "gap_open_frac", "gap_extend_frac", "translate"]
+    )]
+    pub match_score: Option<i32>,
+
+    /// The score for different bases (see --match)
+    #[arg(
+        long,
+        value_name = "INT",
+        allow_hyphen_values = true,
+        requires = "match_score"
+    )]
+    pub mismatch: Option<i32>,
+
+    /// Score IUPAC ambiguity codes as --match against any base they could be
+    ///
+    /// Two codes match if they stand for any of the same bases, e.g., R (A or G) matches A, G,
+    /// S, and N, but not C, T, or Y.  These columns count as identities in the pid too, and the
+    /// ambiguity codes count as nucleotides when the input is checked (see --match).
+    /// --early-abort can't be used, since its bound on pid only counts residues that are the same.
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "match_score",
+        conflicts_with_all = ["alignment_free", "ungapped", "score_only", "early_abort"]
+    )]
+    pub iupac: bool,
+
+    /// Score with a weighted blend of substitution matrices (instead of BLOSUM62)
+    ///
+    /// Give comma-separated name:weight pairs, e.g., `blosum62:0.7,custom.mat:0.3`.  Each name is
+    /// either a built-in matrix (blosum62, pam40, pam120, pam200, pam250) or a matrix file in
+    /// the NCBI format.  The score for each residue pair is the weighted sum of the matrices'
+    /// scores, rounded to the nearest integer.  Weights don't need to sum to 1.
+    #[arg(long, value_parser = matrix_blend)]
+    pub matrix_blend: Option<Matrix>,
+
+    /// Allow a --matrix-blend file that isn't symmetric
+    ///
+    /// Residues of x (the sequence that comes first in the input) are looked up by row and residues
+    /// of y by column, so x is the query and y the subject.  The reciprocal y vs. x row of each
+    /// pair reports the same x vs. y alignment, and --verify will flag most pairs.
+    #[arg(long, default_value_t = false)]
+    pub allow_asymmetric: bool,
+
+    /// Override the scores of specific residue pairs
+    ///
+    /// Each line is two residues and a score, e.g., `W C -6`, applied on top of BLOSUM62 (or the
+    /// --matrix, --match, or --matrix-blend).  Scores are set for both orders of the pair, and pairs not in the file
+    /// keep the base matrix's score.  Lines starting with # are ignored.
+    #[arg(long, value_name = "FILE", value_parser = score_table)]
+    pub score_table: Option<ScoreTable>,
+
+    /// Score every pair with a stop (`*`), including `*` against `*`, as this
+    ///
+    /// E.g., translated CDSs with `*` for stop codons.  BLOSUM62 scores `*` as -4 against
+    /// everything else and 1 against itself, so stops line up with each other for a small
+    /// bonus.  A strongly negative score, e.g., -20, keeps alignments from running through
+    /// stops.  This overrides the --score-table and --matrix-blend scores of `*`.
+    #[arg(long, value_name = "INT", allow_hyphen_values = true)]
+    pub stop_char_score: Option<i32>,
+
+    /// Also score residues by their structural classes, given by this table of `id<TAB>classes`
+    ///
+    /// classes has one character per residue, e.g., H, E, or C from DSSP.  Aligned residues of
+    /// the same class score --class-bonus more than the matrix says, and of different classes
+    /// --class-penalty less.  Residues of records that aren't in the table score as usual.
+    /// Sequences are only treated as duplicates if their classes are the same too.  Blank lines
+    /// and lines starting with # are ignored.
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = class_table,
+        conflicts_with_all = [
+            "alignment_free",
+            "ungapped",
+            "score_only",
+            "verify",
+            "max_gap_len",
+            "max_score",
+            "guide_msa",
+            "profile",
+        ]
+    )]
+    pub class_file: Option<ClassTable>,
+
+    /// How much more residues of the same class score (see --class-file)
+    #[arg(long, value_name = "N", default_value_t = 2, requires = "class_file")]
+    pub class_bonus: u8,
+
+    /// How much less residues of different classes score (see --class-file)
+    #[arg(long, value_name = "N", default_value_t = 2, requires = "class_file")]
+    pub class_penalty: u8,
+
+    /// Reward aligning the conserved catalytic residues of inteins
+    ///
+    /// Each sequence is taken to be a whole intein.  Its N-terminal Cys, Ser, or Ala, the Thr and
+    /// His of block B (the first TxxH in the N-terminal half), and its C-terminal His and Asn
+    /// (or Gln) are marked as catalytic.  Two of the same catalytic residue score --intein-bonus
+    /// more than the matrix says, and two different ones score it less.  Other residues score
+    /// as usual.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = [
+            "class_file",
+            "alignment_free",
+            "ungapped",
+            "score_only",
+            "verify",
+            "max_gap_len",
+            "max_score",
+            "guide_msa",
+            "profile",
+        ]
+    )]
+    pub intein_motifs: bool,
+
+    /// How much more two of the same catalytic residue score (see --intein-motifs)
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 4,
+        requires = "intein_motifs"
+    )]
+    pub intein_bonus: u8,
+
+    /// Report distances (1 - pid) instead of percent identities
+    ///
+    /// Replaces the pid column with a dist column.
+    #[arg(long, default_value_t = false)]
+    pub distance: bool,
+
+    /// Correct the distances for multiple substitutions at the same site
+    ///
+    /// Anything but none implies --distance, and also applies to the phylip-dist matrix.  Very
+    /// divergent pairs are saturated, and get a distance of inf.
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = DistanceCorrection::None,
+        conflicts_with = "alignment_free"
+    )]
+    pub distance_correction: DistanceCorrection,
+
+    /// Which cells of the phylip-dist matrix to write
+    ///
+    /// lower (the usual PHYLIP layout) and upper leave out the diagonal, so the first row of
+    /// lower and the last row of upper are only a name.  full writes every cell, with 0 on the
+    /// diagonal, since that's the distance of each sequence to itself.
+    #[arg(long, value_enum, default_value_t = MatrixShape::Lower)]
+    pub matrix_shape: MatrixShape,
+
+    /// The most records to write a phylip-dist or scipy-condensed matrix for
+    ///
+    /// The matrix holds all N x N pids in memory until the end, which is 800 MB for 10,000
+    /// records.  With more records than this, align stops before aligning anything, rather than
+    /// running out of memory hours in.
+    #[arg(long, value_name = "N", default_value_t = 10_000)]
+    pub matrix_max_n: usize,
+
+    /// Also write the full pid matrix to this file, in a binary format that can be mmapped
+    ///
+    /// All numbers are little-endian.  The file starts with the 8 bytes `ALNPID01`, then N and
+    /// the byte offset of the matrix, both as u64s.  Then come the N ids, in input order, each
+    /// as a u32 byte length followed by its UTF-8 bytes.  After zero padding, at the offset
+    /// (a multiple of 8), is the N x N matrix of pids as row-major f32s.  The diagonal is 1,
+    /// and pairs that weren't aligned are NaN.
+    #[arg(long, value_name = "PATH", value_parser = doesnt_exist)]
+    pub matrix_binary: Option<PathBuf>,
+
+    /// The pid (and pid_trimmed) of an alignment with no columns, e.g., of two empty sequences
+    ///
+    /// nan is there if you really want it, but many tools reading the output choke on it.  JSON
+    /// has no NaN, so the jsonl and json-grouped formats write null instead.
+    #[arg(long, value_enum, default_value_t = EmptyPid::Zero)]
+    pub empty_pid: EmptyPid,
+
+    /// What pid divides the matches by
+    ///
+    /// Tools differ on this, so pick the one to compare with.  Self-hits and the filters and
+    /// clusters by pid all use it too, but pid_trimmed and the pid_over_x and pid_over_y of
+    /// --extra-pid don't change.
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = PidDenominator::AlnLen,
+        conflicts_with = "alignment_free"
+    )]
+    pub pid_denominator: PidDenominator,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Tsv)]
+    pub format: OutputFormat,
+
+    /// The weight of each edge of the edgelist format
+    #[arg(long, value_enum, default_value_t = EdgeWeight::Pid)]
+    pub edge_weight: EdgeWeight,
+
+    /// Only write edges with at least this weight to the edgelist format
+    ///
+    /// The other outputs still get every pair.
+    #[arg(long, value_name = "WEIGHT")]
+    pub min_edge_weight: Option<f64>,
+
+    /// Also write the results to this file, in the format given by its extension (repeatable)
+    ///
+    /// Extensions are .tsv, .jsonl, .json (json-grouped), .blast6 or .m8, .maf, .bed, .edges
+    /// (edgelist), .pair (pairwise), .phy or .phylip, and .condensed (scipy-condensed).  Every pair is aligned once and written to all of the outputs.
+    #[arg(long = "out", value_name = "PATH", value_parser = output_file)]
+    pub extra_outputs: Vec<PathBuf>,
+
+    /// Also write each alignment to this file in the stacked pairwise view, to read by eye
+    ///
+    /// The same as an --out file in the pairwise format, whatever its extension.  This is much
+    /// bigger than the table, but the filters, like --min-pid, apply to it too.
+    #[arg(
+        long,
+        value_name = "PATH",
+        value_parser = output_file,
+        conflicts_with_all = ["ungapped", "score_only", "reversed", "alignment_free"]
+    )]
+    pub alignment_file: Option<PathBuf>,
+
+    /// Only compute the score and counts of each alignment, in much less memory
+    ///
+    /// The usual aligner keeps a traceback matrix of xlen * ylen cells, which can get big for long
+    /// pairs.  This keeps only two rows, tracking the counts along the best path as it goes.
+    /// Scores are the same, but when several alignments tie, the counts (and so pid) may come
+    /// from a different one.  There are no ops, so the columns that need them aren't available.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = [
+            "show_aln_ops",
+            "annotate_on",
+            "internal_aln_len",
+            "pid_trimmed",
+            "subst_matrix",
+            "verify",
+            "alignment_free",
+            "ungapped",
+        ]
+    )]
+    pub score_only: bool,
+
+    /// Have each worker align a dummy pair before starting on the real ones
+    ///
+    /// This gets the aligner's buffers allocated up front, so the first real pairs run at the
+    /// same speed as the rest.  Mostly useful when timing runs.
+    #[arg(long, default_value_t = false)]
+    pub warm_up: bool,
+
+    /// Skip pairs estimated to take longer than this many seconds to align
+    ///
+    /// No alignment is actually timed or stopped.  Instead, since alignment time scales with the
+    /// number of DP cells (xlen * ylen), the aligner is timed on a test pair at startup to estimate how many DP cells fit
+    /// in this many seconds, and pairs with more cells than that are skipped before they're
+    /// aligned, with a warning, and don't appear in the output.  The estimate of the cells is
+    /// printed with --verbose.
+    #[arg(long)]
+    pub per_alignment_timeout: Option<f64>,
+
+    /// Limit the total DP cells (xlen * ylen) of the pairs being aligned at once
+    ///
+    /// DP memory scales with xlen * ylen, so this bounds peak memory when a few very long
+    /// sequences would otherwise be aligned on many threads at the same time.  New pairs are held
+    /// back until enough earlier ones finish.  A pair bigger than the whole budget is still
+    /// aligned, but on its own.
+    #[arg(long, value_name = "N")]
+    pub max_cells: Option<usize>,
+
+    /// Stop as soon as any pair (other than a self-hit) has a pid of at least this (0 to 1)
+    ///
+    /// For quickly checking whether there are any near-duplicates.  The pair that was found is
+    /// written and printed to stderr.  Pairs that were being aligned at the same time may be
+    /// written too, and the other outputs (e.g., clusters) only cover the pairs seen so far.
+    #[arg(long, value_name = "PID", value_parser = fraction, conflicts_with = "stable_blocks")]
+    pub stop_on_pid: Option<f64>,
+
+    /// Only write pairs with a higher pid than every pair finished before them
+    ///
+    /// For watching the most interesting hits turn up as the run goes, e.g., with a named pipe
+    /// as the output.  Self-hits are skipped.  With --threads, pairs finishing at the same time
+    /// may be written slightly out of order.  Like the rows, the other per-pair outputs (e.g.,
+    /// --dotplot) only get these pairs, but clustering and best hits still see every pair.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["sort_by", "stable_blocks", "min_cluster_size"]
+    )]
+    pub stream_best: bool,
+
+    /// Every N finished pairs, flush the output and record the last finished pair
+    ///
+    /// The pair is written to OUT_FILE.checkpoint, numbering pairs from 0 in the order they're
+    /// sent to the workers (self-hits aren't counted).  Every pair up to and including it has
+    /// had its rows written.  The file is replaced atomically, so a crash never leaves it half
+    /// written.
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["sort_by", "stable_blocks", "profile"]
+    )]
+    pub checkpoint_interval: Option<u64>,
+
+    /// Only align shard I of N of the pairs, e.g., 3/8, for splitting a run across machines
+    ///
+    /// The records are split into N contiguous ranges with about the same number of pairs, and
+    /// each shard aligns the pairs whose earlier record is in its range (including self-hits).
+    /// So the N shards cover every pair exactly once.  Only shard 1 writes the TSV header, and
+    /// the outputs can be concatenated in any order.  Run every shard with the same input.
+    #[arg(
+        long,
+        value_name = "I/N",
+        value_parser = shard,
+        conflicts_with_all = ["cluster", "best_hits", "rbh", "guide_msa", "matrix_binary", "profile"]
+    )]
+    pub shard: Option<Shard>,
+
+    /// Only align the new sequences in this FASTA file, against the input and each other
+    ///
+    /// For growing the result of an earlier run of the input, without aligning its pairs again.
+    /// The new rows are written without a header, so they can be appended to the earlier
+    /// result, e.g., `cat new.tsv >> old.tsv`.  The earlier result is never touched, so a failed
+    /// run can't spoil it.  Use the same options as the earlier run.
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = exists,
+        conflicts_with_all = [
+            "cluster",
+            "best_hits",
+            "rbh",
+            "guide_msa",
+            "profile",
+            "shard",
+            "matrix_binary",
+            "drop_substrings",
+            "skip_ungrouped",
+        ]
+    )]
+    pub add: Option<PathBuf>,
+
+    /// Align each sequence in this FASTA file against each one in the input, and nothing else
+    ///
+    /// E.g., query inteins against a reference database.  Pairs within either file, and
+    /// self-hits, aren't aligned, and each pair has one row, with the query as x and the input
+    /// sequence as y.  Only the row-based formats can be written.
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = exists,
+        conflicts_with_all = [
+            "add",
+            "profile",
+            "reference",
+            "direction",
+            "guide_msa",
+            "matrix_binary",
+            "skip_ungrouped",
+        ]
+    )]
+    pub query: Option<PathBuf>,
+
+    /// Only write pairs with pid below this value (0 to 1)
+    ///
+    /// Useful for finding divergent pairs.  Since self-hits have a pid of 1, any --max-pid
+    /// excludes them.  This only filters the rows that are written; clustering and the matrix
+    /// formats still see every pair.
+    #[arg(long, value_parser = fraction)]
+    pub max_pid: Option<f64>,
+
+    /// Only write pairs with pid of at least this value (0 to 1)
+    ///
+    /// Like --max-pid, this only filters the rows that are written, unless --early-abort skips
+    /// the pairs before they're aligned.
+    #[arg(long, value_parser = fraction)]
+    pub min_pid: Option<f64>,
+
+    /// Don't align pairs that can't reach --min-pid
+    ///
+    /// Before a pair is aligned, its pid is bounded by the residues the two sequences have in
+    /// common (each as many times as it's in both) over the length of the longer one, which no
+    /// global alignment can beat.  Pairs whose bound is below --min-pid are skipped without any
+    /// DP, so unlike the filter alone, they're also left out of clustering and the matrix
+    /// formats.  This pays off when most pairs are unrelated.
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "min_pid",
+        conflicts_with_all = ["alignment_free", "ungapped", "repeat_aware"]
+    )]
+    pub early_abort: bool,
+
+    /// Only write pairs with a raw alignment score of at least this value
+    ///
+    /// Unlike pid, the score grows with the length of the alignment, e.g., for thresholding the
+    /// edges of a homology graph.  Self-hits are compared by their self-score.  Like --max-pid,
+    /// this only filters the rows that are written.
+    #[arg(
+        long,
+        value_name = "INT",
+        allow_hyphen_values = true,
+        conflicts_with = "alignment_free"
+    )]
+    pub min_score: Option<i32>,
+
+    /// Write the pairs that were left out of the rows to this file, with the filter that left
+    /// out each one
+    ///
+    /// The TSV has x, y, and filter columns, where filter is the flag that did it, e.g.,
+    /// max-pid or exclude-pairs.  Each pair is only logged for the first filter that skips it.
+    /// Pairs of other shards (see --shard) or of old records (see --add) aren't logged.
+    #[arg(long, value_name = "FILE", value_parser = doesnt_exist)]
+    pub log_skipped: Option<PathBuf>,
+
+    /// Only look for exact and near duplicates, for deduplicating big, redundant inputs
+    ///
+    /// Records are first grouped by exact sequence, and the number of exact duplicates is
+    /// reported before any aligning (use --verbose to list them).  Then only records of the same
+    /// length are aligned, and only pairs with pid at least --near-dup-pid are written.
+    #[arg(long, default_value_t = false)]
+    pub exact_match_only: bool,
+
+    /// Lowest pid (0 to 1) of the near duplicates written by --exact-match-only
+    #[arg(
+        long,
+        value_name = "FRACTION",
+        default_value_t = 0.95,
+        value_parser = fraction,
+        requires = "exact_match_only"
+    )]
+    pub near_dup_pid: f64,
+
+    /// Write one row for each set of records with identical sequences, instead of a row each
+    ///
+    /// Records with the same sequence as an earlier one are left out, and the first record
+    /// stands for all of them, since their rows would have the same numbers.  Adds x_collapsed
+    /// and y_collapsed columns with the comma-separated ids of the records each one stands for,
+    /// or - if none.  Only for the tsv and jsonl formats.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = [
+            "exact_match_only",
+            "alignment_free",
+            "class_file",
+            "intein_motifs",
+            "cluster",
+            "best_hits",
+            "rbh",
+            "guide_msa",
+            "matrix_binary",
+        ]
+    )]
+    pub collapse_identical_output: bool,
+
+    /// Number of decimal places for pid and other non-integer columns
+    #[arg(long, default_value_t = 4)]
+    pub precision: usize,
+
+    /// Compare pairs by k-mer content instead of aligning them (see --kmer-size)
+    ///
+    /// Much faster than alignment for big inputs, but approximate.  The pid column is replaced by
+    /// kmer_cosine, the cosine similarity of the two sequences' k-mer count vectors (0 to 1).
+    /// Clustering, --max-pid, --distance, and the matrix formats use this similarity in place of
+    /// pid.  alnlen and matches aren't written, and options that need an alignment can't be used.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = [
+            "show_aln_ops",
+            "show_coords",
+            "extra_pid",
+            "score_norm",
+            "max_score",
+            "edit_distance",
+            "len_diff",
+            "best_hits",
+            "rbh",
+            "reversed",
+            "per_alignment_timeout",
+        ]
+    )]
+    pub alignment_free: bool,
+
+    /// Find the best ungapped alignment (diagonal) of each pair instead of a gapped one
+    ///
+    /// Much faster than the full alignment, and useful when you expect no indels.  One sequence
+    /// is slid along the other, and the offset whose overlap scores best is kept.  Adds an offset
+    /// column: the position on x where y starts (negative if y starts before x).  alnlen, pid,
+    /// and the coordinates refer to the overlap only.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["show_aln_ops", "reversed", "alignment_free", "per_alignment_timeout"]
+    )]
+    pub ungapped: bool,
+
+    /// How much of each sequence to align
+    ///
+    /// Local (Smith-Waterman) alignments are of the best-scoring parts of the two sequences,
+    /// e.g., a domain they share within longer, unrelated flanks.  Semiglobal alignments are of
+    /// all of x, the earlier sequence of the pair, within any part of y.  Either way, alnlen and
+    /// pid are over the aligned part only (see --clip-counts-as-length), and the coordinates show
+    /// which part that is.
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = AlignMode::Global,
+        conflicts_with_all = [
+            "repeat_aware",
+            "alignment_free",
+            "ungapped",
+            "score_only",
+            "max_gap_len",
+            "profile",
+            "reference",
+        ]
+    )]
+    pub mode: AlignMode,
+
+    /// Experimental: let y skip unaligned ends, for sequences with repeats, and add a segments
+    /// column
+    ///
+    /// x is still aligned end to end, but the start and end of y can be clipped for the cost of
+    /// opening a gap, so an extra repeat copy at either end of y doesn't have to be gapped.
+    /// rust-bio only clips at the ends, so a copy in the middle still shows up as a long gap.
+    /// segments is the number of runs of aligned residues between gaps and clips, so a pair with
+    /// repeats the other lacks has more of them.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["alignment_free", "ungapped", "score_only", "verify", "profile"]
+    )]
+    pub repeat_aware: bool,
+
+    /// Keep gaps to at most N residues, and add a gap_limit column
+    ///
+    /// rust-bio's penalties are affine, so a gap can't cost more just for being long.  Instead, a
+    /// pair with a longer gap is realigned with the gap extend penalty doubled, up to 4 times,
+    /// until its gaps fit.  The rows then have the realigned score.  gap_limit is ok if the
+    /// first alignment fit, realigned if a steeper one did, or exceeded if none did (which
+    /// keeps the steepest alignment).
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["alignment_free", "ungapped", "score_only", "repeat_aware"]
+    )]
+    pub max_gap_len: Option<usize>,
+
+    /// Add mean_gap_len and max_gap_len columns
+    ///
+    /// These are over the runs of insertions or deletions, so with the gap opens they tell many
+    /// small gaps from a few large ones.  Both are 0 for a pair without gaps.
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["alignment_free", "ungapped", "score_only"]
+    )]
+    pub gap_stats: bool,
+
+    /// Length of the k-mers for --alignment-free
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(1..))]
+    kmer_size: u8,
+
+    /// Align each sequence to this profile (a PSI-BLAST ASCII PSSM) instead of to each other
+    ///
+    /// Writes one row per sequence with its profile alignment score, and its identity to the
+    /// profile's consensus residues.  Uses the gap penalties, but not the scoring matrix.
+    #[arg(
+        long,
+        value_parser = exists,
+        conflicts_with_all = [
+            "show_aln_ops",
+            "show_coords",
+            "extra_pid",
+            "score_norm",
+            "edit_distance",
+            "len_diff",
+            "cluster",
+            "best_hits",
+            "rbh",
+            "bin_output",
+            "reversed",
+            "alignment_free",
+            "ungapped",
+            "stable_blocks",
+        ]
+    )]
+    pub profile: Option<PathBuf>,
+
+    /// Lower --profile's gap open penalties where this aligned FASTA file already has gaps
+    ///
+    /// The first sequence of the alignment is the profile's frame, so it needs a residue for each
+    /// profile position, e.g., the query the PSSM was built from.  A gap that skips a position
+    /// opens with the gap open penalty times one minus the fraction of the other sequences with a
+    /// gap in its column.  A gap inserted between two positions is scaled the same way, by the
+    /// fraction with a residue in the first sequence's gap columns there.  So the alignments
+    /// follow the gaps the alignment already has.  Gap extension isn't changed.
+    #[arg(long, value_name = "FILE", value_parser = gap_profile, requires = "profile")]
+    pub msa_gap_profile: Option<GapProfile>,
+
+    /// Align each sequence to windows of the long sequences in this FASTA file (see --ref-window)
+    ///
+    /// Instead of aligning the sequences to each other, each one is aligned end to end within
+    /// every window of every reference, and the best-scoring window is written, in one row per
+    /// sequence.  The window_start, ref_start, and ref_end columns are 0-based positions on the
+    /// reference, with ref_end exclusive.
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = exists,
+        requires = "ref_window",
+        conflicts_with_all = [
+            "profile",
+            "show_aln_ops",
+            "show_coords",
+            "extra_pid",
+            "score_norm",
+            "edit_distance",
+            "len_diff",
+            "cluster",
+            "best_hits",
+            "rbh",
+            "bin_output",
+            "reversed",
+            "alignment_free",
+            "ungapped",
+            "stable_blocks",
+        ]
+    )]
+    pub reference: Option<PathBuf>,
+
+    /// The length of the windows of the --reference
+    ///
+    /// Longer windows cost more per alignment.  A sequence longer than (W - step) may not fit in
+    /// any one window, so make W at least the longest sequence plus the step.
+    #[arg(
+        long,
+        value_name = "W",
+        requires = "reference",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub ref_window: Option<u64>,
+
+    /// How far apart the --reference windows start [default: half of --ref-window]
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "ref_window",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub ref_step: Option<u64>,
+
+    /// Also write the pid within each domain of the --reference, from this GFF file
+    ///
+    /// Each feature is a domain, on the reference named in its first column, from its 1-based
+    /// start to its end (inclusive), and named by its Name or ID attribute, or else its type.
+    /// Each domain adds a NAME_pid column: the pid of the columns of the alignment on that span
+    /// of the reference, including insertions within it.  Domains that the alignment doesn't
+    /// reach, e.g., on another reference, get the --empty-pid.  Blank lines and lines starting
+    /// with # are ignored.
+    #[arg(long, value_name = "FILE", value_parser = domain_table, requires = "reference")]
+    pub ref_domains: Option<Domains>,
+
+    /// Sort the rows by this column (highest first) before writing them
+    ///
+    /// All rows are held in memory until every pair is done.  Ties are written in input order.
+    /// Both rows of a pair stay together.
+    #[arg(long, value_enum, conflicts_with = "stable_blocks")]
+    pub sort_by: Option<SortKey>,
+
+    /// Also write the number of times each residue was aligned to each other residue
+    ///
+    /// A 20 x 20 TSV table summed over the Match and Subst columns of every alignment, with the
+    /// x residue as the row and the y residue as the column (x comes first in the input).
+    /// Nonstandard residues and self-hits aren't counted.
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = doesnt_exist,
+        conflicts_with_all = ["alignment_free", "ungapped"]
+    )]
+    pub subst_matrix: Option<PathBuf>,
+
+    /// Also write each sequence's amino acid composition to this file
+    ///
+    /// A TSV table with a row per sequence: its length, its GRAVY (mean Kyte-Doolittle
+    /// hydropathy), and the fraction of each of the 20 standard residues, with anything else
+    /// counted as other.  Covers the same sequences as the alignments.
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = doesnt_exist,
+        conflicts_with = "profile"
+    )]
+    pub composition: Option<PathBuf>,
+
+    /// Also write a histogram of the pids of the written pairs to this file
+    ///
+    /// The table has the start of each bin and its count of pairs, not counting self-hits.  The
+    /// bins split 0 to 1 evenly (see --hist-bins), and the last one includes 1.
+    #[arg(long, value_name = "FILE", value_parser = doesnt_exist, conflicts_with = "profile")]
+    pub pid_histogram: Option<PathBuf>,
+
+    /// Number of --pid-histogram bins
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 20,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "pid_histogram"
+    )]
+    pub hist_bins: u64,
+
+    /// Write how conserved each position of the --conservation-ref sequence is to this file
+    ///
+    /// A TSV table with a row per reference position: how many of the reference's alignments put
+    /// a residue across it, how many of those match, and conservation, the fraction of the
+    /// alignments with a match there (so gaps count against it).
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = doesnt_exist,
+        requires = "conservation_ref",
+        conflicts_with_all = ["alignment_free", "ungapped", "score_only", "reversed", "profile"]
+    )]
+    pub conservation: Option<PathBuf>,
+
+    /// The id of the reference sequence for --conservation
+    #[arg(long, value_name = "ID", requires = "conservation")]
+    pub conservation_ref: Option<String>,
+
+    /// Only align records within the same group, taken from their descriptions by this regex
+    ///
+    /// The group is the first capture group of the match, or the whole match if the regex has no
+    /// groups, e.g., `taxon=(\w+)`.  Records without a description or a match are aligned among
+    /// themselves as one more group (see --skip-ungrouped).  Pairs from different groups (or with
+    /// --between, the same group) are left out of every output, so they are NaN in the
+    /// phylip-dist matrix.
+    #[arg(long, value_name = "PATTERN", value_parser = regex, conflicts_with = "groups")]
+    pub group_by_desc: Option<Regex>,
+
+    /// Only align records with the same base id, taken from their ids by this regex
+    ///
+    /// Like --group-by-desc, but the group is picked out of the id, e.g., `^(.+)_v\d+$` to
+    /// only align the versions of each gene, like gene_A_v1 and gene_A_v2, to each other.
+    /// Records whose id doesn't match are aligned among themselves (see --skip-ungrouped).
+    #[arg(long, value_name = "REGEX", value_parser = regex)]
+    pub pair_by_id_pattern: Option<Regex>,
+
+    /// Only align records within the same group, given by this table of `id<TAB>group` lines
+    ///
+    /// Like --group-by-desc, but the groups come from a file.  Records whose id isn't in the
+    /// table are aligned among themselves as one more group (see --skip-ungrouped).  Blank lines
+    /// and lines starting with # are ignored.
+    #[arg(long, value_name = "FILE", value_parser = group_table)]
+    pub groups: Option<GroupTable>,
+
+    /// Add a pair_weight column, the product of the two records' weights from this table of
+    /// `id<TAB>weight` lines
+    ///
+    /// E.g., weights from sequence quality, so the edges of low-quality sequences can be
+    /// down-weighted downstream.  Records that aren't in the table have a weight of 1.  Blank
+    /// lines and lines starting with # are ignored.
+    #[arg(long, value_name = "FILE", value_parser = weight_table)]
+    pub weights: Option<WeightTable>,
+
+    /// With --group-by-desc, --pair-by-id-pattern, or --groups, drop records without a group instead of grouping them
+    /// together
+    #[arg(long, default_value_t = false, requires = "grouping")]
+    pub skip_ungrouped: bool,
+
+    /// With --group-by-desc, --pair-by-id-pattern, or --groups, align only pairs from different groups instead
+    ///
+    /// Self-hits are still written.
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "grouping",
+        conflicts_with = "within"
+    )]
+    pub between: bool,
+
+    /// With --group-by-desc, --pair-by-id-pattern, or --groups, align only pairs from the same group (the default)
+    #[arg(long, default_value_t = false, requires = "grouping")]
+    pub within: bool,
+
+    /// Don't align the pairs in this file, one pair of ids per line
+    ///
+    /// The ids are separated by whitespace, and either order excludes the pair.  Excluded pairs
+    /// are left out of every output, like pairs from different groups.  Ids that aren't in the
+    /// input are skipped with a warning.  Self-hits are still written.  Blank lines and lines
+    /// starting with # are ignored.
+    #[arg(long, value_name = "FILE", value_parser = pair_list)]
+    pub exclude_pairs: Option<PairList>,
+
+    /// Also align this fraction (0 to 1) of pairs as y vs. x, and warn if the results differ
+    ///
+    /// Global alignment should give the same score either way, so this is a check for scoring or
+    /// clipping bugs.  Only matches and alignment length are compared, and these can differ a bit
+    /// when there are several equally good alignments, so a few warnings aren't necessarily a
+    /// bug.  The sample is the same from run to run.  The alignment is checked as aligned, before
+    /// --max-gap-len realigns it or --mask-low-complexity unmatches its X's.
+    #[arg(long, value_name = "FRACTION", value_parser = fraction, conflicts_with = "reversed")]
+    pub verify: Option<f64>,
+
+    /// Which rows to write for each pair
+    #[arg(long, value_enum, default_value_t = Direction::Both)]
+    pub direction: Direction,
+
+    /// Write one row per pair, with the columns of both directions
+    ///
+    /// The y vs. x row of a pair is its x vs. y row with the sides swapped, and every column that
+    /// depends on the direction already comes in an x and a y version: xlen and ylen, the
+    /// coordinates, pid_over_x and pid_over_y of --extra-pid, and the x_ and y_ columns.  So
+    /// this writes only the x vs. y row, as --direction input-order does.
+    #[arg(long, default_value_t = false, conflicts_with = "direction")]
+    pub single_row: bool,
+
+    /// Write each record's 0-based index instead of its id, with the ids in OUT.index
+    ///
+    /// This makes the output much smaller when the ids are long accessions.  Every output gets
+    /// the indices, including the matrices, clusters, and logs.  OUT.index has an
+    /// `index<TAB>id` line per record, in input order, to join them back.  The indices are of the
+    /// records that are left after the input filters, --add's records come after the others,
+    /// and --query's come before them.
+    #[arg(long, default_value_t = false, conflicts_with_all = ["profile", "reference"])]
+    pub use_indices: bool,
+
+    /// Hold at most this many pairs' rows in memory while writing them in input order
+    ///
+    /// Rows are always written in the order of the pairs' x then y, with each record's self-hit
+    /// before its other pairs, so they're in the same order with any --threads, and two runs can
+    /// be diffed.  The pairs are sent a block at a time to the workers' shared queue, and each
+    /// block's rows are written once the whole block is done.  Smaller blocks use less memory,
+    /// but leave workers idle more often at the end of each block.  [default: 1000]
+    #[arg(long, value_name = "PAIRS", value_parser = clap::value_parser!(u32).range(1..))]
+    stable_blocks: Option<u32>,
 }
 
-impl Cli {
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One tab-separated line per alignment
+    Tsv,
+    /// PHYLIP distance matrix (1 - pid), for tree building, lower-triangular unless set by
+    /// --matrix-shape
+    PhylipDist,
+    /// A condensed distance matrix (1 - pid), one distance per line, for scipy.cluster.hierarchy
+    ///
+    /// The distances are in the order of scipy's pdist: (0, 1), (0, 2), ..., (0, n-1), (1, 2),
+    /// and so on, so `numpy.loadtxt` gives the array linkage expects.  The ids are written in
+    /// input order to a sidecar file, named like the output with .ids on the end.  Pairs that
+    /// weren't aligned are NaN.
+    ScipyCondensed,
+    /// BLAST tabular (-outfmt 6) columns, without a header
+    ///
+    /// pident is a percentage and coordinates are 1-based (regardless of --coord-base), as in
+    /// BLAST.  There are no e-values, so evalue is always 0, and bitscore is the raw alignment
+    /// score.
+    Blast6,
+    /// One JSON object per line, with the same fields as the tsv columns
+    Jsonl,
+    /// A MAF alignment block per pair, with the gapped sequences
+    ///
+    /// Each block has the score, then an s line for x and one for y, with 0-based starts on the +
+    /// strand.  Only the x vs. y block is written, whatever the --direction.
+    Maf,
+    /// A two-sequence Stockholm alignment per pair, e.g., for hmmbuild
+    ///
+    /// Each alignment is named x_vs_y (its #=GF ID line, which hmmbuild needs to tell the
+    /// alignments of a file apart) and has the score in a #=GF CC line.  Only x vs. y is
+    /// written, whatever the --direction, and self-hits are skipped.
+    Stockholm,
+    /// A JSON array with an object per query, holding its hits
+    ///
+    /// Each object has the query id and an array of hits, which are the objects jsonl would write
+    /// for its rows, by --direction.  Everything is held in memory and written at the end.
+    JsonGrouped,
+    /// A `source target weight` line per pair, space-separated and without a header, for graph
+    /// tools
+    ///
+    /// The weight is picked by --edge-weight, and --min-edge-weight drops light edges.  Edges
+    /// are undirected, so only x vs. y is written, whatever the --direction, and self-hits are
+    /// skipped.
+    Edgelist,
+    /// A BED interval per pair, of the part of y (the reference) that x (the query) aligned to
+    ///
+    /// chrom is y's id, name is x's id, and score is the pid times 1000.  Coordinates are 0-based
+    /// and half-open (regardless of --coord-base), and terminal gaps and clips are left out.
+    /// With --direction both, each record of a pair is also the reference for the other.
+    Bed,
+    /// The stacked view of each pair, to read by eye
+    ///
+    /// Each pair has a line with the ids, pid, and score, then blocks of 60 columns: x's row, a
+    /// bar row with `|` for matches and `.` for mismatches, then y's row, with `-` for gaps and
+    /// 1-based positions on either end.  Clipped residues are left out.  Only x vs. y is written,
+    /// whatever the --direction, and self-hits are skipped.
+    Pairwise,
+}
+
+impl OutputFormat {
+    /// True for formats that write lines as alignments finish, rather than a matrix at the end.
+    pub fn is_row_based(self) -> bool {
+        match self {
+            OutputFormat::Tsv
+            | OutputFormat::Blast6
+            | OutputFormat::Jsonl
+            | OutputFormat::Maf
+            | OutputFormat::Stockholm
+            | OutputFormat::Bed
+            | OutputFormat::Edgelist
+            | OutputFormat::Pairwise => true,
+            OutputFormat::PhylipDist | OutputFormat::ScipyCondensed | OutputFormat::JsonGrouped => {
+                false
+            }
+        }
+    }
+}
+
+/// Columns that the output can be sorted by.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    Pid,
+    Matches,
+    Alnlen,
+    Score,
+}
+
+impl SortKey {
+    /// Compare so that higher values come first.
+    pub fn compare(self, a: &PairResult, b: &PairResult) -> Ordering {
+        match self {
+            SortKey::Pid => b.percent_identity.total_cmp(&a.percent_identity),
+            SortKey::Matches => b.num_matches.cmp(&a.num_matches),
+            SortKey::Alnlen => b.aln_len.cmp(&a.aln_len),
+            SortKey::Score => b.score.cmp(&a.score),
+        }
+    }
+}
+
+/// How much of each sequence is aligned (see --mode).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum AlignMode {
+    /// Both sequences end to end (Needleman-Wunsch)
+    Global,
+    /// The best-scoring parts of the two sequences (Smith-Waterman)
+    Local,
+    /// All of x within any part of y
+    Semiglobal,
+}
+
+/// Which of the two rows (x vs. y, and y vs. x) to write for each pair.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Direction {
+    /// Write both rows
+    Both,
+    /// Only write the row where the x id sorts before the y id
+    Lexical,
+    /// Only write the row where x comes before y in the input
+    InputOrder,
+}
+
+/// The order in which clusters are numbered, from 1.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ClusterNumbering {
+    /// By the first member of each cluster in the input
+    InputOrder,
+    /// By the smallest member id of each cluster, so reordering the input doesn't change the
+    /// numbers
+    SmallestId,
+}
+
+/// Handling of the nonstandard amino acids U and O.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum NonstandardAa {
+    /// Replace them with their closest standard residue (U to C, O to K) when reading
+    Substitute,
+    /// Score them as 0 against everything
+    Neutral,
+    /// Exit with an error if any sequence contains them
+    Error,
+}
+
+/// Which sequence of a pair to write the annotation for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum AnnotateOn {
+    X,
+    Y,
+}
+
+/// Coordinate convention for reported alignment positions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CoordBase {
+    /// 0-based, half-open
+    #[value(name = "0")]
+    Zero,
+    /// 1-based, closed
+    #[value(name = "1")]
+    One,
+}
+
+impl CoordBase {
+    /// Convert a 0-based, half-open `(start, end)` interval to this convention.
+    pub fn convert(&self, start: usize, end: usize) -> (usize, usize) {
+        match self {
+            CoordBase::Zero => (start, end),
+            CoordBase::One => (start + 1, end),
+        }
+    }
+}
+
+/// The pid to report for an alignment with no columns, e.g., of two empty sequences.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EmptyPid {
+    #[value(name = "0")]
+    Zero,
+    #[value(name = "1")]
+    One,
+    Nan,
+}
+
+impl EmptyPid {
+    pub fn pid(self) -> f64 {
+        match self {
+            EmptyPid::Zero => 0.0,
+            EmptyPid::One => 1.0,
+            EmptyPid::Nan => f64::NAN,
+        }
+    }
+}
+
+/// What pid divides the matches by.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PidDenominator {
+    /// The alignment length, counting gaps
+    AlnLen,
+    /// The aligned columns without a gap, i.e., the matches and mismatches
+    Ungapped,
+    /// The length of the shorter sequence
+    ShorterSeq,
+}
+
+impl PidDenominator {
+    /// The denominator of an alignment `aln_len` long with `num_aligned` gapless columns, of
+    /// sequences `x_len` and `y_len` long.
+    pub fn denominator(self, aln_len: i32, num_aligned: i32, x_len: usize, y_len: usize) -> i32 {
+        match self {
+            PidDenominator::AlnLen => aln_len,
+            PidDenominator::Ungapped => num_aligned,
+            // Safe because inteins are short.
+            PidDenominator::ShorterSeq => i32::try_from(x_len.min(y_len)).unwrap(),
+        }
+    }
+}
+
+/// The weight of each edge of the edgelist format.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EdgeWeight {
+    Pid,
+    /// The raw alignment score
+    Score,
+}
+
+/// Which cells of a distance matrix get written.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum MatrixShape {
+    /// Below the diagonal
+    Lower,
+    /// Above the diagonal
+    Upper,
+    /// Every cell, including the diagonal
+    Full,
+}
+
+impl MatrixShape {
+    /// The columns of row `i` of an `n` x `n` matrix that get written.
+    pub fn columns(self, i: usize, n: usize) -> Range<usize> {
+        match self {
+            MatrixShape::Lower => 0..i,
+            MatrixShape::Upper => i + 1..n,
+            MatrixShape::Full => 0..n,
+        }
+    }
+}
+
+/// How to turn a pid into an evolutionary distance.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum DistanceCorrection {
+    /// The uncorrected p-distance, 1 - pid
+    None,
+    /// Poisson correction, -ln(1 - p)
+    Poisson,
+    /// Kimura's protein correction, -ln(1 - p - 0.2 p^2)
+    Kimura,
+}
+
+impl DistanceCorrection {
+    pub fn distance(self, percent_identity: f64) -> f64 {
+        let p = 1.0 - percent_identity;
+        // Written as 0 - ln(x) so identical pairs get 0 rather than -0.
+        let corrected = |x: f64| if x > 0.0 { 0.0 - x.ln() } else { f64::INFINITY };
+
+        match self {
+            DistanceCorrection::None => p,
+            DistanceCorrection::Poisson => corrected(1.0 - p),
+            DistanceCorrection::Kimura => corrected(1.0 - p - 0.2 * p * p),
+        }
+    }
+}
+
+impl AlignArgs {
     pub fn threads(&self) -> usize {
         usize::from(self.threads)
     }
+    /// The gap open score, in scaled units (see `score_scale`).
     pub fn gap_open(&self) -> i32 {
-        -i32::from(self.gap_open)
+        -self.scaled(self.gap_penalty(self.gap_open, self.gap_open_frac, 10.0))
     }
+    /// The gap extend score, in scaled units (see `score_scale`).
     pub fn gap_extend(&self) -> i32 {
-        -i32::from(self.gap_extend)
+        -self.scaled(self.gap_penalty(self.gap_extend, self.gap_extend_frac, 1.0))
+    }
+    /// What all the scores are multiplied by, so fractional gap penalties can be integers.
+    pub fn score_scale(&self) -> i32 {
+        if [self.gap_open, self.gap_extend]
+            .into_iter()
+            .flatten()
+            .any(|penalty| penalty.fract() != 0.0)
+        {
+            FRACTIONAL_SCORE_SCALE
+        } else {
+            1
+        }
+    }
+    fn scaled(&self, penalty: f64) -> i32 {
+        (penalty * f64::from(self.score_scale())).round() as i32
+    }
+    /// A gap penalty, from the absolute value if given, otherwise from the fraction of the
+    /// matrix's average self-match score, otherwise the default.
+    fn gap_penalty(&self, absolute: Option<f64>, fraction: Option<f64>, default: f64) -> f64 {
+        match (absolute, fraction) {
+            (Some(penalty), _) => penalty,
+            (None, Some(fraction)) => (fraction * self.unscaled_matrix().mean_self_score()).round(),
+            (None, None) => default,
+        }
+    }
+    /// The substitution matrix to score alignments with, in scaled units (see `score_scale`).
+    pub fn matrix(&self) -> Matrix {
+        let mut matrix = self.unscaled_matrix();
+        matrix.scale(self.score_scale());
+        matrix
+    }
+    fn unscaled_matrix(&self) -> Matrix {
+        let mut matrix = match (&self.matrix_name, &self.matrix_blend, self.match_score) {
+            (Some(matrix), _, _) | (None, Some(matrix), _) => matrix.clone(),
+            // Safe because --match requires --mismatch.
+            (None, None, Some(score)) if self.iupac => Matrix::iupac(score, self.mismatch.unwrap()),
+            (None, None, Some(score)) => Matrix::match_mismatch(score, self.mismatch.unwrap()),
+            (None, None, None) => Matrix::blosum62(),
+        };
+        if let Some(table) = &self.score_table {
+            matrix.apply(table);
+        }
+        if let Some(score) = self.stop_char_score {
+            matrix.fill(b"*", score);
+        }
+        matrix
+    }
+    /// Every file given to be written, e.g., for checking their directories up front.
+    pub fn output_paths(&self) -> impl Iterator<Item = &PathBuf> {
+        let optional = [
+            &self.alignment_file,
+            &self.cluster_out,
+            &self.best_hits,
+            &self.rbh,
+            &self.bin_output,
+            &self.dotplot,
+            &self.guide_msa,
+            &self.matrix_binary,
+            &self.log_skipped,
+            &self.subst_matrix,
+            &self.composition,
+            &self.pid_histogram,
+            &self.conservation,
+        ];
+
+        std::iter::once(&self.out_file)
+            .chain(&self.extra_outputs)
+            .chain(optional.into_iter().flatten())
+    }
+    /// Where --use-indices writes the ids, i.e., the output path with .index on the end.
+    pub fn index_file(&self) -> Option<PathBuf> {
+        self.use_indices.then(|| {
+            let mut path = self.out_file.clone().into_os_string();
+            path.push(".index");
+            path.into()
+        })
+    }
+
+    /// Which rows to write for each pair, with --single-row and --query as input order.
+    pub fn direction(&self) -> Direction {
+        if self.single_row || self.query.is_some() {
+            Direction::InputOrder
+        } else {
+            self.direction
+        }
+    }
+    /// The number of pairs to write at a time in input order, or `None` if the rows are instead
+    /// written as they finish (or all at the end), which these options need.
+    pub fn stable_block_size(&self) -> Option<usize> {
+        let unordered = self.sort_by.is_some()
+            || self.min_cluster_size.is_some()
+            || self.stop_on_pid.is_some()
+            || self.stream_best
+            || self.checkpoint_interval.is_some();
+
+        match self.stable_blocks {
+            Some(size) => Some(size as usize),
+            None => (!unordered).then_some(DEFAULT_STABLE_BLOCK_SIZE),
+        }
+    }
+    pub fn kmer_size(&self) -> usize {
+        usize::from(self.kmer_size)
+    }
+    /// The 0-based field index to use as the id, if ids should be split.
+    pub fn id_field(&self) -> Option<(&str, usize)> {
+        match (&self.id_delimiter, self.id_index) {
+            (Some(delimiter), Some(index)) => Some((delimiter, usize::from(index) - 1)),
+            _ => None,
+        }
     }
 }
 
@@ -76,3 +2021,60 @@ fn verify_cli() {
     use clap::CommandFactory;
     Cli::command().debug_assert()
 }
+
+#[test]
+fn validators_return_matchable_errors() {
+    assert!(matches!(
+        exists("no/such/file"),
+        Err(Error::FileNotFound(_))
+    ));
+    assert!(matches!(
+        doesnt_exist("Cargo.toml"),
+        Err(Error::OutputExists(_))
+    ));
+    assert!(matches!(fraction("1.5"), Err(Error::Parse(_))));
+    assert!(matches!(gap_penalty("0.25"), Err(Error::Parse(_))));
+    assert!(matches!(gap_penalty("-1"), Err(Error::Parse(_))));
+    assert!(matches!(
+        matrix_blend("blosum62:1,no/such/matrix:1"),
+        Err(Error::Io { .. })
+    ));
+}
+
+#[test]
+fn fractional_gap_penalties_scale_the_scores() {
+    let args = |gaps: &[&str]| {
+        let argv = ["align", "Cargo.toml", "no_such_out.tsv"];
+        match Cli::parse_from(argv.iter().chain(gaps)).into_command() {
+            Command::Align(args) => args,
+            _ => unreachable!(),
+        }
+    };
+
+    let whole = args(&["--gap-extend", "1"]);
+    assert_eq!(whole.score_scale(), 1);
+    assert_eq!((whole.gap_open(), whole.gap_extend()), (-10, -1));
+
+    let emboss = args(&["--gap-extend", "0.5"]);
+    assert_eq!(emboss.score_scale(), FRACTIONAL_SCORE_SCALE);
+    assert_eq!((emboss.gap_open(), emboss.gap_extend()), (-100, -5));
+    assert_eq!(emboss.matrix().score(b'W', b'W'), 110);
+}
+
+#[test]
+fn distances_are_corrected() {
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+    assert!(close(DistanceCorrection::None.distance(0.75), 0.25));
+    assert!(close(
+        DistanceCorrection::Poisson.distance(0.75),
+        -(0.75f64).ln()
+    ));
+    assert!(close(
+        DistanceCorrection::Kimura.distance(0.75),
+        -(1.0 - 0.25 - 0.2 * 0.0625f64).ln()
+    ));
+    assert_eq!(DistanceCorrection::None.distance(1.0), 0.0);
+    assert!(DistanceCorrection::Poisson.distance(0.0).is_infinite());
+    assert!(DistanceCorrection::Kimura.distance(0.1).is_infinite());
+}
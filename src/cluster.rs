@@ -0,0 +1,178 @@
+//! Single-linkage clustering of the input records by percent identity.
+
+use crate::cli::ClusterNumbering;
+use bio::io::fasta::Record;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Disjoint sets over record indices, with union by rank and path halving.
+struct UnionFind {
+    parents: Vec<usize>,
+    ranks: Vec<u8>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> Self {
+        Self {
+            parents: (0..n).collect(),
+            ranks: vec![0; n],
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+
+        i
+    }
+
+    fn union(&mut self, i: usize, j: usize) {
+        let i = self.find(i);
+        let j = self.find(j);
+
+        if i == j {
+            return;
+        }
+
+        match self.ranks[i].cmp(&self.ranks[j]) {
+            std::cmp::Ordering::Less => self.parents[i] = j,
+            std::cmp::Ordering::Greater => self.parents[j] = i,
+            std::cmp::Ordering::Equal => {
+                self.parents[j] = i;
+                self.ranks[i] += 1;
+            }
+        }
+    }
+}
+
+/// Clusters built up as alignments finish.
+///
+/// Any pair at or above the threshold joins the clusters of its two records (single-linkage).
+/// Worker threads share one instance and call `add` for each pair they align.
+pub struct Clusters {
+    threshold: f64,
+    numbering: ClusterNumbering,
+    sets: Mutex<UnionFind>,
+}
+
+impl Clusters {
+    pub fn new(num_records: usize, threshold: f64, numbering: ClusterNumbering) -> Self {
+        Self {
+            threshold,
+            numbering,
+            sets: Mutex::new(UnionFind::new(num_records)),
+        }
+    }
+
+    /// Link the records at `x_index` and `y_index` if their percent identity is high enough.
+    pub fn add(&self, x_index: usize, y_index: usize, percent_identity: f64) {
+        if percent_identity >= self.threshold {
+            self.sets.lock().unwrap().union(x_index, y_index);
+        }
+    }
+
+    /// The cluster id of each record, in input order.
+    ///
+    /// Clusters are numbered from 1 in the order of their first member in the input, or of
+    /// their smallest member id (ties going to the first), so the numbering doesn't depend on
+    /// the order in which alignments finished.
+    fn assignments(&self, records: &[Record]) -> Vec<usize> {
+        let mut sets = self.sets.lock().unwrap();
+        let n = sets.parents.len();
+        let roots = (0..n).map(|i| sets.find(i)).collect::<Vec<_>>();
+
+        // The member of each cluster (by root) that it's numbered by.
+        let mut firsts: Vec<Option<usize>> = vec![None; n];
+        for (i, &root) in roots.iter().enumerate() {
+            let first = &mut firsts[root];
+            let smaller = |j: usize| {
+                self.numbering == ClusterNumbering::SmallestId && records[i].id() < records[j].id()
+            };
+            if first.is_none_or(smaller) {
+                *first = Some(i);
+            }
+        }
+
+        let mut order = firsts
+            .iter()
+            .enumerate()
+            .filter_map(|(root, first)| first.map(|first| (first, root)))
+            .collect::<Vec<_>>();
+        if self.numbering == ClusterNumbering::SmallestId {
+            order.sort_by_key(|&(first, _)| (records[first].id(), first));
+        }
+
+        let mut cluster_ids = vec![0; n];
+        for (id, (_, root)) in order.into_iter().enumerate() {
+            cluster_ids[root] = id + 1;
+        }
+        roots.into_iter().map(|root| cluster_ids[root]).collect()
+    }
+
+    /// The cluster id of each record whose cluster has at least `min_size` members.
+    pub fn large(&self, min_size: usize, records: &[Record]) -> Vec<Option<usize>> {
+        let assignments = self.assignments(records);
+        let mut sizes = vec![0; assignments.len() + 1];
+        assignments.iter().for_each(|&cluster| sizes[cluster] += 1);
+
+        assignments
+            .into_iter()
+            .map(|cluster| (sizes[cluster] >= min_size).then_some(cluster))
+            .collect()
+    }
+
+    /// Write the `id<TAB>cluster` table.
+    pub fn write(&self, path: &Path, records: &[Record]) {
+        let mut out = BufWriter::new(File::create(path).unwrap());
+
+        writeln!(out, "id\tcluster").unwrap();
+        records
+            .iter()
+            .zip(self.assignments(records))
+            .for_each(|(record, cluster)| {
+                writeln!(out, "{}\t{cluster}", record.id()).unwrap();
+            });
+    }
+}
+
+#[cfg(test)]
+fn records(ids: &[&str]) -> Vec<Record> {
+    ids.iter()
+        .map(|id| Record::with_attrs(id, None, b"ACDE"))
+        .collect()
+}
+
+#[test]
+fn clusters_are_single_linkage() {
+    let records = records(&["a", "b", "c", "d", "e"]);
+    let clusters = Clusters::new(5, 0.5, ClusterNumbering::InputOrder);
+
+    // 0 - 1 - 2 chain together even though 0 and 2 are not linked directly.
+    clusters.add(0, 1, 0.9);
+    clusters.add(1, 2, 0.5);
+    clusters.add(0, 2, 0.1);
+    // Below the threshold, so 3 stays on its own.
+    clusters.add(3, 4, 0.49);
+    clusters.add(2, 4, 0.7);
+
+    assert_eq!(clusters.assignments(&records), vec![1, 1, 1, 2, 1]);
+    assert_eq!(
+        clusters.large(2, &records),
+        vec![Some(1), Some(1), Some(1), None, Some(1)]
+    );
+}
+
+#[test]
+fn clusters_can_be_numbered_by_smallest_id() {
+    let records = records(&["m", "z", "b", "q", "a"]);
+    let clusters = Clusters::new(5, 0.5, ClusterNumbering::SmallestId);
+    clusters.add(0, 1, 0.9);
+    clusters.add(2, 3, 0.9);
+
+    // {a} sorts first, then {b, q}, then {m, z}.
+    assert_eq!(clusters.assignments(&records), vec![3, 3, 2, 2, 1]);
+}
@@ -0,0 +1,117 @@
+//! Per-record amino acid composition, for `--composition`.
+
+use bio::io::fasta::Record;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// The residues with their own column, in the usual BLOSUM order.
+const RESIDUES: &[u8; 20] = b"ARNDCQEGHILKMFPSTWYV";
+
+/// Kyte-Doolittle hydropathy of each of the `RESIDUES`.
+const HYDROPATHY: [f64; 20] = [
+    1.8, -4.5, -3.5, -3.5, 2.5, -3.5, -3.5, -0.4, -3.2, 4.5, 3.8, -3.9, 1.9, 2.8, -1.6, -0.8, -0.7,
+    -0.9, -1.3, 4.2,
+];
+
+/// Counts of each residue of a sequence, with everything nonstandard lumped together.
+struct Composition {
+    counts: [usize; 20],
+    other: usize,
+}
+
+impl Composition {
+    fn new(seq: &[u8]) -> Self {
+        let mut counts = [0; 20];
+        let mut other = 0;
+
+        for c in seq.to_ascii_uppercase() {
+            match RESIDUES.iter().position(|&r| r == c) {
+                Some(i) => counts[i] += 1,
+                None => other += 1,
+            }
+        }
+
+        Self { counts, other }
+    }
+
+    /// The mean Kyte-Doolittle hydropathy (GRAVY) of the standard residues.
+    fn gravy(&self) -> f64 {
+        let total = self.counts.iter().sum::<usize>();
+        let sum = self
+            .counts
+            .iter()
+            .zip(HYDROPATHY)
+            .map(|(&n, h)| n as f64 * h)
+            .sum::<f64>();
+
+        sum / total as f64
+    }
+
+    /// The Shannon entropy in bits, with the nonstandard residues as one more symbol.
+    fn entropy(&self) -> f64 {
+        let total = self.counts.iter().sum::<usize>() + self.other;
+
+        self.counts
+            .iter()
+            .chain([&self.other])
+            .filter(|&&n| n > 0)
+            .map(|&n| n as f64 / total as f64)
+            .fold(0.0, |entropy, p| entropy - p * p.log2())
+    }
+}
+
+/// The Shannon entropy of the residues of `seq`, in bits, for the entropy columns.
+pub fn entropy(seq: &[u8]) -> f64 {
+    Composition::new(seq).entropy()
+}
+
+/// Write a TSV table with each record's length, GRAVY, and the fraction of each residue.
+pub fn write(path: &Path, records: &[Record], precision: usize) {
+    let mut out = BufWriter::new(File::create(path).unwrap());
+
+    let header = RESIDUES
+        .iter()
+        .map(|&c| format!("\t{}", c as char))
+        .collect::<String>();
+    writeln!(out, "id\tlen\tgravy{header}\tother").unwrap();
+
+    for record in records {
+        let len = record.seq().len();
+        let composition = Composition::new(record.seq());
+
+        let fractions = composition
+            .counts
+            .iter()
+            .chain([&composition.other])
+            .map(|&n| format!("\t{:.precision$}", n as f64 / len as f64))
+            .collect::<String>();
+
+        writeln!(
+            out,
+            "{}\t{len}\t{:.precision$}{fractions}",
+            record.id(),
+            composition.gravy()
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn residues_are_counted() {
+    let composition = Composition::new(b"AAIkX*");
+
+    assert_eq!(composition.counts[0], 2);
+    assert_eq!(composition.counts[9], 1);
+    assert_eq!(composition.counts[11], 1);
+    assert_eq!(composition.other, 2);
+    assert!((composition.gravy() - (1.8 + 1.8 + 4.5 - 3.9) / 4.0).abs() < 1e-9);
+}
+
+#[test]
+fn entropy_is_in_bits() {
+    assert_eq!(entropy(b"AAAA"), 0.0);
+    assert_eq!(entropy(b"AcaC"), 1.0);
+    assert_eq!(entropy(b"ACXB"), 1.5);
+    assert_eq!(entropy(b""), 0.0);
+}
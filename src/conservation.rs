@@ -0,0 +1,135 @@
+//! How often each position of a reference sequence is conserved in its alignments, for
+//! `--conservation`.
+
+use bio::alignment::{Alignment, AlignmentOperation};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+struct Counts {
+    num_alignments: u64,
+    /// Per reference position, how many alignments put a residue (rather than a gap) across it.
+    aligned: Vec<u64>,
+    /// Per reference position, how many of those residues were the same.
+    matches: Vec<u64>,
+}
+
+/// Per-position counts over every alignment of the reference to another record.
+pub struct Conservation {
+    /// Index of the reference in the records.
+    reference: usize,
+    residues: Vec<u8>,
+    counts: Mutex<Counts>,
+}
+
+impl Conservation {
+    pub fn new(reference: usize, residues: &[u8]) -> Self {
+        Self {
+            reference,
+            residues: residues.to_vec(),
+            counts: Mutex::new(Counts {
+                num_alignments: 0,
+                aligned: vec![0; residues.len()],
+                matches: vec![0; residues.len()],
+            }),
+        }
+    }
+
+    /// Does the pair of records `x_index` and `y_index` count?  Self-hits don't.
+    pub fn wants(&self, x_index: usize, y_index: usize) -> bool {
+        x_index != y_index && (x_index == self.reference || y_index == self.reference)
+    }
+
+    /// Project an alignment with record `x_index` as x onto the reference, and count it.
+    pub fn add(&self, x_index: usize, alignment: &Alignment) {
+        let reference_is_x = x_index == self.reference;
+        let (mut i, mut j) = (0, 0);
+        // The reference position of each aligned column, and whether it's a match.
+        let mut columns = Vec::with_capacity(self.residues.len());
+
+        for op in &alignment.operations {
+            let reference_pos = if reference_is_x { i } else { j };
+            match op {
+                AlignmentOperation::Match | AlignmentOperation::Subst => {
+                    columns.push((reference_pos, *op == AlignmentOperation::Match));
+                    i += 1;
+                    j += 1;
+                }
+                AlignmentOperation::Ins => i += 1,
+                AlignmentOperation::Del => j += 1,
+                AlignmentOperation::Xclip(n) => i += n,
+                AlignmentOperation::Yclip(n) => j += n,
+            }
+        }
+
+        let counts = &mut *self.counts.lock().unwrap();
+        counts.num_alignments += 1;
+        for (pos, is_match) in columns {
+            counts.aligned[pos] += 1;
+            counts.matches[pos] += u64::from(is_match);
+        }
+    }
+
+    /// Count a record identical to the reference, which matches everywhere.
+    pub fn add_identical(&self) {
+        let counts = &mut *self.counts.lock().unwrap();
+        counts.num_alignments += 1;
+        counts.aligned.iter_mut().for_each(|n| *n += 1);
+        counts.matches.iter_mut().for_each(|n| *n += 1);
+    }
+
+    /// Write a TSV table with a row per reference position (1-based).
+    ///
+    /// conservation is the fraction of alignments with a match there, so gaps count against it.
+    pub fn write(&self, path: &Path, precision: usize) {
+        let mut out = BufWriter::new(File::create(path).unwrap());
+        let counts = self.counts.lock().unwrap();
+
+        writeln!(out, "pos\tresidue\taligned\tmatches\tconservation").unwrap();
+        for (pos, &residue) in self.residues.iter().enumerate() {
+            let (aligned, matches) = (counts.aligned[pos], counts.matches[pos]);
+            let conservation = matches as f64 / counts.num_alignments as f64;
+            writeln!(
+                out,
+                "{}\t{}\t{aligned}\t{matches}\t{conservation:.precision$}",
+                pos + 1,
+                char::from(residue)
+            )
+            .unwrap();
+        }
+    }
+}
+
+#[test]
+fn alignments_are_projected_onto_the_reference() {
+    use AlignmentOperation::*;
+
+    let alignment = |operations| Alignment {
+        score: 0,
+        xstart: 0,
+        ystart: 0,
+        xend: 0,
+        yend: 0,
+        xlen: 0,
+        ylen: 0,
+        operations,
+        mode: bio::alignment::AlignmentMode::Global,
+    };
+
+    let conservation = Conservation::new(1, b"ACDE");
+    assert!(conservation.wants(0, 1));
+    assert!(!conservation.wants(1, 1));
+    assert!(!conservation.wants(0, 2));
+
+    // The reference is y here, so its residues advance on Match, Subst, and Del.
+    conservation.add(0, &alignment(vec![Match, Del, Ins, Subst, Match]));
+    // And x here.
+    conservation.add(1, &alignment(vec![Match, Match, Ins, Del, Match]));
+    conservation.add_identical();
+
+    let counts = conservation.counts.lock().unwrap();
+    assert_eq!(counts.num_alignments, 3);
+    assert_eq!(counts.aligned, vec![3, 2, 2, 3]);
+    assert_eq!(counts.matches, vec![3, 2, 1, 3]);
+}
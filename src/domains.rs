@@ -0,0 +1,203 @@
+//! Domains of the references, for the per-domain pid columns of `--ref-domains`.
+
+use crate::cli::EmptyPid;
+use crate::error::{Error, Result};
+use crate::percent_identity;
+use bio::alignment::{Alignment, AlignmentOperation};
+use bio::io::fasta::Record;
+use std::fs;
+use std::path::Path;
+
+/// A named span of a reference, 0-based and half-open.
+#[derive(Clone, Debug, PartialEq)]
+struct Domain {
+    reference: String,
+    name: String,
+    start: usize,
+    end: usize,
+}
+
+/// The domains of a GFF file, in file order.
+#[derive(Clone, Debug)]
+pub struct Domains {
+    domains: Vec<Domain>,
+}
+
+impl Domains {
+    /// Each line is a GFF feature: the reference id, source, type, then the 1-based start and
+    /// (inclusive) end.  The name is the Name or ID attribute, or else the type.  Blank lines and
+    /// lines starting with `#` are skipped.  Names can't repeat, since they name the columns.
+    pub fn from_text(text: &str) -> Result<Self> {
+        let mut domains: Vec<Domain> = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = line.split('\t').collect::<Vec<_>>();
+            let bad = |what: &str| Error::parse(format!("line {} {what}", i + 1));
+            if fields.len() < 5 {
+                return Err(bad(
+                    "should have at least the first 5 GFF columns, separated by tabs",
+                ));
+            }
+
+            let (start, end) = match (fields[3].trim().parse::<usize>(), fields[4].trim().parse()) {
+                (Ok(start), Ok(end)) if 1 <= start && start <= end => (start, end),
+                _ => {
+                    return Err(bad(
+                        "should have a start of at least 1, and an end no less than it",
+                    ))
+                }
+            };
+            let name = fields
+                .get(8)
+                .and_then(|attributes| {
+                    attribute(attributes, "Name").or(attribute(attributes, "ID"))
+                })
+                .unwrap_or(fields[2].trim());
+            if domains.iter().any(|domain| domain.name == name) {
+                return Err(bad(&format!("has the domain name {name} again")));
+            }
+
+            domains.push(Domain {
+                reference: fields[0].trim().to_string(),
+                name: name.to_string(),
+                start: start - 1,
+                end,
+            });
+        }
+
+        Ok(Self { domains })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::from_text(&text).map_err(|e| Error::InvalidFile {
+            kind: "domain file",
+            path: path.to_path_buf(),
+            source: Box::new(e),
+        })
+    }
+
+    /// Check that each domain fits on one of the `references`.
+    pub fn check(&self, references: &[Record]) -> Result<()> {
+        for domain in &self.domains {
+            match references.iter().find(|r| r.id() == domain.reference) {
+                None => {
+                    return Err(Error::parse(format!(
+                        "domain {} is on {}, which isn't in the reference",
+                        domain.name, domain.reference
+                    )))
+                }
+                Some(reference) if domain.end > reference.seq().len() => {
+                    return Err(Error::parse(format!(
+                        "domain {} ends at {}, past the end of {} ({} residues)",
+                        domain.name,
+                        domain.end,
+                        domain.reference,
+                        reference.seq().len()
+                    )))
+                }
+                Some(_) => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.domains.iter().map(|domain| domain.name.as_str())
+    }
+
+    /// The pid of the columns of `alignment` on each domain, in file order.
+    ///
+    /// The alignment is of a query (x) to the part of `reference` starting at `offset`, without
+    /// clips, as from a semiglobal alignment.  A domain's columns are those with one of its
+    /// residues, and the insertions between two of them.  Domains without any columns get the
+    /// `empty_pid`.
+    pub fn pids(
+        &self,
+        reference: &str,
+        alignment: &Alignment,
+        offset: usize,
+        empty_pid: EmptyPid,
+    ) -> Vec<f64> {
+        let mut counts = vec![(0, 0); self.domains.len()];
+        let mut pos = offset + alignment.ystart;
+
+        for op in &alignment.operations {
+            // An insertion sits between the residues before and at `pos`.
+            let in_domain = |d: &Domain| match op {
+                AlignmentOperation::Ins => d.start < pos && pos < d.end,
+                _ => d.start <= pos && pos < d.end,
+            };
+            for (domain, (aln_len, matches)) in self.domains.iter().zip(&mut counts) {
+                if domain.reference == reference && in_domain(domain) {
+                    *aln_len += 1;
+                    *matches += i32::from(*op == AlignmentOperation::Match);
+                }
+            }
+
+            match op {
+                AlignmentOperation::Ins => (),
+                AlignmentOperation::Yclip(n) => pos += n,
+                AlignmentOperation::Xclip(_) => (),
+                _ => pos += 1,
+            }
+        }
+
+        counts
+            .into_iter()
+            .map(|(aln_len, matches)| percent_identity(aln_len, matches, empty_pid))
+            .collect()
+    }
+}
+
+/// The value of the `key` attribute of a GFF attributes column, e.g., `ID=d1;Name=kinase`.
+fn attribute<'a>(attributes: &'a str, key: &str) -> Option<&'a str> {
+    attributes
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, value)| value)
+}
+
+#[test]
+fn domain_pids_are_over_their_reference_span() {
+    use AlignmentOperation::*;
+
+    let domains = Domains::from_text(
+        "##gff-version 3\n\
+         ref\t.\tdomain\t3\t5\t.\t+\t.\tID=d1;Name=kinase\n\
+         ref\t.\tdomain\t6\t8\n\
+         other\t.\tsh2\t1\t4\t.\t.\t.\tID=d3\n",
+    )
+    .unwrap();
+    assert_eq!(
+        domains.names().collect::<Vec<_>>(),
+        ["kinase", "domain", "d3"]
+    );
+
+    // The query covers reference positions 2 to 7 (0-based) of a window starting at 1.
+    let alignment = Alignment {
+        ystart: 1,
+        operations: vec![Match, Ins, Subst, Match, Match, Del, Match],
+        ..Alignment::default()
+    };
+    let pids = domains.pids("ref", &alignment, 1, EmptyPid::Nan);
+    // kinase is 2..5, with the insertion between 2 and 3.
+    assert_eq!(pids[0], 2.0 / 4.0);
+    // domain is 5..8: the Match at 5, the Del at 6, and the Match at 7.
+    assert_eq!(pids[1], 2.0 / 3.0);
+    assert!(pids[2].is_nan());
+
+    assert!(Domains::from_text("ref\t.\td\t0\t5\n").is_err());
+    assert!(Domains::from_text("ref\t.\td\t5\t4\n").is_err());
+    assert!(Domains::from_text("ref\t.\td\t1\t4\nref\t.\td\t5\t6\n").is_err());
+    assert!(Domains::from_text("ref d 1 4\n").is_err());
+}
@@ -0,0 +1,80 @@
+//! The matched positions of each alignment, for `--dotplot`.
+
+use crate::error::{Error, Result};
+use bio::alignment::{Alignment, AlignmentOperation};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// The (0-based) x and y positions of each Match column of `alignment`.
+pub fn match_coords(alignment: &Alignment) -> Vec<(usize, usize)> {
+    let (mut i, mut j) = (0, 0);
+    let mut coords = Vec::new();
+
+    for op in &alignment.operations {
+        match op {
+            AlignmentOperation::Match => {
+                coords.push((i, j));
+                i += 1;
+                j += 1;
+            }
+            AlignmentOperation::Subst => {
+                i += 1;
+                j += 1;
+            }
+            AlignmentOperation::Ins => i += 1,
+            AlignmentOperation::Del => j += 1,
+            AlignmentOperation::Xclip(n) => i += n,
+            AlignmentOperation::Yclip(n) => j += n,
+        }
+    }
+
+    coords
+}
+
+/// A directory with one coordinate file per pair.
+pub struct Dotplot {
+    dir: PathBuf,
+}
+
+impl Dotplot {
+    /// Create `dir`.
+    pub fn new(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir).map_err(|source| Error::Io {
+            path: dir.to_path_buf(),
+            source,
+        })?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Write the (1-based) matched positions of the pair to `DIR/X_vs_Y.tsv`.
+    ///
+    /// A `/` in an id would name a subdirectory, so it's replaced with `_`.
+    pub fn write(&self, x_id: &str, y_id: &str, coords: &[(usize, usize)]) {
+        let name = format!("{x_id}_vs_{y_id}.tsv").replace('/', "_");
+        let path = self.dir.join(name);
+        let mut out = BufWriter::new(File::create(&path).unwrap_or_else(|e| {
+            panic!("couldn't create {}: {e}", path.display());
+        }));
+
+        writeln!(out, "x_pos\ty_pos").unwrap();
+        for (i, j) in coords {
+            writeln!(out, "{}\t{}", i + 1, j + 1).unwrap();
+        }
+    }
+}
+
+#[test]
+fn matches_are_projected_onto_both_sequences() {
+    use AlignmentOperation::*;
+
+    let alignment = Alignment {
+        operations: vec![Match, Ins, Subst, Match, Del, Del, Match],
+        ..Alignment::default()
+    };
+
+    assert_eq!(match_coords(&alignment), vec![(0, 0), (3, 2), (4, 5)]);
+}
@@ -0,0 +1,62 @@
+//! Errors from checking the arguments and reading the input files.
+
+use std::path::PathBuf;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("file {} does not exist", .0.display())]
+    FileNotFound(PathBuf),
+
+    #[error("file {} already exists", .0.display())]
+    OutputExists(PathBuf),
+
+    #[error(
+        "directory {} doesn't exist, so {} can't be written (use --mkdir to create it)",
+        dir.display(),
+        path.display()
+    )]
+    MissingDirectory { dir: PathBuf, path: PathBuf },
+
+    #[error("couldn't read or write {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    /// A malformed argument value or file contents.
+    #[error("{0}")]
+    Parse(String),
+
+    /// Arguments that can't be used together, or that don't fit the input.
+    #[error("{0}")]
+    Usage(String),
+
+    #[error("{failed} pairs failed and {dropped} were never aligned, so they're missing from the output")]
+    MissingPairs { failed: usize, dropped: usize },
+
+    /// A file that was read but couldn't be parsed, e.g., a bad matrix or profile.
+    #[error("bad {kind} {}: {source}", path.display())]
+    InvalidFile {
+        kind: &'static str,
+        path: PathBuf,
+        source: Box<Error>,
+    },
+
+    #[error(
+        "can't tell the format of {} (use .tsv, .jsonl, .json, .blast6, .m8, .maf, .sto, .bed, .edges, .phy, .phylip, or .condensed)",
+        .0.display()
+    )]
+    UnknownFormat(PathBuf),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub(crate) fn parse(message: impl Into<String>) -> Self {
+        Self::Parse(message.into())
+    }
+
+    pub(crate) fn usage(message: impl Into<String>) -> Self {
+        Self::Usage(message.into())
+    }
+}
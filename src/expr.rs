@@ -0,0 +1,260 @@
+//! Extra columns computed from the others with a small arithmetic expression, for `--compute`.
+//!
+//! Expressions have numbers, the column names in `Var`, `+ - * /`, unary minus, and parentheses,
+//! with the usual precedence.
+
+use crate::error::{Error, Result};
+use crate::output::COLUMN_NAMES;
+
+/// The numeric columns an expression can use.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Var {
+    XLen,
+    YLen,
+    AlnLen,
+    Matches,
+    Mismatches,
+    /// The number of gap opens.
+    Gaps,
+    Score,
+    Pid,
+}
+
+impl Var {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "xlen" => Some(Var::XLen),
+            "ylen" => Some(Var::YLen),
+            "alnlen" | "aln_len" => Some(Var::AlnLen),
+            "matches" => Some(Var::Matches),
+            "mismatches" => Some(Var::Mismatches),
+            "gaps" => Some(Var::Gaps),
+            "score" => Some(Var::Score),
+            "pid" => Some(Var::Pid),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Num(f64),
+    Var(Var),
+    Neg(Box<Expr>),
+    Op(u8, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, value: &impl Fn(Var) -> f64) -> f64 {
+        match self {
+            Expr::Num(x) => *x,
+            Expr::Var(var) => value(*var),
+            Expr::Neg(e) => -e.eval(value),
+            Expr::Op(op, a, b) => {
+                let (a, b) = (a.eval(value), b.eval(value));
+                match op {
+                    b'+' => a + b,
+                    b'-' => a - b,
+                    b'*' => a * b,
+                    _ => a / b,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Num(f64),
+    Name(String),
+    /// An operator or parenthesis.
+    Symbol(u8),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let bytes = s.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        let start = i;
+        match c {
+            b' ' | b'\t' => i += 1,
+            b'+' | b'-' | b'*' | b'/' | b'(' | b')' => {
+                tokens.push(Token::Symbol(c));
+                i += 1;
+            }
+            b'0'..=b'9' | b'.' => {
+                while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'.') {
+                    i += 1;
+                }
+                let x = s[start..i]
+                    .parse()
+                    .map_err(|_| Error::parse(format!("{} is not a number", &s[start..i])))?;
+                tokens.push(Token::Num(x));
+            }
+            b'a'..=b'z' | b'_' => {
+                while i < bytes.len() && (bytes[i].is_ascii_lowercase() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                tokens.push(Token::Name(s[start..i].to_string()));
+            }
+            _ => {
+                return Err(Error::parse(format!(
+                    "unexpected {:?} in {s}",
+                    char::from(c)
+                )))
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// A recursive descent parser over the tokens.
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    /// Take the next token if it's one of `symbols`.
+    fn symbol(&mut self, symbols: &[u8]) -> Option<u8> {
+        match self.peek() {
+            Some(&Token::Symbol(c)) if symbols.contains(&c) => {
+                self.pos += 1;
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+
+    /// expr = term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Expr> {
+        let mut e = self.term()?;
+        while let Some(op) = self.symbol(b"+-") {
+            e = Expr::Op(op, Box::new(e), Box::new(self.term()?));
+        }
+        Ok(e)
+    }
+
+    /// term = factor (('*' | '/') factor)*
+    fn term(&mut self) -> Result<Expr> {
+        let mut e = self.factor()?;
+        while let Some(op) = self.symbol(b"*/") {
+            e = Expr::Op(op, Box::new(e), Box::new(self.factor()?));
+        }
+        Ok(e)
+    }
+
+    /// factor = '-' factor | number | column | '(' expr ')'
+    fn factor(&mut self) -> Result<Expr> {
+        if self.symbol(b"-").is_some() {
+            return Ok(Expr::Neg(Box::new(self.factor()?)));
+        }
+        if self.symbol(b"(").is_some() {
+            let e = self.expr()?;
+            return match self.symbol(b")") {
+                Some(_) => Ok(e),
+                None => Err(Error::parse("missing a )".to_string())),
+            };
+        }
+
+        let token = self.peek().cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Num(x)) => Ok(Expr::Num(x)),
+            Some(Token::Name(name)) => Var::from_name(&name).map(Expr::Var).ok_or_else(|| {
+                Error::parse(format!(
+                    "unknown column {name} (use xlen, ylen, alnlen, matches, mismatches, gaps, score, or pid)"
+                ))
+            }),
+            Some(Token::Symbol(c)) => Err(Error::parse(format!(
+                "unexpected {:?}",
+                char::from(c)
+            ))),
+            None => Err(Error::parse("the expression ends too soon".to_string())),
+        }
+    }
+}
+
+/// A `name=expr` column.
+#[derive(Clone, Debug)]
+pub struct ComputedColumn {
+    pub name: String,
+    expr: Expr,
+}
+
+impl ComputedColumn {
+    pub fn parse(s: &str) -> Result<Self> {
+        let (name, text) = s
+            .split_once('=')
+            .ok_or_else(|| Error::parse(format!("{s} should look like name=expression")))?;
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(Error::parse(format!(
+                "{name:?} isn't a column name, which can't be empty or have spaces"
+            )));
+        }
+        if COLUMN_NAMES.contains(&name) {
+            return Err(Error::parse(format!("{name} is already a column")));
+        }
+
+        let tokens = tokenize(text)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+        };
+        let expr = parser.expr()?;
+        if parser.pos < tokens.len() {
+            return Err(Error::parse(format!(
+                "unexpected stuff at the end of {text}"
+            )));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            expr,
+        })
+    }
+
+    /// Evaluate the expression, with `value` giving the value of each column.
+    pub fn eval(&self, value: impl Fn(Var) -> f64) -> f64 {
+        self.expr.eval(&value)
+    }
+}
+
+#[test]
+fn expressions_follow_precedence() {
+    let value = |var| match var {
+        Var::Matches => 6.0,
+        Var::YLen => 8.0,
+        Var::Score => 10.0,
+        _ => 2.0,
+    };
+    let eval = |s: &str| ComputedColumn::parse(s).unwrap().eval(value);
+
+    assert_eq!(eval("cov=matches/ylen"), 0.75);
+    assert_eq!(eval("z = score - 2 * aln_len"), 6.0);
+    assert_eq!(eval("z=(score - 2) * -alnlen / 4"), -4.0);
+    assert_eq!(ComputedColumn::parse("cov=1").unwrap().name, "cov");
+
+    for bad in [
+        "matches/ylen",
+        "=1",
+        "x=",
+        "x=(1",
+        "x=1 2",
+        "x=nope",
+        "x=1 % 2",
+        "x=matches/alnlen",
+        "score=1",
+    ] {
+        assert!(ComputedColumn::parse(bad).is_err(), "{bad}");
+    }
+}
@@ -0,0 +1,123 @@
+//! Position-specific gap open penalties from an existing alignment, for `--msa-gap-profile`.
+
+use crate::error::{Error, Result};
+use bio::io::fasta::Reader;
+use std::fs;
+use std::path::Path;
+
+/// How often the other sequences of an alignment have gaps, by position of its first sequence.
+#[derive(Clone, Debug)]
+pub struct GapProfile {
+    /// For each position, the fraction of the other sequences with a gap in its column.
+    deletions: Vec<f64>,
+    /// For each boundary between positions, including both ends, the fraction of the other
+    /// sequences with a residue in the first sequence's gap columns there.
+    insertions: Vec<f64>,
+}
+
+fn is_gap(c: u8) -> bool {
+    c == b'-' || c == b'.'
+}
+
+impl GapProfile {
+    /// Parse an aligned FASTA, with `-` or `.` for gaps.  The first sequence is the frame, so
+    /// its residues are the positions.
+    pub fn from_text(text: &str) -> Result<Self> {
+        let records = Reader::new(text.as_bytes())
+            .records()
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(|e| Error::parse(e.to_string()))?;
+        if records.len() < 2 {
+            return Err(Error::parse("the alignment needs at least 2 sequences"));
+        }
+
+        let first = records[0].seq();
+        if let Some(record) = records.iter().find(|r| r.seq().len() != first.len()) {
+            return Err(Error::parse(format!(
+                "sequence {} has {} columns, but the first has {}",
+                record.id(),
+                record.seq().len(),
+                first.len()
+            )));
+        }
+        let len = first.iter().filter(|&&c| !is_gap(c)).count();
+        if len == 0 {
+            return Err(Error::parse("the first sequence has no residues"));
+        }
+
+        let others = &records[1..];
+        let mut deletions = Vec::with_capacity(len);
+        // Which of the other sequences have a residue at each boundary.
+        let mut inserted = vec![vec![false; others.len()]; len + 1];
+        for (column, &c) in first.iter().enumerate() {
+            if is_gap(c) {
+                let boundary = deletions.len();
+                for (k, other) in others.iter().enumerate() {
+                    inserted[boundary][k] |= !is_gap(other.seq()[column]);
+                }
+            } else {
+                let num_gaps = others
+                    .iter()
+                    .filter(|other| is_gap(other.seq()[column]))
+                    .count();
+                deletions.push(num_gaps as f64 / others.len() as f64);
+            }
+        }
+        let insertions = inserted
+            .into_iter()
+            .map(|rows| rows.iter().filter(|&&r| r).count() as f64 / others.len() as f64)
+            .collect();
+
+        Ok(Self {
+            deletions,
+            insertions,
+        })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::from_text(&text).map_err(|e| Error::InvalidFile {
+            kind: "gap profile alignment",
+            path: path.to_path_buf(),
+            source: Box::new(e),
+        })
+    }
+
+    /// The number of positions.
+    pub fn len(&self) -> usize {
+        self.deletions.len()
+    }
+
+    /// The gap open penalty for skipping each position, and for inserting at each boundary,
+    /// scaled by one minus how often the alignment has that gap.
+    pub fn gap_opens(&self, gap_open: i32) -> (Vec<i32>, Vec<i32>) {
+        let scale = |frequencies: &[f64]| {
+            frequencies
+                .iter()
+                .map(|f| (f64::from(gap_open) * (1.0 - f)).round() as i32)
+                .collect()
+        };
+
+        (scale(&self.deletions), scale(&self.insertions))
+    }
+}
+
+#[test]
+fn gap_frequencies_are_by_position_of_the_first_sequence() {
+    let gaps = GapProfile::from_text(">ref\nMK-V\n>a\nM--V\n>b\nMKAV\n").unwrap();
+    assert_eq!(gaps.len(), 3);
+    assert_eq!(gaps.deletions, vec![0.0, 0.5, 0.0]);
+    assert_eq!(gaps.insertions, vec![0.0, 0.0, 0.5, 0.0]);
+    assert_eq!(
+        gaps.gap_opens(-10),
+        (vec![-10, -5, -10], vec![-10, -10, -5, -10])
+    );
+
+    assert!(GapProfile::from_text(">ref\nMKV\n").is_err());
+    assert!(GapProfile::from_text(">ref\nMKV\n>a\nMK\n").is_err());
+    assert!(GapProfile::from_text(">ref\n---\n>a\nMKV\n").is_err());
+}
@@ -0,0 +1,182 @@
+//! Splitting the records into groups, by their descriptions (`--group-by-desc`), by their ids
+//! (`--pair-by-id-pattern`), or by a table of ids (`--groups`).
+
+use crate::error::{Error, Result};
+use bio::io::fasta::Record;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The group name used for records whose description doesn't match, or whose id isn't in the
+/// group table.
+const UNGROUPED: &str = "ungrouped";
+
+/// The group name of a description or id: the first capture group of `pattern` if it has one,
+/// otherwise the whole match.
+fn group_name<'a>(text: Option<&'a str>, pattern: &Regex) -> Option<&'a str> {
+    let captures = pattern.captures(text?)?;
+
+    captures
+        .get(1)
+        .or_else(|| captures.get(0))
+        .map(|m| m.as_str())
+}
+
+/// Assign each record the index of its group, named in `names`, in order of first appearance.
+///
+/// Records without a name go in the "ungrouped" group, or are dropped if `skip_ungrouped`.
+/// Returns the kept records along with their group indices.
+fn assign_groups(
+    records: Vec<Record>,
+    names: Vec<Option<String>>,
+    skip_ungrouped: bool,
+) -> (Vec<Record>, Vec<usize>) {
+    let mut indices: HashMap<String, usize> = HashMap::new();
+    let mut kept = Vec::with_capacity(records.len());
+    let mut groups = Vec::with_capacity(records.len());
+
+    for (record, name) in records.into_iter().zip(names) {
+        let name = match name {
+            Some(name) => name,
+            None if skip_ungrouped => continue,
+            None => UNGROUPED.to_string(),
+        };
+
+        let next = indices.len();
+        groups.push(*indices.entry(name).or_insert(next));
+        kept.push(record);
+    }
+
+    (kept, groups)
+}
+
+/// Group records by the part of their description that `pattern` picks out.
+pub fn group_by_desc(
+    records: Vec<Record>,
+    pattern: &Regex,
+    skip_ungrouped: bool,
+) -> (Vec<Record>, Vec<usize>) {
+    let names = records
+        .iter()
+        .map(|r| group_name(r.desc(), pattern).map(str::to_string))
+        .collect();
+
+    assign_groups(records, names, skip_ungrouped)
+}
+
+/// Group records by the base of their ids that `pattern` picks out.
+pub fn group_by_id(
+    records: Vec<Record>,
+    pattern: &Regex,
+    skip_ungrouped: bool,
+) -> (Vec<Record>, Vec<usize>) {
+    let names = records
+        .iter()
+        .map(|r| group_name(Some(r.id()), pattern).map(str::to_string))
+        .collect();
+
+    assign_groups(records, names, skip_ungrouped)
+}
+
+/// A table of `id<TAB>group` lines, for `--groups`.
+#[derive(Clone)]
+pub struct GroupTable {
+    groups: HashMap<String, String>,
+}
+
+impl GroupTable {
+    pub fn from_text(text: &str) -> Result<Self> {
+        let groups = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| match line.split('\t').collect::<Vec<_>>()[..] {
+                [id, group] => Ok((id.trim().to_string(), group.trim().to_string())),
+                _ => Err(Error::parse(format!(
+                    "line {} should have an id and a group, separated by a tab",
+                    i + 1
+                ))),
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { groups })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::from_text(&text).map_err(|e| Error::InvalidFile {
+            kind: "group table",
+            path: path.to_path_buf(),
+            source: Box::new(e),
+        })
+    }
+
+    /// Group records by looking up their ids.
+    pub fn group(&self, records: Vec<Record>, skip_ungrouped: bool) -> (Vec<Record>, Vec<usize>) {
+        let names = records
+            .iter()
+            .map(|r| self.groups.get(r.id()).cloned())
+            .collect();
+
+        assign_groups(records, names, skip_ungrouped)
+    }
+}
+
+#[test]
+fn records_are_grouped_by_the_capture() {
+    let records = vec![
+        Record::with_attrs("a", Some("taxon=Bacteria len=10"), b"ACDE"),
+        Record::with_attrs("b", Some("taxon=Archaea"), b"ACDE"),
+        Record::with_attrs("c", None, b"ACDE"),
+        Record::with_attrs("d", Some("taxon=Bacteria"), b"ACDE"),
+    ];
+    let pattern = Regex::new(r"taxon=(\w+)").unwrap();
+
+    let (kept, groups) = group_by_desc(records.clone(), &pattern, false);
+    assert_eq!(kept.len(), 4);
+    assert_eq!(groups, vec![0, 1, 2, 0]);
+
+    let (kept, groups) = group_by_desc(records, &pattern, true);
+    let ids = kept.iter().map(|r| r.id()).collect::<Vec<_>>();
+    assert_eq!(ids, vec!["a", "b", "d"]);
+    assert_eq!(groups, vec![0, 1, 0]);
+}
+
+#[test]
+fn records_are_grouped_by_their_base_ids() {
+    let records = vec![
+        Record::with_attrs("gene_A_v1", None, b"ACDE"),
+        Record::with_attrs("gene_B_v1", None, b"ACDE"),
+        Record::with_attrs("gene_A_v2", None, b"ACDE"),
+        Record::with_attrs("other", None, b"ACDE"),
+    ];
+    let pattern = Regex::new(r"^(.+)_v\d+$").unwrap();
+
+    let (_, groups) = group_by_id(records, &pattern, false);
+    assert_eq!(groups, vec![0, 1, 0, 2]);
+}
+
+#[test]
+fn records_are_grouped_by_the_table() {
+    let records = vec![
+        Record::with_attrs("a", None, b"ACDE"),
+        Record::with_attrs("b", None, b"ACDE"),
+        Record::with_attrs("c", None, b"ACDE"),
+        Record::with_attrs("d", None, b"ACDE"),
+    ];
+    let table = GroupTable::from_text("# id\tgroup\na\tx\nb\ty\nd\tx\n").unwrap();
+    assert!(GroupTable::from_text("a x\n").is_err());
+
+    let (_, groups) = table.group(records.clone(), false);
+    assert_eq!(groups, vec![0, 1, 2, 0]);
+
+    let (kept, groups) = table.group(records, true);
+    let ids = kept.iter().map(|r| r.id()).collect::<Vec<_>>();
+    assert_eq!(ids, vec!["a", "b", "d"]);
+    assert_eq!(groups, vec![0, 1, 0]);
+}
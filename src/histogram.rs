@@ -0,0 +1,59 @@
+//! A histogram of the pids of the written pairs, for `--pid-histogram`.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{self, AtomicUsize};
+
+/// Counts of pids in equal-width bins from 0 to 1.  The last bin includes 1.
+pub struct PidHistogram {
+    counts: Vec<AtomicUsize>,
+}
+
+impl PidHistogram {
+    pub fn new(num_bins: usize) -> Self {
+        Self {
+            counts: (0..num_bins).map(|_| AtomicUsize::new(0)).collect(),
+        }
+    }
+
+    fn bin(&self, percent_identity: f64) -> usize {
+        let n = self.counts.len();
+        ((percent_identity * n as f64) as usize).min(n - 1)
+    }
+
+    /// Count a pid.  NaN (see --empty-pid) isn't counted.
+    pub fn add(&self, percent_identity: f64) {
+        if !percent_identity.is_nan() {
+            self.counts[self.bin(percent_identity)].fetch_add(1, atomic::Ordering::Relaxed);
+        }
+    }
+
+    /// Write a TSV table of the lower edge of each bin and its count.
+    pub fn write(&self, path: &Path, precision: usize) {
+        let mut out = BufWriter::new(File::create(path).unwrap());
+        let n = self.counts.len();
+
+        writeln!(out, "bin_start\tcount").unwrap();
+        for (i, count) in self.counts.iter().enumerate() {
+            let start = i as f64 / n as f64;
+            let count = count.load(atomic::Ordering::Relaxed);
+            writeln!(out, "{start:.precision$}\t{count}").unwrap();
+        }
+    }
+}
+
+#[test]
+fn pids_are_binned() {
+    let histogram = PidHistogram::new(4);
+    [0.0, 0.24, 0.25, 0.9, 1.0, f64::NAN]
+        .into_iter()
+        .for_each(|pid| histogram.add(pid));
+
+    let counts = histogram
+        .counts
+        .iter()
+        .map(|count| count.load(atomic::Ordering::Relaxed))
+        .collect::<Vec<_>>();
+    assert_eq!(counts, vec![2, 1, 0, 2]);
+}
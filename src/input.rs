@@ -0,0 +1,588 @@
+//! Reading the input sequences.
+
+use crate::cli::{AlignArgs, NonstandardAa};
+use crate::error::{Error, Result};
+use crate::mask;
+use bio::io::fasta::{Reader, Record};
+use flate2::read::MultiGzDecoder;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// File extensions treated as FASTA when the input is a directory, each also with `.gz`.
+const FASTA_EXTENSIONS: [&str; 2] = ["fasta", "fa"];
+
+/// The first two bytes of a gzip file.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// How the input sequences should be read.
+pub struct InputOptions<'a> {
+    /// Split ids on this delimiter and keep the field at this (0-based) index.
+    pub id_field: Option<(&'a str, usize)>,
+    pub recursive: bool,
+    pub nonstandard_aa: NonstandardAa,
+    /// Drop records shorter than this.
+    pub min_seq_len: Option<usize>,
+    /// Drop records longer than this.
+    pub max_seq_len: Option<usize>,
+    /// Cut records down to their first this many residues.
+    pub truncate: Option<usize>,
+    /// Drop records whose sequence is contained in another record's.
+    pub drop_substrings: bool,
+    /// Mask low-complexity regions with X.
+    pub mask_low_complexity: bool,
+    /// Table of `old<TAB>new` ids to rename records with.
+    pub relabel: Option<&'a RelabelTable>,
+    /// Exit with an error if a record's id isn't in the relabel table.
+    pub strict_relabel: bool,
+    /// Stop reading after this many records.
+    pub max_records: Option<usize>,
+    pub verbose: bool,
+}
+
+impl<'a> InputOptions<'a> {
+    pub fn new(args: &'a AlignArgs) -> Self {
+        Self {
+            id_field: args.id_field(),
+            recursive: args.recursive,
+            nonstandard_aa: args.nonstandard_aa,
+            min_seq_len: args.min_seq_len,
+            max_seq_len: args.max_seq_len,
+            truncate: args.truncate.map(|k| k as usize),
+            drop_substrings: args.drop_substrings,
+            mask_low_complexity: args.mask_low_complexity,
+            relabel: args.relabel.as_ref(),
+            strict_relabel: args.strict_relabel,
+            max_records: args.max_records,
+            verbose: args.verbose,
+        }
+    }
+}
+
+/// Pick the `index`-th (0-based) `delimiter`-separated field of `id`.
+///
+/// Falls back to the full id (with a warning) if there are too few fields.
+fn id_field<'a>(id: &'a str, delimiter: &str, index: usize) -> &'a str {
+    match id.split(delimiter).nth(index) {
+        Some(field) => field,
+        None => {
+            eprintln!(
+                "warning: id {id} has fewer than {} fields split on '{delimiter}', using the full id",
+                index + 1
+            );
+            id
+        }
+    }
+}
+
+/// FASTA files in `dir`, sorted by path so the record order is stable.
+pub fn fasta_files(dir: &Path, recursive: bool) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            if recursive {
+                files.extend(fasta_files(&path, recursive)?);
+            }
+        } else if path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(is_fasta_name)
+        {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Whether a file name has one of the FASTA extensions, gzipped or not.
+fn is_fasta_name(name: &str) -> bool {
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+
+    name.rsplit_once('.')
+        .is_some_and(|(_, ext)| FASTA_EXTENSIONS.contains(&ext))
+}
+
+/// Open a FASTA file, or stdin for `-`.  Gzipped input, by its magic bytes, is decompressed.
+pub fn open_fasta(path: &Path) -> io::Result<Reader<Box<dyn BufRead>>> {
+    let input: Box<dyn Read> = if path == Path::new("-") {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(path)?)
+    };
+    let mut input = BufReader::new(input);
+
+    let input: Box<dyn BufRead> = if input.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Box::new(BufReader::new(MultiGzDecoder::new(input)))
+    } else {
+        Box::new(input)
+    };
+
+    Ok(Reader::from_bufread(input))
+}
+
+/// Apply the `--nonstandard-aa` policy to an (uppercase) sequence.
+fn handle_nonstandard_aa(id: &str, seq: &mut [u8], policy: NonstandardAa) -> Result<()> {
+    match policy {
+        NonstandardAa::Substitute => seq.iter_mut().for_each(|c| match c {
+            b'U' => *c = b'C',
+            b'O' => *c = b'K',
+            _ => (),
+        }),
+        // Handled by the scoring matrix.
+        NonstandardAa::Neutral => (),
+        NonstandardAa::Error => {
+            if let Some(c) = seq.iter().find(|&&c| c == b'U' || c == b'O') {
+                return Err(Error::parse(format!(
+                    "sequence {id} contains nonstandard residue {}",
+                    *c as char
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Read at most `limit` records from a FASTA file.
+fn read_fasta(path: &Path, opts: &InputOptions, limit: usize) -> Result<Vec<Record>> {
+    let reader = open_fasta(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    reader
+        .records()
+        .take(limit)
+        .map(|x| {
+            let x = x.map_err(|source| fasta_error(path, source))?;
+            let id = match opts.id_field {
+                None => x.id(),
+                Some((delimiter, index)) => id_field(x.id(), delimiter, index),
+            };
+            // Some of the inteins have lowercase AA residues.  This breaks the alignment.
+            let mut uppercase_seq = x.seq().to_ascii_uppercase();
+            handle_nonstandard_aa(id, &mut uppercase_seq, opts.nonstandard_aa)?;
+            Ok(Record::with_attrs(id, x.desc(), uppercase_seq.as_slice()))
+        })
+        .collect()
+}
+
+/// A record that couldn't be read.  The FASTA reader reports malformed records as `Other`.
+fn fasta_error(path: &Path, source: io::Error) -> Error {
+    if source.kind() == io::ErrorKind::Other {
+        Error::InvalidFile {
+            kind: "FASTA file",
+            path: path.to_path_buf(),
+            source: Box::new(Error::parse(source.to_string())),
+        }
+    } else {
+        Error::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
+
+/// A two-column `old<TAB>new` id table, for `--relabel`.
+#[derive(Clone)]
+pub struct RelabelTable {
+    ids: HashMap<String, String>,
+}
+
+impl RelabelTable {
+    pub fn from_text(text: &str) -> Result<Self> {
+        let ids = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(
+                |(i, line)| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                    [old, new] => Ok((old.to_string(), new.to_string())),
+                    _ => Err(Error::parse(format!(
+                        "line {} should have two columns",
+                        i + 1
+                    ))),
+                },
+            )
+            .collect::<Result<_>>()?;
+
+        Ok(Self { ids })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::from_text(&text).map_err(|e| Error::InvalidFile {
+            kind: "relabel table",
+            path: path.to_path_buf(),
+            source: Box::new(e),
+        })
+    }
+
+    /// Rename records through the table.  Ids not in the table are kept, unless `strict`.
+    fn relabel(&self, records: Vec<Record>, strict: bool) -> Result<Vec<Record>> {
+        records
+            .into_iter()
+            .map(|record| match self.ids.get(record.id()) {
+                Some(new) => Ok(Record::with_attrs(new, record.desc(), record.seq())),
+                None if strict => Err(Error::parse(format!(
+                    "id {} is not in the relabel table",
+                    record.id()
+                ))),
+                None => Ok(record),
+            })
+            .collect()
+    }
+}
+
+/// Read the records from a FASTA file, or from every FASTA file in a directory.
+///
+/// When reading a directory, a warning is printed for ids that show up in more than one file.
+/// Records outside the allowed length range are dropped, and the number dropped is logged.  Ids
+/// are relabeled last, so every output uses the new ids.
+pub fn get_records(path: &Path, opts: &InputOptions) -> Result<Vec<Record>> {
+    let mut records = match opts.max_records {
+        None => read_input(path, opts, usize::MAX)?,
+        Some(max_records) => {
+            // Read one extra to tell whether anything was cut off.
+            let mut records = read_input(path, opts, max_records + 1)?;
+            if records.len() > max_records {
+                records.truncate(max_records);
+                if opts.verbose {
+                    eprintln!("read only the first {max_records} records (see --max-records)");
+                }
+            }
+            records
+        }
+    };
+
+    if opts.min_seq_len.is_some() || opts.max_seq_len.is_some() {
+        let num_records = records.len();
+        records.retain(|record| {
+            let len = record.seq().len();
+            opts.min_seq_len.is_none_or(|min| len >= min)
+                && opts.max_seq_len.is_none_or(|max| len <= max)
+        });
+        if opts.verbose {
+            eprintln!(
+                "filtered {} of {num_records} records by length",
+                num_records - records.len()
+            );
+        }
+    }
+
+    if let Some(max_len) = opts.truncate {
+        records = truncate(records, max_len);
+    }
+
+    if opts.drop_substrings {
+        records = drop_substrings(records, opts.verbose);
+    }
+
+    if opts.mask_low_complexity {
+        records = mask_low_complexity(records, opts.verbose);
+    }
+
+    match opts.relabel {
+        Some(table) => table.relabel(records, opts.strict_relabel),
+        None => Ok(records),
+    }
+}
+
+/// Keep only the first `max_len` residues of each record, warning if any were cut.
+fn truncate(records: Vec<Record>, max_len: usize) -> Vec<Record> {
+    let mut num_truncated = 0;
+
+    let records = records
+        .into_iter()
+        .map(|record| {
+            if record.seq().len() <= max_len {
+                return record;
+            }
+
+            num_truncated += 1;
+            Record::with_attrs(record.id(), record.desc(), &record.seq()[..max_len])
+        })
+        .collect();
+
+    if num_truncated > 0 {
+        eprintln!("warning: truncated {num_truncated} records to their first {max_len} residues");
+    }
+
+    records
+}
+
+/// Mask the low-complexity regions of each record with X.
+fn mask_low_complexity(records: Vec<Record>, verbose: bool) -> Vec<Record> {
+    let mut num_masked = 0;
+    let mut num_records = 0;
+
+    let records = records
+        .into_iter()
+        .map(|record| {
+            let mut seq = record.seq().to_vec();
+            let n = mask::mask_low_complexity(&mut seq);
+            if n == 0 {
+                return record;
+            }
+
+            num_masked += n;
+            num_records += 1;
+            Record::with_attrs(record.id(), record.desc(), &seq)
+        })
+        .collect();
+
+    if verbose {
+        eprintln!("masked {num_masked} low-complexity residues in {num_records} records");
+    }
+
+    records
+}
+
+/// Rename each record to its index, writing an `index<TAB>id` line for each to `path`.
+pub fn index_records(records: Vec<Record>, path: &Path) -> Vec<Record> {
+    let mut out = BufWriter::new(File::create(path).unwrap());
+
+    records
+        .into_iter()
+        .enumerate()
+        .map(|(i, record)| {
+            writeln!(out, "{i}\t{}", record.id()).unwrap();
+            Record::with_attrs(&i.to_string(), record.desc(), record.seq())
+        })
+        .collect()
+}
+
+/// Drop records whose sequence is an exact substring of another record's, keeping the order.
+///
+/// Records are scanned longest first, so each only needs checking against the longer (or equal
+/// length, earlier) records that were kept before it.
+fn drop_substrings(records: Vec<Record>, verbose: bool) -> Vec<Record> {
+    let mut order = (0..records.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| std::cmp::Reverse(records[i].seq().len()));
+
+    let contains = |haystack: &[u8], needle: &[u8]| {
+        needle.is_empty() || haystack.windows(needle.len()).any(|w| w == needle)
+    };
+
+    let mut kept: Vec<usize> = Vec::with_capacity(records.len());
+    let mut dropped = vec![false; records.len()];
+
+    for i in order {
+        let seq = records[i].seq();
+        match kept.iter().find(|&&k| contains(records[k].seq(), seq)) {
+            Some(&k) => {
+                if verbose {
+                    eprintln!(
+                        "dropped {}, which is contained in {}",
+                        records[i].id(),
+                        records[k].id()
+                    );
+                }
+                dropped[i] = true;
+            }
+            None => kept.push(i),
+        }
+    }
+
+    let num_records = records.len();
+    let records = records
+        .into_iter()
+        .zip(dropped)
+        .filter_map(|(record, dropped)| (!dropped).then_some(record))
+        .collect::<Vec<_>>();
+
+    if verbose {
+        eprintln!(
+            "dropped {} of {num_records} records that were substrings of others",
+            num_records - records.len()
+        );
+    }
+
+    records
+}
+
+/// The groups of records with exactly the same sequence, each in input order, by first record.
+///
+/// Records without a duplicate aren't included.
+pub fn exact_duplicates(records: &[Record]) -> Vec<Vec<usize>> {
+    let mut groups: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for (i, record) in records.iter().enumerate() {
+        groups.entry(record.seq()).or_default().push(i);
+    }
+
+    let mut groups = groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect::<Vec<_>>();
+    groups.sort_unstable_by_key(|group| group[0]);
+
+    groups
+}
+
+/// The records that stand for their exact duplicates, for `--collapse-identical-output`.
+pub struct Collapsed {
+    /// The ids of the later records with the same sequence as each record, comma-separated, or
+    /// `-` if there are none.  `None` for records collapsed into an earlier one.
+    ids: Vec<Option<String>>,
+}
+
+impl Collapsed {
+    pub fn new(records: &[Record]) -> Self {
+        let mut ids = vec![Some("-".to_string()); records.len()];
+        for group in exact_duplicates(records) {
+            let others = group[1..]
+                .iter()
+                .map(|&i| records[i].id())
+                .collect::<Vec<_>>();
+            ids[group[0]] = Some(others.join(","));
+            for &i in &group[1..] {
+                ids[i] = None;
+            }
+        }
+
+        Self { ids }
+    }
+
+    /// Is record `i` left out, since an earlier record has its sequence?
+    pub fn is_collapsed(&self, i: usize) -> bool {
+        self.ids[i].is_none()
+    }
+
+    /// The number of records left out.
+    pub fn num_collapsed(&self) -> usize {
+        self.ids.iter().filter(|ids| ids.is_none()).count()
+    }
+
+    /// The ids record `i` stands for, or `-`.  Only for records that aren't collapsed.
+    pub fn ids(&self, i: usize) -> &str {
+        self.ids[i].as_deref().unwrap()
+    }
+}
+
+/// Read at most `limit` records from the input file or directory.
+fn read_input(path: &Path, opts: &InputOptions, limit: usize) -> Result<Vec<Record>> {
+    if !path.is_dir() {
+        return read_fasta(path, opts, limit);
+    }
+
+    let mut seen: HashMap<String, PathBuf> = HashMap::new();
+    let mut records = Vec::new();
+
+    let files = fasta_files(path, opts.recursive).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    for file in files {
+        if records.len() >= limit {
+            break;
+        }
+
+        for record in read_fasta(&file, opts, limit - records.len())? {
+            match seen.get(record.id()) {
+                Some(first) if first != &file => eprintln!(
+                    "warning: id {} in {} was already seen in {}",
+                    record.id(),
+                    file.display(),
+                    first.display()
+                ),
+                Some(_) => (),
+                None => {
+                    seen.insert(record.id().to_string(), file.clone());
+                }
+            }
+
+            records.push(record);
+        }
+    }
+
+    Ok(records)
+}
+
+#[test]
+fn exact_duplicates_are_grouped() {
+    let records = vec![
+        Record::with_attrs("a", None, b"ACDE"),
+        Record::with_attrs("b", None, b"ACDF"),
+        Record::with_attrs("c", None, b"ACDF"),
+        Record::with_attrs("d", None, b"ACDE"),
+        Record::with_attrs("e", None, b"ACD"),
+    ];
+
+    assert_eq!(exact_duplicates(&records), vec![vec![0, 3], vec![1, 2]]);
+
+    let collapsed = Collapsed::new(&records);
+    assert_eq!(collapsed.num_collapsed(), 2);
+    assert!(collapsed.is_collapsed(3));
+    assert_eq!(collapsed.ids(0), "d");
+    assert_eq!(collapsed.ids(4), "-");
+}
+
+#[test]
+fn substrings_are_dropped() {
+    let records = vec![
+        Record::with_attrs("a", None, b"CDE"),
+        Record::with_attrs("b", None, b"ACDEF"),
+        Record::with_attrs("c", None, b"ACDEF"),
+        Record::with_attrs("d", None, b"ACE"),
+        Record::with_attrs("e", None, b"FA"),
+    ];
+
+    let kept = drop_substrings(records, false);
+    let ids = kept.iter().map(|r| r.id()).collect::<Vec<_>>();
+    assert_eq!(ids, vec!["b", "d", "e"]);
+}
+
+#[test]
+fn gzipped_fasta_is_read_like_plain_fasta() {
+    use flate2::write::GzEncoder;
+
+    let dir = std::env::temp_dir().join(format!("align-input-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let text = b">a\nMKVL\n>b desc\nACDE\n";
+    fs::write(dir.join("plain.fa"), text).unwrap();
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(text).unwrap();
+    fs::write(dir.join("gzipped.fasta.gz"), encoder.finish().unwrap()).unwrap();
+    fs::write(dir.join("notes.txt.gz"), b"").unwrap();
+
+    let files = fasta_files(&dir, false).unwrap();
+    let read = |path: &Path| {
+        open_fasta(path)
+            .unwrap()
+            .records()
+            .map(|r| r.unwrap())
+            .collect::<Vec<_>>()
+    };
+    let records = files.iter().map(|file| read(file)).collect::<Vec<_>>();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(files.len(), 2);
+    assert_eq!(records[0], records[1]);
+    assert_eq!(records[0][1].desc(), Some("desc"));
+}
+
+#[test]
+fn relabel_table_errors_are_returned() {
+    assert!(RelabelTable::from_text("a\tb\nc\n").is_err());
+
+    let table = RelabelTable::from_text("a\tx\n\nb y\n").unwrap();
+    let records = vec![
+        Record::with_attrs("a", None, b"ACDE"),
+        Record::with_attrs("c", None, b"ACDF"),
+    ];
+    let relabeled = table.relabel(records.clone(), false).unwrap();
+    let ids = relabeled.iter().map(|r| r.id()).collect::<Vec<_>>();
+    assert_eq!(ids, vec!["x", "c"]);
+    assert!(table.relabel(records, true).is_err());
+}
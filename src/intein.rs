@@ -0,0 +1,67 @@
+//! A small model of the conserved catalytic residues of inteins, for `--intein-motifs`.
+//!
+//! The residues are given classes for `ClassScoring`, so aligning the same catalytic residue of
+//! two inteins scores a bonus.  Everything else is unclassed and scores as usual.
+
+use bio::io::fasta::Record;
+
+/// The class of residues that aren't one of the catalytic residues.
+pub const UNCLASSED: u8 = b'.';
+
+/// The N-terminal Cys, Ser, or Ala (block A), which starts the splicing.
+const NUCLEOPHILE: u8 = b'1';
+/// The Thr of block B's TxxH.
+const BLOCK_B_THR: u8 = b'2';
+/// The His of block B's TxxH.
+const BLOCK_B_HIS: u8 = b'3';
+/// The penultimate His (block G).
+const BLOCK_G_HIS: u8 = b'4';
+/// The C-terminal Asn (or Gln), which is cyclized to release the intein.
+const TERMINAL_ASN: u8 = b'5';
+
+/// The class of each residue of `seq`, assuming it runs from the intein's first residue to its
+/// last.
+///
+/// Block B is taken to be the first TxxH in the N-terminal half.  A residue that doesn't fit its
+/// motif, e.g., an intein starting with something other than C, S, or A, stays unclassed.
+pub fn motif_classes(seq: &[u8]) -> Vec<u8> {
+    let len = seq.len();
+    let mut classes = vec![UNCLASSED; len];
+    let is = |i: usize, residues: &[u8]| residues.contains(&seq[i].to_ascii_uppercase());
+
+    if len > 0 && is(0, b"CSA") {
+        classes[0] = NUCLEOPHILE;
+    }
+
+    if let Some(i) =
+        (0..(len / 2).min(len.saturating_sub(3))).find(|&i| is(i, b"T") && is(i + 3, b"H"))
+    {
+        classes[i] = BLOCK_B_THR;
+        classes[i + 3] = BLOCK_B_HIS;
+    }
+
+    if len > 1 && is(len - 1, b"NQ") {
+        classes[len - 1] = TERMINAL_ASN;
+        if is(len - 2, b"H") {
+            classes[len - 2] = BLOCK_G_HIS;
+        }
+    }
+
+    classes
+}
+
+/// The motif classes of each record, for `ClassScoring::from_classes`.
+pub fn record_classes(records: &[Record]) -> Vec<Option<Vec<u8>>> {
+    records
+        .iter()
+        .map(|record| Some(motif_classes(record.seq())))
+        .collect()
+}
+
+#[test]
+fn catalytic_residues_are_classed() {
+    assert_eq!(motif_classes(b"CAWTKLHAAAAAAAAHN"), b"1..2..3........45");
+    // No nucleophile, a TxxH that's too late for block B, and no C-terminal His.
+    assert_eq!(motif_classes(b"GAAAAATKLHAN"), b"...........5");
+    assert_eq!(motif_classes(b"C"), b"1");
+}
@@ -0,0 +1,45 @@
+//! Alignment-free comparison of sequences by their k-mer content.
+
+use std::collections::HashMap;
+
+/// Count each k-mer of `seq`.
+fn kmer_counts(seq: &[u8], k: usize) -> HashMap<&[u8], u32> {
+    let mut counts = HashMap::new();
+    for kmer in seq.windows(k) {
+        *counts.entry(kmer).or_insert(0) += 1;
+    }
+
+    counts
+}
+
+/// Cosine similarity of the k-mer count vectors of `x` and `y`.
+///
+/// This is between 0 and 1.  It is 0 if either sequence is shorter than `k`.
+pub fn cosine_similarity(x: &[u8], y: &[u8], k: usize) -> f64 {
+    let x_counts = kmer_counts(x, k);
+    let y_counts = kmer_counts(y, k);
+
+    let dot: f64 = x_counts
+        .iter()
+        .filter_map(|(kmer, &n)| y_counts.get(kmer).map(|&m| f64::from(n) * f64::from(m)))
+        .sum();
+
+    let norm =
+        |counts: &HashMap<&[u8], u32>| counts.values().map(|&n| f64::from(n).powi(2)).sum::<f64>();
+    let norms = (norm(&x_counts) * norm(&y_counts)).sqrt();
+
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+#[test]
+fn cosine_similarity_of_kmer_counts() {
+    assert_eq!(cosine_similarity(b"ACDEF", b"ACDEF", 3), 1.0);
+    assert_eq!(cosine_similarity(b"AAAA", b"CCCC", 2), 0.0);
+    assert_eq!(cosine_similarity(b"A", b"A", 2), 0.0);
+    // ACD, CDE shared out of ACD, CDE, DEF and ACD, CDE, DEW.
+    assert!((cosine_similarity(b"ACDEF", b"ACDEW", 3) - 2.0 / 3.0).abs() < 1e-12);
+}
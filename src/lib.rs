@@ -1,43 +1,824 @@
+mod best_hits;
+mod bins;
+mod cell_budget;
+mod checkpoint;
+mod classes;
 pub mod cli;
+mod cluster;
+mod composition;
+mod conservation;
+mod domains;
+mod dotplot;
+pub mod error;
+pub mod expr;
+mod gap_profile;
+mod groups;
+mod histogram;
+mod input;
+mod intein;
+mod kmer;
+mod mask;
+mod msa;
+mod output;
+mod pair_list;
+mod pid_matrix;
+mod profile;
+mod progress;
+mod ref_window;
+mod score_only;
+pub mod scoring;
+mod selftest;
+mod shard;
+mod skip_log;
+mod subst_counts;
+mod throttle;
+mod translate;
+mod ungapped;
+mod validate;
+mod verify;
+mod weights;
+mod worker_load;
 
-use crate::cli::Cli;
-use bio::alignment::pairwise::Aligner;
+use crate::best_hits::BestHits;
+use crate::bins::Bins;
+use crate::cell_budget::{CellBudget, Reservation};
+use crate::checkpoint::Checkpoint;
+use crate::classes::{mark_matches, restore_matches, ClassScoring};
+use crate::cli::{
+    AlignArgs, AlignMode, AnnotateOn, Cli, ClusterNumbering, Command, CoordBase, Direction,
+    DistanceCorrection, EdgeWeight, EmptyPid, NonstandardAa, OutputFormat, PidDenominator,
+};
+use crate::cluster::Clusters;
+use crate::conservation::Conservation;
+use crate::dotplot::{match_coords, Dotplot};
+use crate::error::Error;
+use crate::expr::ComputedColumn;
+use crate::histogram::PidHistogram;
+use crate::input::{exact_duplicates, get_records, index_records, Collapsed, InputOptions};
+use crate::output::{
+    print_alignment_info, print_to_outputs, write_buffers, write_header, write_maf_header,
+    GroupedHits, Output, Score,
+};
+use crate::pid_matrix::PidMatrix;
+use crate::profile::Profile;
+use crate::progress::Progress;
+use crate::ref_window::best_window;
+use crate::scoring::{is_standard, iupac_overlap, Matrix};
+use crate::skip_log::SkipLog;
+use crate::subst_counts::SubstCounts;
+use crate::throttle::Throttle;
+use crate::translate::{Frame, Translations};
+use crate::validate::{looks_like_iupac_nucleotide, looks_like_nucleotide};
+use crate::verify::Verifier;
+use crate::worker_load::WorkerLoad;
+use bio::alignment::pairwise::{Aligner, MatchFunc, Scoring};
 use bio::alignment::{Alignment, AlignmentMode, AlignmentOperation};
-use bio::io::fasta::{Reader, Record};
-use bio::scores::blosum62;
+use bio::io::fasta::Record;
 use crossbeam::channel;
 use itertools::Itertools;
-use std::fs::File;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs::{self, File};
 use std::io::{BufWriter, Write};
-use std::path::PathBuf;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::{self, AtomicBool, AtomicU64, AtomicUsize};
 use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+/// A pair of records to align, by their positions in the input.
+struct RecordPair {
+    /// Position in the order pairs are sent to the workers.
+    index: usize,
+    x_index: usize,
+    y_index: usize,
+    /// All of the input, shared, so sending a pair doesn't copy its sequences.
+    records: Arc<Vec<Record>>,
+    /// Position within the current block when writing stable blocks, else `None`.
+    slot: Option<usize>,
+    /// This pair's share of the --max-cells budget, given back when the pair is dropped.
+    _reservation: Option<Reservation>,
+}
+
+impl RecordPair {
+    fn x(&self) -> &Record {
+        &self.records[self.x_index]
+    }
+
+    fn y(&self) -> &Record {
+        &self.records[self.y_index]
+    }
+}
 
 // Some types to simplify things.
-type RecordPairSender = channel::Sender<(Record, Record)>;
+type RecordPairSender = channel::Sender<RecordPair>;
+/// A bin's pid and row, for `--bin-output`.
+type BinRow = (f64, Vec<u8>);
+/// The output of one pair of a stable block (a buffer per output, and its `--bin-output` row),
+/// and its slot in that block.
+type BlockResult = (usize, Vec<Vec<u8>>, Option<BinRow>);
 struct Workers {
     thread_handles: Vec<JoinHandle<()>>,
-    senders: Vec<RecordPairSender>,
+    /// The queue all the workers take their pairs from.
+    sender: RecordPairSender,
 }
 
-/// Double check the alignment assumptions!
-fn assert_global(x: &Record, y: &Record, alignment: &Alignment) {
-    assert_eq!(alignment.xstart, 0);
-    assert_eq!(alignment.xend, x.seq().len());
-    assert_eq!(alignment.ystart, 0);
-    assert_eq!(alignment.yend, y.seq().len());
+/// Returns the name of the filter that rejects a pair's result, or `None` to keep it.  See
+/// `AlignerConfig::filter`.
+pub type PairFilter = Arc<dyn Fn(&PairResult) -> Option<&'static str> + Send + Sync>;
 
-    match alignment.mode {
-        AlignmentMode::Global => (),
-        _ => panic!("should be global"),
-    };
+/// Scoring for global alignment, and which results to keep.
+///
+/// Gap penalties are negative, as rust-bio expects.  The default matches the CLI defaults.
+pub struct AlignerConfig {
+    pub gap_open: i32,
+    pub gap_extend: i32,
+    pub matrix: Matrix,
+    /// `None` keeps every result.
+    pub filter: Option<PairFilter>,
+    /// The percent identity of zero-length alignments.
+    pub empty_pid: EmptyPid,
+    /// What the percent identity divides the matches by.
+    pub pid_denominator: PidDenominator,
+}
+
+impl Default for AlignerConfig {
+    fn default() -> Self {
+        Self {
+            gap_open: -10,
+            gap_extend: -1,
+            matrix: Matrix::blosum62(),
+            filter: None,
+            empty_pid: EmptyPid::Zero,
+            pid_denominator: PidDenominator::AlnLen,
+        }
+    }
+}
+
+impl AlignerConfig {
+    /// The percent identity of an alignment `aln_len` long, with `num_matches` matches and
+    /// `num_mismatches` mismatches, of sequences `x_len` and `y_len` long.
+    fn pid(
+        &self,
+        aln_len: i32,
+        num_matches: i32,
+        num_mismatches: i32,
+        x_len: usize,
+        y_len: usize,
+    ) -> f64 {
+        let denominator =
+            self.pid_denominator
+                .denominator(aln_len, num_matches + num_mismatches, x_len, y_len);
+
+        percent_identity(denominator, num_matches, self.empty_pid)
+    }
+
+    /// Only keep the results that `keep` returns true for.  `name` says which filter rejected a
+    /// result (see `rejected_by`).
+    ///
+    /// Calling this again adds another filter, and results have to pass all of them.  The CLI's
+    /// row filters (e.g., --max-pid) are built this way.  In the CLI, only the rows that are
+    /// written are filtered; clustering and the matrix formats still see every pair.
+    pub fn filter(
+        mut self,
+        name: &'static str,
+        keep: impl Fn(&PairResult) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        let this = move |result: &PairResult| (!keep(result)).then_some(name);
+        self.filter = Some(match self.filter.take() {
+            None => Arc::new(this),
+            Some(previous) => Arc::new(move |result| previous(result).or_else(|| this(result))),
+        });
+
+        self
+    }
+
+    /// The name of the first filter that rejects `result`, or `None` if it passes them all.
+    pub fn rejected_by(&self, result: &PairResult) -> Option<&'static str> {
+        self.filter.as_ref().and_then(|filter| filter(result))
+    }
+
+    /// Does `result` pass the filters?
+    pub fn keeps(&self, result: &PairResult) -> bool {
+        self.rejected_by(result).is_none()
+    }
+}
+
+/// Globally align two sequences, returning the alignment and its percent identity.
+///
+/// This builds a new aligner each call, so it's meant for one-off pairs rather than many.
+pub fn align_pair(x: &[u8], y: &[u8], config: &AlignerConfig) -> (Alignment, f64) {
+    let score = |a: u8, b: u8| config.matrix.score(a, b);
+    let mut aligner = Aligner::new(config.gap_open, config.gap_extend, &score);
+    let alignment = aligner.global(x, y);
+
+    let pid = config.pid(
+        alignment_length(&alignment),
+        count_identities(&alignment),
+        count_mismatches(&alignment),
+        x.len(),
+        y.len(),
+    );
+
+    (alignment, pid)
+}
+
+/// The numbers for one aligned pair, for sorting and for library users.
+#[derive(Copy, Clone, Debug)]
+pub struct PairResult {
+    pub x_index: usize,
+    pub y_index: usize,
+    pub aln_len: i32,
+    pub num_matches: i32,
+    pub percent_identity: f64,
+    pub score: i32,
+}
+
+impl PairResult {
+    fn new(x_index: usize, y_index: usize, info: &AlignmentInfo) -> Self {
+        Self {
+            x_index,
+            y_index,
+            aln_len: info.aln_len,
+            num_matches: info.num_matches,
+            percent_identity: info.percent_identity,
+            score: info.score,
+        }
+    }
+}
+
+/// Globally align every pair of `records` (not including self-hits), in input order.
+///
+/// Only the results that pass `config`'s filters are returned.
+pub fn align_all(records: &[Record], config: &AlignerConfig) -> Vec<PairResult> {
+    let score = |a: u8, b: u8| config.matrix.score(a, b);
+    let mut aligner = Aligner::new(config.gap_open, config.gap_extend, &score);
+
+    records
+        .iter()
+        .enumerate()
+        .tuple_combinations()
+        .map(|((x_index, x), (y_index, y))| {
+            let alignment = aligner.global(x.seq(), y.seq());
+            let aln_len = alignment_length(&alignment);
+            let num_matches = count_identities(&alignment);
+
+            PairResult {
+                x_index,
+                y_index,
+                aln_len,
+                num_matches,
+                percent_identity: config.pid(
+                    aln_len,
+                    num_matches,
+                    count_mismatches(&alignment),
+                    x.seq().len(),
+                    y.seq().len(),
+                ),
+                score: alignment.score,
+            }
+        })
+        .filter(|result| config.keeps(result))
+        .collect()
+}
+
+/// Like `align_all`, but sorted with `compare`.  The sort is stable, so ties stay in input order.
+pub fn align_all_sorted_by<F>(
+    records: &[Record],
+    config: &AlignerConfig,
+    compare: F,
+) -> Vec<PairResult>
+where
+    F: Fn(&PairResult, &PairResult) -> Ordering,
+{
+    let mut results = align_all(records, config);
+    results.sort_by(compare);
+
+    results
+}
+
+/// Rows for the writer thread: a buffer per output (see `print_to_outputs`), and the
+/// `--bin-output` row along with the pid that picks its bin.
+struct Rows {
+    buffers: Vec<Vec<u8>>,
+    bin: Option<BinRow>,
+    /// The index of a pair whose rows have all been sent, for `--checkpoint-interval`.
+    finished: Option<usize>,
+}
+
+/// Pair results with their rendered rows, collected for `--sort-by`.
+type SortedRows = Mutex<Vec<(PairResult, Vec<Vec<u8>>)>>;
+
+/// Everything the worker threads share: scoring, output, and result collectors.
+struct Context {
+    config: AlignerConfig,
+    /// The main output, then any extra `--out` files.
+    outputs: Vec<Output>,
+    /// `None` unless also writing the rows split by pid.
+    bins: Option<Bins>,
+    opts: OutputOptions,
+    /// `None` if not clustering.
+    clusters: Option<Clusters>,
+    /// `None` unless writing best hits or reciprocal best hits.
+    best_hits: Option<BestHits>,
+    /// `None` unless writing one of the matrix formats or the guide MSA.
+    pids: Option<PidMatrix>,
+    /// Pairs with more DP cells than this are skipped.  `None` for no limit.
+    max_cells: Option<usize>,
+    /// Pairs that can't reach this pid are skipped before aligning.  `None` unless --early-abort.
+    early_abort: Option<f64>,
+    /// Where workers send their output when writing stable blocks.
+    block_results: Option<channel::Sender<BlockResult>>,
+    low_pid: LowPidCounter,
+    /// `None` unless the rows are buffered to be sorted or filtered by cluster before writing.
+    sorted: Option<SortedRows>,
+    /// `None` unless checking that alignments are symmetric.
+    verifier: Option<Verifier>,
+    /// Align a dummy pair in each worker before the real ones.
+    warm_up: bool,
+    /// `None` unless counting aligned residue pairs.  Each worker merges its counts in here.
+    subst_counts: Option<Mutex<SubstCounts>>,
+    /// `None` unless counting conservation along a reference.
+    conservation: Option<Conservation>,
+    /// `None` unless writing the matched positions of each pair.
+    dotplot: Option<Dotplot>,
+    /// `None` unless counting the pids of the written pairs.
+    histogram: Option<PidHistogram>,
+    /// `None` unless scoring by structural class or intein motifs.  Its matrix replaces `config`'s.
+    classes: Option<ClassScoring>,
+    /// Stop once a pair reaches this pid.  `None` to align everything.
+    stop_on_pid: Option<f64>,
+    /// Set by the worker that finds a pair at or above `stop_on_pid`.
+    stopped: AtomicBool,
+    /// The ids of pairs whose worker panicked.
+    failed: Mutex<Vec<(String, String)>>,
+    /// Pairs the workers have finished with, whether they worked or failed.
+    processed: AtomicUsize,
+    /// `None` unless counting the pairs and residues of each worker.
+    worker_load: Option<WorkerLoad>,
+    /// `None` unless reporting progress on stderr.
+    progress: Option<Progress>,
+    /// `None` unless only writing pairs that beat the best pid so far.
+    best_so_far: Option<BestSoFar>,
+    /// `None` unless there's a json-grouped output.
+    grouped: Option<GroupedHits>,
+    /// `None` unless the number of running workers is set by --control-file.
+    throttle: Option<Throttle>,
+    /// `None` unless logging the pairs that the filters skipped.
+    skip_log: Option<SkipLog>,
+    /// The self-score of each record.  `None` unless writing the max_score column.
+    self_scores: Option<Vec<i32>>,
+    /// The translated frames of each record.  `None` unless aligning with --translate.
+    translations: Option<Translations>,
+    /// `None` unless collapsing the rows of identical records.
+    collapsed: Option<Collapsed>,
+    /// The weight of each record.  `None` unless writing the pair_weight column.
+    weights: Option<Vec<f64>>,
+    /// The entropy of each record.  `None` unless writing the entropy columns.
+    entropies: Option<Vec<f64>>,
+    /// Tell the writer when each pair is finished, so it can write checkpoints.
+    checkpointing: bool,
+}
+
+impl Context {
+    /// The lower of the self-scores of records `i` and `j`, if they were computed.
+    fn max_score(&self, i: usize, j: usize) -> Option<i32> {
+        self.self_scores
+            .as_ref()
+            .map(|scores| scores[i].min(scores[j]))
+    }
+
+    /// The product of the weights of records `i` and `j`, if they were given.
+    fn pair_weight(&self, i: usize, j: usize) -> Option<f64> {
+        self.weights.as_ref().map(|weights| weights[i] * weights[j])
+    }
+
+    /// The entropies of records `i` and `j`, if they were computed.
+    fn entropies(&self, i: usize, j: usize) -> Option<(f64, f64)> {
+        self.entropies
+            .as_ref()
+            .map(|entropies| (entropies[i], entropies[j]))
+    }
+
+    /// The ids that records `i` and `j` stand for, if collapsing identical records.
+    fn collapsed_ids(&self, i: usize, j: usize) -> Option<(String, String)> {
+        self.collapsed
+            .as_ref()
+            .map(|collapsed| (collapsed.ids(i).to_string(), collapsed.ids(j).to_string()))
+    }
+}
+
+/// Counts aligned pairs with a suspiciously low pid, to catch misconfigured scoring.
+///
+/// With bad gap penalties (e.g., --gap-open 0), global alignments smear into mostly gaps and
+/// nearly every pair gets a pid close to 0.  Even unrelated proteins usually do better than that.
+#[derive(Default)]
+struct LowPidCounter {
+    aligned: AtomicUsize,
+    low: AtomicUsize,
+}
+
+impl LowPidCounter {
+    /// Pids below this are suspicious.
+    const FLOOR: f64 = 0.05;
+    /// Warn if more than this fraction of pairs are below the floor.
+    const MAX_FRACTION: f64 = 0.9;
+    /// Don't bother warning with fewer pairs than this.
+    const MIN_PAIRS: usize = 10;
+
+    fn add(&self, percent_identity: f64) {
+        self.aligned.fetch_add(1, atomic::Ordering::Relaxed);
+        if percent_identity < Self::FLOOR {
+            self.low.fetch_add(1, atomic::Ordering::Relaxed);
+        }
+    }
+
+    fn warn_if_degenerate(&self) {
+        let aligned = self.aligned.load(atomic::Ordering::Relaxed);
+        let low = self.low.load(atomic::Ordering::Relaxed);
+
+        if aligned >= Self::MIN_PAIRS && low as f64 / aligned as f64 > Self::MAX_FRACTION {
+            eprintln!(
+                "warning: {low} of {aligned} aligned pairs have pid below {}; check that the gap penalties and scoring matrix make sense",
+                Self::FLOOR
+            );
+        }
+    }
+}
+
+/// The highest pid seen so far, shared by the workers for `--stream-best`.
+#[derive(Default)]
+struct BestSoFar {
+    /// The bits of the f64 pid, starting from 0.0.
+    pid: AtomicU64,
+}
+
+impl BestSoFar {
+    /// Is `percent_identity` higher than any before it?  If so, it's the new best.
+    fn beats(&self, percent_identity: f64) -> bool {
+        self.pid
+            .fetch_update(
+                atomic::Ordering::Relaxed,
+                atomic::Ordering::Relaxed,
+                |best| {
+                    (percent_identity > f64::from_bits(best)).then(|| percent_identity.to_bits())
+                },
+            )
+            .is_ok()
+    }
+}
+
+/// The coordinator's side of writing stable blocks.
+struct StableBlocks<'a> {
+    size: usize,
+    results: channel::Receiver<BlockResult>,
+    context: &'a Context,
+    /// The self-hits to write in among the pairs, if they aren't aligned.
+    self_hits: Option<SelfHits<'a>>,
+}
+
+impl StableBlocks<'_> {
+    /// Wait for all the pairs of the current `block`, then write them in order.
+    ///
+    /// Each record's self-hit goes just before the first pair it's x of (or after the last pair
+    /// of an earlier x, if it has none), so the rows are in the order of the pairs' x then y.
+    fn write_block(&mut self, block: &[PairRef]) {
+        let mut rows = vec![(Vec::new(), None); block.len()];
+        for _ in 0..block.len() {
+            let (slot, buffers, bin) = self.results.recv().unwrap();
+            rows[slot] = (buffers, bin);
+        }
+
+        for ((_, ((x_index, _), _)), (buffers, bin)) in block.iter().zip(rows) {
+            if let Some(self_hits) = &mut self.self_hits {
+                self_hits.write_through(x_index + 1);
+            }
+            write_buffers(&self.context.outputs, &buffers);
+            if let (Some(bins), Some((pid, buf))) = (&self.context.bins, bin) {
+                bins.writer(pid).lock().unwrap().write_all(&buf).unwrap();
+            }
+        }
+    }
+
+    /// Write the self-hits that come after every pair.
+    fn finish(&mut self) {
+        if let Some(self_hits) = &mut self.self_hits {
+            self_hits.write_through(usize::MAX);
+        }
+    }
+}
+
+/// The self-hits of records `next..end` that the stable blocks haven't written yet.
+struct SelfHits<'a> {
+    records: &'a [Record],
+    context: &'a Context,
+    next: usize,
+    end: usize,
+}
+
+impl SelfHits<'_> {
+    /// Write the self-hits of the records before `index`.
+    fn write_through(&mut self, index: usize) {
+        let end = index.min(self.end);
+        if self.next < end {
+            write_self_hits(self.records, self.next..end, self.context);
+            self.next = end;
+        }
+    }
+}
+
+/// Options that control which optional columns are written.
+#[derive(Copy, Clone)]
+struct OutputOptions {
+    show_aln_ops: bool,
+    dual_ops: bool,
+    /// The fraction of nonstandard columns over which a pair is low confidence.  `None` if the
+    /// low_confidence column should not be shown.
+    low_confidence: Option<f64>,
+    /// Write the pair_weight column.
+    pair_weight: bool,
+    /// Write the x_entropy and y_entropy columns.
+    entropy: bool,
+    /// Keep the ops even if they aren't shown, because an output needs the gapped sequences.
+    keep_ops: bool,
+    /// `None` if coordinates should not be shown.
+    coord_base: Option<CoordBase>,
+    extra_pid: bool,
+    score_norm: bool,
+    max_score: bool,
+    edit_distance: bool,
+    internal_aln_len: bool,
+    pid_trimmed: bool,
+    empty_pid: EmptyPid,
+    max_gap_len: Option<usize>,
+    mask_low_complexity: bool,
+    gap_stats: bool,
+    len_diff: bool,
+    edge_weight: EdgeWeight,
+    min_edge_weight: Option<f64>,
+    /// The --compute columns.  Leaked so the options stay `Copy`, since they last the whole run.
+    computed: &'static [ComputedColumn],
+    /// The tolerance for the full_length column.  `None` if it should not be shown.
+    full_length: Option<f64>,
+    mark_self: bool,
+    /// y was reversed before aligning.
+    reversed: bool,
+    /// The sequences were translated before aligning.
+    translate: bool,
+    /// Write the x_collapsed and y_collapsed columns.
+    collapse_identical: bool,
+    /// Write 1 - pid rather than pid.
+    distance: bool,
+    distance_correction: DistanceCorrection,
+    format: OutputFormat,
+    /// Decimal places for non-integer columns.
+    precision: usize,
+    direction: Direction,
+    /// Compare pairs by k-mer content with this k instead of aligning them.
+    kmer_size: Option<usize>,
+    /// Find the best ungapped diagonal instead of doing a gapped alignment.
+    ungapped: bool,
+    /// Align in linear memory, without a traceback.
+    score_only: bool,
+    /// Which sequence to write the per-residue annotation for, if any.
+    annotate_on: Option<AnnotateOn>,
+    /// Count Xclip and Yclip operations in the alignment length.
+    clip_counts_as_length: bool,
+    /// Allow clipping the ends of y.
+    repeat_aware: bool,
+    mode: AlignMode,
+    /// Count IUPAC ambiguity codes that could be the same base as identities.
+    iupac: bool,
+    /// What the scores were multiplied by while aligning.
+    score_scale: i32,
+}
+
+impl OutputOptions {
+    fn new(args: &AlignArgs) -> Self {
+        Self {
+            show_aln_ops: args.show_aln_ops,
+            dual_ops: args.dual_ops,
+            low_confidence: args.low_confidence,
+            pair_weight: args.weights.is_some(),
+            entropy: args.entropy,
+            keep_ops: false,
+            coord_base: if args.show_coords {
+                Some(args.coord_base)
+            } else {
+                None
+            },
+            extra_pid: args.extra_pid,
+            score_norm: args.score_norm,
+            max_score: args.max_score,
+            edit_distance: args.edit_distance,
+            internal_aln_len: args.internal_aln_len,
+            pid_trimmed: args.pid_trimmed,
+            empty_pid: args.empty_pid,
+            max_gap_len: args.max_gap_len,
+            mask_low_complexity: args.mask_low_complexity,
+            gap_stats: args.gap_stats,
+            len_diff: args.len_diff,
+            full_length: args.full_length.then_some(args.full_length_tol),
+            computed: Vec::leak(args.compute.clone()),
+            edge_weight: args.edge_weight,
+            min_edge_weight: args.min_edge_weight,
+            mark_self: args.mark_self,
+            reversed: args.reversed,
+            translate: args.translate,
+            collapse_identical: args.collapse_identical_output,
+            distance: args.distance || args.distance_correction != DistanceCorrection::None,
+            distance_correction: args.distance_correction,
+            format: args.format,
+            precision: args.precision,
+            direction: args.direction(),
+            kmer_size: args.alignment_free.then(|| args.kmer_size()),
+            ungapped: args.ungapped,
+            score_only: args.score_only,
+            annotate_on: args.annotate_on,
+            clip_counts_as_length: args.clip_counts_as_length,
+            repeat_aware: args.repeat_aware,
+            mode: args.mode,
+            iupac: args.iupac,
+            score_scale: args.score_scale(),
+        }
+    }
+
+    /// A raw score, to write.
+    fn score(self, score: i32) -> Score {
+        Score {
+            score,
+            scale: self.score_scale,
+        }
+    }
+}
+
+/// Start and end positions of an alignment on both sequences.
+///
+/// These are always stored 0-based and half-open, like rust-bio's `Alignment`.  They are only
+/// converted to the user's chosen convention when printed.
+#[derive(Copy, Clone)]
+struct Coords {
+    xstart: usize,
+    xend: usize,
+    ystart: usize,
+    yend: usize,
+}
+
+impl Coords {
+    fn from_alignment(alignment: &Alignment) -> Self {
+        Self {
+            xstart: alignment.xstart,
+            xend: alignment.xend,
+            ystart: alignment.ystart,
+            yend: alignment.yend,
+        }
+    }
+
+    /// Coordinates from the first to the last column where neither sequence has a gap.
+    ///
+    /// Unlike `from_alignment`, this leaves out terminal gaps and clips.  An alignment without
+    /// any such columns gets empty coordinates at the start.
+    fn internal(alignment: &Alignment) -> Self {
+        let (mut i, mut j) = (0, 0);
+        let mut span: Option<Self> = None;
+
+        for op in &alignment.operations {
+            match op {
+                AlignmentOperation::Match | AlignmentOperation::Subst => {
+                    let span = span.get_or_insert(Self {
+                        xstart: i,
+                        xend: i,
+                        ystart: j,
+                        yend: j,
+                    });
+                    i += 1;
+                    j += 1;
+                    span.xend = i;
+                    span.yend = j;
+                }
+                AlignmentOperation::Ins => i += 1,
+                AlignmentOperation::Del => j += 1,
+                AlignmentOperation::Xclip(n) => i += n,
+                AlignmentOperation::Yclip(n) => j += n,
+            }
+        }
+
+        span.unwrap_or(Self::full_length(0, 0))
+    }
+
+    /// Coordinates of an alignment covering both sequences end to end.
+    fn full_length(x_len: usize, y_len: usize) -> Self {
+        Self {
+            xstart: 0,
+            xend: x_len,
+            ystart: 0,
+            yend: y_len,
+        }
+    }
+
+    /// Do these coordinates cover all of both sequences, to within `tol` of their lengths?
+    fn cover(&self, x_len: usize, y_len: usize, tol: f64) -> bool {
+        let covers =
+            |start: usize, end: usize, len: usize| (end - start) as f64 >= (1.0 - tol) * len as f64;
+
+        covers(self.xstart, self.xend, x_len) && covers(self.ystart, self.yend, y_len)
+    }
+
+    /// Swap x and y, for the reciprocal output line.
+    fn swap(self) -> Self {
+        Self {
+            xstart: self.ystart,
+            xend: self.yend,
+            ystart: self.xstart,
+            yend: self.xend,
+        }
+    }
+
+    fn show(&self, coord_base: CoordBase) -> String {
+        let (xstart, xend) = coord_base.convert(self.xstart, self.xend);
+        let (ystart, yend) = coord_base.convert(self.ystart, self.yend);
+
+        format!("{xstart}\t{xend}\t{ystart}\t{yend}")
+    }
+}
+
+/// Double check the alignment assumptions of the --mode!
+fn assert_mode(x: &Record, y: &Record, alignment: &Alignment, mode: AlignMode) {
+    let (xlen, ylen) = (x.seq().len(), y.seq().len());
+    assert!(alignment.xstart <= alignment.xend && alignment.xend <= xlen);
+    assert!(alignment.ystart <= alignment.yend && alignment.yend <= ylen);
+
+    match mode {
+        AlignMode::Global => {
+            assert_eq!(alignment.xstart, 0);
+            assert_eq!(alignment.xend, xlen);
+            assert_eq!(alignment.ystart, 0);
+            assert_eq!(alignment.yend, ylen);
+
+            match alignment.mode {
+                AlignmentMode::Global => (),
+                _ => panic!("should be global"),
+            };
+        }
+        AlignMode::Semiglobal => {
+            assert_eq!(alignment.xstart, 0);
+            assert_eq!(alignment.xend, xlen);
+        }
+        AlignMode::Local => (),
+    }
+}
+
+/// Align `x` and `y` by `mode`, with an aligner whose clip penalties were set for it (see
+/// `set_up_workers`).
+///
+/// Local and semiglobal alignments use `custom` rather than rust-bio's `local` and
+/// `semiglobal`, since those drop the clips from the ops, and the columns that walk the ops
+/// need them to find their place in each sequence.
+fn align_by_mode<F: MatchFunc>(
+    aligner: &mut Aligner<F>,
+    x: &[u8],
+    y: &[u8],
+    mode: AlignMode,
+) -> Alignment {
+    match mode {
+        AlignMode::Global => aligner.global(x, y),
+        AlignMode::Local | AlignMode::Semiglobal => aligner.custom(x, y),
+    }
+}
+
+/// The ops of `seq` aligned to itself, where masked X's are mismatches if masking.
+fn show_self_alignment_ops(seq: &[u8], mask_low_complexity: bool) -> String {
+    seq.iter()
+        .map(|&c| {
+            if mask_low_complexity && c == b'X' {
+                'S'
+            } else {
+                'M'
+            }
+        })
+        .collect()
 }
 
-fn show_self_alignment_ops(len: usize) -> String {
-    "M".repeat(len)
+/// The ops from x's side and from y's side (see --dual-ops).
+pub fn show_dual_ops(alignment: &Alignment) -> (String, String) {
+    let columns = alignment.operations.iter().flat_map(|op| {
+        let (x, y, n) = match op {
+            AlignmentOperation::Match => ('M', 'M', 1),
+            AlignmentOperation::Subst => ('S', 'S', 1),
+            AlignmentOperation::Ins => ('I', '-', 1),
+            AlignmentOperation::Del => ('-', 'I', 1),
+            AlignmentOperation::Xclip(size) => ('C', '-', *size),
+            AlignmentOperation::Yclip(size) => ('-', 'C', *size),
+        };
+        std::iter::repeat_n((x, y), n)
+    });
+
+    columns.unzip()
 }
 
-fn show_alignment_ops(alignment: &Alignment) -> String {
+pub fn show_alignment_ops(alignment: &Alignment) -> String {
     alignment
         .operations
         .iter()
@@ -53,7 +834,7 @@ fn show_alignment_ops(alignment: &Alignment) -> String {
 }
 
 /// Count identities/matches in the alignment.
-fn count_identities(alignment: &Alignment) -> i32 {
+pub fn count_identities(alignment: &Alignment) -> i32 {
     let n = alignment
         .operations
         .iter()
@@ -66,198 +847,2648 @@ fn count_identities(alignment: &Alignment) -> i32 {
     i32::try_from(n).unwrap()
 }
 
-/// The length of the alignment is the number of alignment operations.
-fn alignment_length(alignment: &Alignment) -> i32 {
-    let len = alignment.operations.len();
+/// The highest pid that a global alignment of `x` and `y` could have, for --early-abort.
+///
+/// Each match uses up a residue of both sequences, and the alignment is at least as long as the
+/// longer one.  Two empty sequences give NaN, which never counts as too low.
+fn max_possible_pid(x: &[u8], y: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    x.iter().for_each(|&a| counts[usize::from(a)] += 1);
+    let shared = y
+        .iter()
+        .filter(|&&b| {
+            let count = &mut counts[usize::from(b)];
+            let available = *count > 0;
+            *count = count.saturating_sub(1);
+            available
+        })
+        .count();
 
-    // This should never fail as inteins are short.
-    i32::try_from(len).unwrap()
+    shared as f64 / x.len().max(y.len()) as f64
 }
 
-/// Percent identity is the number of matches divided by the alignment length.
-fn percent_identity(aln_len: i32, num_matches: i32) -> f64 {
-    f64::from(num_matches) / f64::from(aln_len)
+/// The fraction of the alignment length made up of columns with a nonstandard residue in `x` or
+/// `y` (see --low-confidence).
+fn nonstandard_fraction(x: &[u8], y: &[u8], alignment: &Alignment) -> f64 {
+    let (mut i, mut j) = (0, 0);
+    let mut nonstandard = 0;
+
+    for op in &alignment.operations {
+        match op {
+            AlignmentOperation::Match | AlignmentOperation::Subst => {
+                if !is_standard(x[i]) || !is_standard(y[j]) {
+                    nonstandard += 1;
+                }
+                i += 1;
+                j += 1;
+            }
+            AlignmentOperation::Ins => i += 1,
+            AlignmentOperation::Del => j += 1,
+            AlignmentOperation::Xclip(n) => i += n,
+            AlignmentOperation::Yclip(n) => j += n,
+        }
+    }
+
+    match alignment_length(alignment) {
+        0 => 0.0,
+        aln_len => f64::from(nonstandard) / f64::from(aln_len),
+    }
 }
 
-/// Print one line with info for alignment.
-fn print_alignment_info_line(
-    out: &mut BufWriter<File>,
-    x: &Record,
-    y: &Record,
-    aln_len: i32,
-    num_matches: i32,
-    percent_identity: f64,
-    aln_ops: &Option<String>,
-) {
-    let x_name = x.id();
-    let y_name = y.id();
+/// Count mismatches (substitutions) in the alignment.
+pub fn count_mismatches(alignment: &Alignment) -> i32 {
+    let n = alignment
+        .operations
+        .iter()
+        .filter(|op| **op == AlignmentOperation::Subst)
+        .count();
 
-    let x_len = x.seq().len();
-    let y_len = y.seq().len();
+    i32::try_from(n).unwrap()
+}
 
-    let aln_ops = match aln_ops {
-        None => "".to_string(),
-        Some(ops) => format!("\t{ops}"),
-    };
+/// Count gap openings, i.e., runs of consecutive insertions or deletions.
+pub fn count_gap_opens(alignment: &Alignment) -> i32 {
+    let n = alignment
+        .operations
+        .iter()
+        .dedup()
+        .filter(|op| matches!(op, AlignmentOperation::Ins | AlignmentOperation::Del))
+        .count();
 
-    writeln!(
-        out,
-        "{x_name}\t{y_name}\t{x_len}\t{y_len}\t{aln_len}\t{num_matches}\t{percent_identity}{aln_ops}"
-    )
-    .unwrap();
+    i32::try_from(n).unwrap()
 }
 
-/// Print the tab-separated results of the alignment.
-fn print_alignment_info(
-    out: &Mutex<BufWriter<File>>,
-    x: &Record,
-    y: &Record,
-    alignment: &Alignment,
-    show_aln_ops: bool,
-) {
-    let aln_len = alignment_length(alignment);
-    let num_matches = count_identities(alignment);
+/// Count runs of aligned (Match or Subst) columns, split by gaps and clips.
+fn count_segments(alignment: &Alignment) -> i32 {
+    let n = alignment
+        .operations
+        .iter()
+        .map(|op| matches!(op, AlignmentOperation::Match | AlignmentOperation::Subst))
+        .dedup()
+        .filter(|&aligned| aligned)
+        .count();
 
-    let percent_identity = percent_identity(aln_len, num_matches);
+    i32::try_from(n).unwrap()
+}
 
-    let aln_ops = if show_aln_ops {
-        Some(show_alignment_ops(alignment))
-    } else {
-        None
-    };
-
-    let stdout = &mut *(out.lock().unwrap());
-    print_alignment_info_line(
-        stdout,
-        x,
-        y,
-        aln_len,
-        num_matches,
-        percent_identity,
-        &aln_ops,
-    );
-    print_alignment_info_line(
-        stdout,
-        y,
-        x,
-        aln_len,
-        num_matches,
-        percent_identity,
-        &aln_ops,
-    );
+/// The lengths of the runs of insertions or deletions, in alignment order.
+pub fn gap_lengths(alignment: &Alignment) -> Vec<usize> {
+    alignment
+        .operations
+        .iter()
+        .dedup_with_count()
+        .filter(|(_, op)| matches!(op, AlignmentOperation::Ins | AlignmentOperation::Del))
+        .map(|(n, _)| n)
+        .collect()
 }
 
-fn get_records(path: PathBuf) -> Vec<Record> {
-    let file = File::open(path).unwrap();
-    let reader = Reader::new(file);
+/// The length of the longest run of insertions or deletions.
+fn longest_gap(alignment: &Alignment) -> usize {
+    gap_lengths(alignment).into_iter().max().unwrap_or(0)
+}
 
-    reader
-        .records()
-        .map(|x| {
-            let x = x.unwrap();
-            // Some of the inteins have lowercase AA residues.  This breaks the alignment.
-            let uppercase_seq = x.seq().to_ascii_uppercase();
-            Record::with_attrs(x.id(), x.desc(), uppercase_seq.as_slice())
-        })
-        .collect::<Vec<Record>>()
+/// The mean and max gap lengths of an alignment, for `--gap-stats`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct GapStats {
+    /// 0 if there are no gaps.
+    mean: f64,
+    max: usize,
 }
 
-/// Set up the worker threads and channels.
-fn set_up_workers(
-    num_threads: usize,
-    gap_open: i32,
-    gap_extend: i32,
-    out: Arc<Mutex<BufWriter<File>>>,
-    show_aln_ops: bool,
-) -> Workers {
-    let mut thread_handles = Vec::with_capacity(num_threads);
-    let mut senders = Vec::with_capacity(num_threads);
+impl GapStats {
+    fn from_alignment(alignment: &Alignment) -> Self {
+        let lengths = gap_lengths(alignment);
+        if lengths.is_empty() {
+            return Self::default();
+        }
 
-    (0..num_threads).for_each(|_| {
-        let (s, r) = channel::bounded::<(Record, Record)>(256);
+        Self {
+            mean: lengths.iter().sum::<usize>() as f64 / lengths.len() as f64,
+            max: lengths.into_iter().max().unwrap_or(0),
+        }
+    }
+}
 
-        let out = out.clone();
-        let handle = thread::spawn(move || {
-            let mut aligner = Aligner::new(gap_open, gap_extend, &blosum62);
-            for (x, y) in r {
-                let alignment = aligner.global(x.seq(), y.seq());
-                assert_global(&x, &y, &alignment);
-                print_alignment_info(&out, &x, &y, &alignment, show_aln_ops);
-            }
-        });
+/// How a pair did against `--max-gap-len`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum GapLimit {
+    /// The alignment's gaps already fit.
+    Ok,
+    /// A realignment with a steeper gap extend penalty fit.
+    Realigned,
+    /// Even the steepest realignment has a longer gap.
+    Exceeded,
+}
 
-        senders.push(s);
-        thread_handles.push(handle);
-    });
+impl GapLimit {
+    fn as_str(self) -> &'static str {
+        match self {
+            GapLimit::Ok => "ok",
+            GapLimit::Realigned => "realigned",
+            GapLimit::Exceeded => "exceeded",
+        }
+    }
+}
 
-    Workers {
-        thread_handles,
-        senders,
+/// Realign x and y with steeper gap extend penalties until no gap is longer than `max_gap_len`.
+fn fit_gaps(
+    x: &[u8],
+    y: &[u8],
+    config: &AlignerConfig,
+    max_gap_len: usize,
+    alignment: Alignment,
+) -> (Alignment, GapLimit) {
+    if longest_gap(&alignment) <= max_gap_len {
+        return (alignment, GapLimit::Ok);
+    }
+
+    let score = |a: u8, b: u8| config.matrix.score(a, b);
+    let mut alignment = alignment;
+    for factor in [2, 4, 8, 16] {
+        let mut aligner = Aligner::new(config.gap_open, config.gap_extend * factor, &score);
+        alignment = aligner.global(x, y);
+        if longest_gap(&alignment) <= max_gap_len {
+            return (alignment, GapLimit::Realigned);
+        }
     }
+
+    (alignment, GapLimit::Exceeded)
 }
 
-/// Self-hits don't need alignment, so write out the equal sequence alignment info.
-fn write_self_hits(records: &[Record], out: Arc<Mutex<BufWriter<File>>>, show_aln_ops: bool) {
-    let mut out = out.lock().unwrap();
-    records.iter().for_each(|r| {
-        // Safe because inteins are short.
-        let len = i32::try_from(r.seq().len()).unwrap();
+/// Count edits (substitutions, insertions, and deletions) in the alignment.
+pub fn count_edits(alignment: &Alignment) -> i32 {
+    let n = alignment
+        .operations
+        .iter()
+        .filter(|op| {
+            matches!(
+                op,
+                AlignmentOperation::Subst | AlignmentOperation::Ins | AlignmentOperation::Del
+            )
+        })
+        .count();
 
-        let aln_ops = if show_aln_ops {
-            Some(show_self_alignment_ops(r.seq().len()))
-        } else {
-            None
-        };
+    i32::try_from(n).unwrap()
+}
 
-        print_alignment_info_line(&mut out, r, r, len, len, 1.0, &aln_ops);
-    });
+/// The length of the alignment is the number of alignment operations, not counting clips.
+pub fn alignment_length(alignment: &Alignment) -> i32 {
+    let len = alignment
+        .operations
+        .iter()
+        .filter(|op| {
+            !matches!(
+                op,
+                AlignmentOperation::Xclip(_) | AlignmentOperation::Yclip(_)
+            )
+        })
+        .count();
+
+    // This should never fail as inteins are short.
+    i32::try_from(len).unwrap()
 }
 
-/// Align records
-///
-/// The worker threads will handle file output.
+/// The number of alignment operations, including clips (see `--clip-counts-as-length`).
+fn alignment_length_with_clips(alignment: &Alignment) -> i32 {
+    i32::try_from(alignment.operations.len()).unwrap()
+}
+
+/// The alignment length from the first to the last column where neither sequence has a gap.
 ///
-/// The senders will drop at the end of this function, so you don't have to manually close them.
-fn align_records(records: Vec<Record>, senders: Vec<RecordPairSender>, num_threads: usize) {
-    records
-        .iter()
-        .tuple_combinations::<(_, _)>()
-        .enumerate()
-        .for_each(|(i, (x, y))| {
-            let tx = &senders[i % num_threads];
+/// I.e., the leading and trailing runs of insertions and deletions are left out.
+pub fn internal_alignment_length(alignment: &Alignment) -> i32 {
+    let ops = &alignment.operations;
+    let is_aligned = |op: &AlignmentOperation| {
+        matches!(op, AlignmentOperation::Match | AlignmentOperation::Subst)
+    };
 
-            tx.send((x.clone(), y.clone())).unwrap();
-        });
+    let len = match (
+        ops.iter().position(is_aligned),
+        ops.iter().rposition(is_aligned),
+    ) {
+        (Some(first), Some(last)) => ops[first..=last]
+            .iter()
+            .filter(|op| {
+                !matches!(
+                    op,
+                    AlignmentOperation::Xclip(_) | AlignmentOperation::Yclip(_)
+                )
+            })
+            .count(),
+        _ => 0,
+    };
+
+    i32::try_from(len).unwrap()
 }
 
-fn write_header(out: &Mutex<BufWriter<File>>) {
-    let mut out = out.lock().unwrap();
-    writeln!(&mut out, "x\ty\txlen\tylen\talnlen\tmatches\tpid").unwrap();
+/// Percent identity is the number of matches divided by the alignment length, or by another
+/// denominator (see `PidDenominator`).
+pub fn percent_identity(aln_len: i32, num_matches: i32, empty_pid: EmptyPid) -> f64 {
+    if aln_len == 0 {
+        empty_pid.pid()
+    } else {
+        f64::from(num_matches) / f64::from(aln_len)
+    }
 }
 
-pub fn run(cli: Cli) {
-    let out = File::create(cli.out_file.clone()).unwrap();
-    let out = Arc::new(Mutex::new(BufWriter::new(out)));
+/// The `--annotate-on` strings of an alignment, one character per residue of x and of y.
+///
+/// Each residue is `.` if it's aligned to the same residue, `x` if it's aligned to a different
+/// one, or `-` if it's aligned to a gap.
+#[derive(Clone)]
+struct Annotations {
+    x: String,
+    y: String,
+}
 
-    write_header(&out);
+impl Annotations {
+    fn from_alignment(alignment: &Alignment) -> Self {
+        let mut x = String::with_capacity(alignment.xlen);
+        let mut y = String::with_capacity(alignment.ylen);
 
-    let records = get_records(cli.in_file.clone());
+        for op in &alignment.operations {
+            match op {
+                AlignmentOperation::Match => {
+                    x.push('.');
+                    y.push('.');
+                }
+                AlignmentOperation::Subst => {
+                    x.push('x');
+                    y.push('x');
+                }
+                AlignmentOperation::Ins => x.push('-'),
+                AlignmentOperation::Del => y.push('-'),
+                AlignmentOperation::Xclip(n) => x.extend(std::iter::repeat_n('-', *n)),
+                AlignmentOperation::Yclip(n) => y.extend(std::iter::repeat_n('-', *n)),
+            }
+        }
 
-    let num_threads = cli.threads();
-    let Workers {
-        thread_handles,
-        senders,
-    } = set_up_workers(
-        num_threads,
-        cli.gap_open(),
-        cli.gap_extend(),
-        out.clone(),
-        cli.show_aln_ops,
-    );
+        Self { x, y }
+    }
+
+    fn identical(len: usize) -> Self {
+        Self {
+            x: ".".repeat(len),
+            y: ".".repeat(len),
+        }
+    }
 
-    write_self_hits(&records, out, cli.show_aln_ops);
-    align_records(records, senders, num_threads);
+    /// The annotation of the sequence `--annotate-on` picks.
+    fn on(&self, annotate_on: AnnotateOn) -> &str {
+        match annotate_on {
+            AnnotateOn::X => &self.x,
+            AnnotateOn::Y => &self.y,
+        }
+    }
+}
 
-    // Wait for the threads to finish working.
-    thread_handles.into_iter().for_each(|t| t.join().unwrap());
+/// The values reported on an output line, other than the record names and lengths.
+#[derive(Clone)]
+struct AlignmentInfo {
+    aln_len: i32,
+    num_matches: i32,
+    percent_identity: f64,
+    score: i32,
+    num_mismatches: i32,
+    num_gap_opens: i32,
+    edit_distance: i32,
+    /// The alignment length without terminal gaps.
+    internal_aln_len: i32,
+    coords: Coords,
+    /// The coords without terminal gaps, for the bed format.
+    internal_coords: Coords,
+    /// `None` if the alignment operations should not be shown.
+    aln_ops: Option<String>,
+    /// `None` unless writing the annotation column.
+    annotations: Option<Annotations>,
+    /// The lower of the two self-scores.  `None` unless writing the max_score column.
+    max_score: Option<i32>,
+    /// Runs of aligned columns.  `None` unless aligning with --repeat-aware.
+    segments: Option<i32>,
+    /// `None` unless aligning with --max-gap-len.
+    gap_limit: Option<GapLimit>,
+    /// `None` unless writing the gap stats columns.
+    gap_stats: Option<GapStats>,
+    /// `None` unless writing the full_length column.
+    full_length: Option<bool>,
+    /// The frames of x and y.  `None` unless aligning with --translate.
+    frames: Option<(Frame, Frame)>,
+    /// The ids that x and y stand for.  `None` unless writing the collapsed columns.
+    collapsed: Option<(String, String)>,
+    /// The ops from x's side and from y's.  `None` unless writing them.
+    dual_ops: Option<(String, String)>,
+    /// `None` unless writing the low_confidence column.
+    low_confidence: Option<bool>,
+    /// The product of the two records' weights.  `None` unless writing the pair_weight column.
+    pair_weight: Option<f64>,
+    /// The entropies of x and y.  `None` unless writing them.
+    entropy: Option<(f64, f64)>,
+}
+
+impl AlignmentInfo {
+    /// The info as seen from y, for the reciprocal output line.
+    fn swapped(&self) -> Self {
+        Self {
+            coords: self.coords.swap(),
+            internal_coords: self.internal_coords.swap(),
+            frames: self.frames.map(|(x_frame, y_frame)| (y_frame, x_frame)),
+            collapsed: self.collapsed.as_ref().map(|(x, y)| (y.clone(), x.clone())),
+            entropy: self.entropy.map(|(x, y)| (y, x)),
+            dual_ops: self.dual_ops.as_ref().map(|(x, y)| (y.clone(), x.clone())),
+            annotations: self.annotations.as_ref().map(|a| Annotations {
+                x: a.y.clone(),
+                y: a.x.clone(),
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// Whether `swapped` changes more than the coords.
+    fn has_sided_columns(&self) -> bool {
+        self.annotations.is_some()
+            || self.frames.is_some()
+            || self.collapsed.is_some()
+            || self.entropy.is_some()
+            || self.dual_ops.is_some()
+    }
+
+    fn new(alignment: &Alignment, config: &AlignerConfig, opts: OutputOptions) -> Self {
+        let aln_len = if opts.clip_counts_as_length {
+            alignment_length_with_clips(alignment)
+        } else {
+            alignment_length(alignment)
+        };
+        let num_matches = count_identities(alignment);
+        let num_mismatches = count_mismatches(alignment);
+
+        let percent_identity = config.pid(
+            aln_len,
+            num_matches,
+            num_mismatches,
+            alignment.xlen,
+            alignment.ylen,
+        );
+
+        let aln_ops = if opts.show_aln_ops || opts.keep_ops {
+            Some(show_alignment_ops(alignment))
+        } else {
+            None
+        };
+
+        Self {
+            aln_len,
+            num_matches,
+            percent_identity,
+            score: alignment.score,
+            num_mismatches,
+            num_gap_opens: count_gap_opens(alignment),
+            edit_distance: count_edits(alignment),
+            internal_aln_len: internal_alignment_length(alignment),
+            coords: Coords::from_alignment(alignment),
+            internal_coords: Coords::internal(alignment),
+            aln_ops,
+            annotations: opts
+                .annotate_on
+                .map(|_| Annotations::from_alignment(alignment)),
+            max_score: None,
+            segments: opts.repeat_aware.then(|| count_segments(alignment)),
+            gap_limit: None,
+            gap_stats: opts.gap_stats.then(|| GapStats::from_alignment(alignment)),
+            full_length: opts
+                .full_length
+                .map(|tol| Coords::internal(alignment).cover(alignment.xlen, alignment.ylen, tol)),
+            frames: None,
+            collapsed: None,
+            dual_ops: opts.dual_ops.then(|| show_dual_ops(alignment)),
+            low_confidence: None,
+            pair_weight: None,
+            entropy: None,
+        }
+    }
+
+    /// Info for two copies of `seq`, which align end to end with all matches.
+    fn identical(seq: &[u8], config: &AlignerConfig, opts: OutputOptions) -> Self {
+        let len = seq.len();
+        // Safe because inteins are short.
+        let aln_len = i32::try_from(len).unwrap();
+        // X vs. X isn't an identity when masking (see --mask-low-complexity).
+        let num_mismatches = if opts.mask_low_complexity {
+            seq.iter().filter(|&&c| c == b'X').count() as i32
+        } else {
+            0
+        };
+        let num_matches = aln_len - num_mismatches;
+
+        let aln_ops = if opts.show_aln_ops || opts.keep_ops {
+            Some(show_self_alignment_ops(seq, opts.mask_low_complexity))
+        } else {
+            None
+        };
+
+        Self {
+            aln_len,
+            num_matches,
+            percent_identity: config.pid(aln_len, num_matches, num_mismatches, len, len),
+            score: self_score(seq, &config.matrix),
+            num_mismatches,
+            num_gap_opens: 0,
+            edit_distance: num_mismatches,
+            internal_aln_len: aln_len,
+            coords: Coords::full_length(len, len),
+            internal_coords: Coords::full_length(len, len),
+            aln_ops,
+            annotations: opts.annotate_on.map(|_| Annotations::identical(len)),
+            max_score: None,
+            segments: opts.repeat_aware.then_some(1),
+            gap_limit: opts.max_gap_len.map(|_| GapLimit::Ok),
+            gap_stats: opts.gap_stats.then(GapStats::default),
+            full_length: opts.full_length.map(|_| true),
+            frames: None,
+            collapsed: None,
+            dual_ops: opts.dual_ops.then(|| {
+                let ops = show_self_alignment_ops(seq, opts.mask_low_complexity);
+                (ops.clone(), ops)
+            }),
+            low_confidence: opts.low_confidence.map(|max| {
+                let nonstandard = seq.iter().filter(|&&c| !is_standard(c)).count();
+                nonstandard as f64 / len as f64 > max
+            }),
+            pair_weight: None,
+            entropy: None,
+        }
+    }
+
+    /// Info for the best ungapped diagonal of `x` and `y`.
+    ///
+    /// The alignment only covers the overlap of the two sequences on that diagonal.
+    fn ungapped(x: &[u8], y: &[u8], config: &AlignerConfig, opts: OutputOptions) -> Self {
+        // Safe because the input sequences are never empty.
+        let d = ungapped::best_diagonal(x, y, &config.matrix).unwrap();
+        // Safe because inteins are short.
+        let aln_len = i32::try_from(d.len).unwrap();
+        let num_matches = i32::try_from(d.num_matches).unwrap();
+
+        let xstart = d.offset.max(0) as usize;
+        let ystart = (-d.offset).max(0) as usize;
+        let coords = Coords {
+            xstart,
+            xend: xstart + d.len,
+            ystart,
+            yend: ystart + d.len,
+        };
+
+        Self {
+            aln_len,
+            num_matches,
+            percent_identity: config.pid(
+                aln_len,
+                num_matches,
+                aln_len - num_matches,
+                x.len(),
+                y.len(),
+            ),
+            score: d.score,
+            num_mismatches: aln_len - num_matches,
+            num_gap_opens: 0,
+            edit_distance: aln_len - num_matches,
+            internal_aln_len: aln_len,
+            coords,
+            internal_coords: coords,
+            aln_ops: None,
+            annotations: None,
+            max_score: None,
+            segments: None,
+            gap_limit: None,
+            gap_stats: None,
+            full_length: opts
+                .full_length
+                .map(|tol| coords.cover(x.len(), y.len(), tol)),
+            frames: None,
+            collapsed: None,
+            dual_ops: None,
+            low_confidence: None,
+            pair_weight: None,
+            entropy: None,
+        }
+    }
+
+    /// Info for a `--score-only` alignment, which has everything but the ops and the
+    /// internal alignment length.
+    fn score_only(x: &[u8], y: &[u8], config: &AlignerConfig) -> Self {
+        let aln = score_only::align(x, y, &config.matrix, config.gap_open, config.gap_extend);
+        // Safe because inteins are short.
+        let aln_len = i32::try_from(aln.aln_len).unwrap();
+        let num_matches = i32::try_from(aln.num_matches).unwrap();
+        let num_mismatches = i32::try_from(aln.num_mismatches).unwrap();
+
+        Self {
+            aln_len,
+            num_matches,
+            percent_identity: config.pid(aln_len, num_matches, num_mismatches, x.len(), y.len()),
+            score: aln.score,
+            num_mismatches,
+            num_gap_opens: i32::try_from(aln.num_gap_opens).unwrap(),
+            edit_distance: aln_len - num_matches,
+            internal_aln_len: aln_len,
+            coords: Coords::full_length(x.len(), y.len()),
+            internal_coords: Coords::full_length(x.len(), y.len()),
+            aln_ops: None,
+            annotations: None,
+            max_score: None,
+            segments: None,
+            gap_limit: None,
+            gap_stats: None,
+            full_length: None,
+            frames: None,
+            collapsed: None,
+            dual_ops: None,
+            low_confidence: None,
+            pair_weight: None,
+            entropy: None,
+        }
+    }
+
+    /// Info for an alignment-free comparison, with the k-mer similarity in place of the pid.
+    fn kmer_similarity(x: &[u8], y: &[u8], k: usize) -> Self {
+        Self {
+            aln_len: 0,
+            num_matches: 0,
+            percent_identity: kmer::cosine_similarity(x, y, k),
+            score: 0,
+            num_mismatches: 0,
+            num_gap_opens: 0,
+            edit_distance: 0,
+            internal_aln_len: 0,
+            coords: Coords::full_length(x.len(), y.len()),
+            internal_coords: Coords::full_length(x.len(), y.len()),
+            aln_ops: None,
+            annotations: None,
+            max_score: None,
+            segments: None,
+            gap_limit: None,
+            gap_stats: None,
+            full_length: None,
+            frames: None,
+            collapsed: None,
+            dual_ops: None,
+            low_confidence: None,
+            pair_weight: None,
+            entropy: None,
+        }
+    }
+}
+
+/// The score of aligning `seq` to itself.
+fn self_score(seq: &[u8], matrix: &Matrix) -> i32 {
+    seq.iter().map(|&c| matrix.score(c, c)).sum()
+}
+
+/// Reverse (but don't complement) a sequence.
+fn reversed(seq: &[u8]) -> Vec<u8> {
+    seq.iter().rev().copied().collect()
+}
+
+/// A made-up pair of sequences of length `len`, for timing and warming up the aligner.
+fn test_pair(len: usize) -> (Vec<u8>, Vec<u8>) {
+    const AMINO_ACIDS: &[u8] = b"ACDEFGHIKLMNPQRSTVWY";
+
+    let x = (0..len).map(|i| AMINO_ACIDS[(i * 7) % 20]).collect();
+    let y = (0..len).map(|i| AMINO_ACIDS[(i * 11 + 3) % 20]).collect();
+
+    (x, y)
+}
+
+/// Estimate how many DP cells can be aligned within `seconds`, by timing a test alignment.
+fn estimate_max_cells(config: &AlignerConfig, seconds: f64) -> usize {
+    const LEN: usize = 400;
+
+    let (x, y) = test_pair(LEN);
+
+    let score = |a: u8, b: u8| config.matrix.score(a, b);
+    let mut aligner = Aligner::new(config.gap_open, config.gap_extend, &score);
+
+    // Take the fastest of a few runs so a hiccup doesn't make the budget too tight.
+    let elapsed = (0..3)
+        .map(|_| {
+            let start = Instant::now();
+            aligner.global(&x, &y);
+            start.elapsed().as_secs_f64()
+        })
+        .fold(f64::INFINITY, f64::min);
+
+    let cells_per_second = (LEN * LEN) as f64 / elapsed;
+
+    (cells_per_second * seconds) as usize
+}
+
+/// Align one pair and send its rows to the writer thread (or wherever the mode needs them).
+fn handle_pair<F: MatchFunc>(
+    context: &Context,
+    aligner: &mut Aligner<F>,
+    subst_counts: &mut Option<SubstCounts>,
+    pair: &RecordPair,
+    rows: &channel::Sender<Rows>,
+) {
+    let Context {
+        config,
+        outputs,
+        bins,
+        opts,
+        clusters,
+        best_hits,
+        pids,
+        max_cells,
+        early_abort,
+        block_results,
+        low_pid,
+        sorted,
+        verifier,
+        stop_on_pid,
+        stopped,
+        conservation,
+        dotplot,
+        classes,
+        histogram,
+        best_so_far,
+        grouped,
+        skip_log,
+        translations,
+        ..
+    } = context;
+    let conservation = conservation
+        .as_ref()
+        .filter(|c| c.wants(pair.x_index, pair.y_index));
+    let opts = *opts;
+    let RecordPair {
+        x_index,
+        y_index,
+        slot,
+        ..
+    } = pair;
+    let (x_index, y_index) = (*x_index, *y_index);
+    let (x, y) = (pair.x(), pair.y());
+
+    // With --translate, the pair is aligned and written as its best pair of frames.
+    let mut frames = None;
+    let translated = translations.as_ref().map(|translations| {
+        let ((x_frame, x_seq), (y_frame, y_seq)) =
+            translations.best_frames(x_index, y_index, |x_seq, y_seq| {
+                align_by_mode(aligner, x_seq, y_seq, opts.mode).score
+            });
+        frames = Some((x_frame, y_frame));
+        (
+            Record::with_attrs(x.id(), x.desc(), x_seq),
+            Record::with_attrs(y.id(), y.desc(), y_seq),
+        )
+    });
+    let (x, y) = match &translated {
+        Some((x, y)) => (x, y),
+        None => (x, y),
+    };
+
+    // Skipped pairs still have to fill their slot, so the block can be written.
+    let send_block_result = |buffers: Vec<Vec<u8>>, bin: Option<BinRow>| {
+        if let (Some(slot), Some(results)) = (slot, block_results) {
+            results.send((*slot, buffers, bin)).unwrap();
+        }
+    };
+
+    // Masked pairs are aligned, so their X's are unmatched like any other pair's.  A local
+    // alignment of identical sequences can leave out ends that score badly, e.g., runs of X.
+    let identical = !opts.reversed
+        && opts.mode != AlignMode::Local
+        && x.seq() == y.seq()
+        && !(opts.mask_low_complexity && x.seq().contains(&b'X'))
+        && classes
+            .as_ref()
+            .is_none_or(|classes| classes.same_classes(x_index, y_index));
+
+    if let Some(max_cells) = max_cells {
+        let cells = x.seq().len() * y.seq().len();
+        if !identical && cells > *max_cells {
+            eprintln!(
+                "warning: skipping {} vs. {}: {cells} DP cells are more than the {max_cells} estimated to fit in the --per-alignment-timeout",
+                x.id(),
+                y.id()
+            );
+            if let Some(skip_log) = skip_log {
+                skip_log.log(x.id(), y.id(), "per-alignment-timeout");
+            }
+            send_block_result(Vec::new(), None);
+            return;
+        }
+    }
+
+    if let Some(min_pid) = early_abort {
+        if !identical && max_possible_pid(x.seq(), y.seq()) < *min_pid {
+            if let Some(skip_log) = skip_log {
+                skip_log.log(x.id(), y.id(), "early-abort");
+            }
+            send_block_result(Vec::new(), None);
+            return;
+        }
+    }
+
+    // The matched positions, if they're wanted for the dotplot.
+    let mut dots = None;
+    let mut info = if identical {
+        if dotplot.is_some() {
+            dots = Some((0..x.seq().len()).map(|i| (i, i)).collect());
+        }
+        if let (Some(counts), false) = (subst_counts, x_index == y_index) {
+            counts.add_identical(x.seq());
+        }
+        if let Some(conservation) = conservation {
+            conservation.add_identical();
+        }
+        // Duplicate sequences are as easy as self-hits, so skip the DP.
+        let mut info = AlignmentInfo::identical(x.seq(), config, opts);
+        if let Some(classes) = classes {
+            info.score = classes.self_score(x_index, x.seq());
+        }
+        info
+    } else if let Some(k) = opts.kmer_size {
+        AlignmentInfo::kmer_similarity(x.seq(), y.seq(), k)
+    } else if opts.ungapped {
+        AlignmentInfo::ungapped(x.seq(), y.seq(), config, opts)
+    } else if opts.score_only {
+        let reversed_y = opts.reversed.then(|| reversed(y.seq()));
+        let info =
+            AlignmentInfo::score_only(x.seq(), reversed_y.as_deref().unwrap_or(y.seq()), config);
+        low_pid.add(info.percent_identity);
+        info
+    } else {
+        let reversed_y = opts.reversed.then(|| reversed(y.seq()));
+        let y_seq = reversed_y.as_deref().unwrap_or(y.seq());
+        let encoded = classes.as_ref().map(|classes| {
+            (
+                classes.encode(x_index, x.seq(), false),
+                classes.encode(y_index, y_seq, opts.reversed),
+            )
+        });
+        let (x_aligned, y_aligned) = match &encoded {
+            Some((x_encoded, y_encoded)) => (&x_encoded[..], &y_encoded[..]),
+            None => (x.seq(), y_seq),
+        };
+        let mut alignment = if opts.repeat_aware {
+            aligner.custom(x_aligned, y_aligned)
+        } else {
+            let alignment = align_by_mode(aligner, x_aligned, y_aligned, opts.mode);
+            assert_mode(x, y, &alignment, opts.mode);
+            alignment
+        };
+        if encoded.is_some() {
+            restore_matches(&mut alignment, x.seq(), y_seq);
+        }
+        if opts.iupac {
+            mark_matches(&mut alignment, x.seq(), y_seq, iupac_overlap);
+        }
+        // Checked before --max-gap-len and --mask-low-complexity change the alignment, since
+        // the backward one is only aligned.
+        if let Some(verifier) = verifier {
+            if verifier.sampled(x_index, y_index) {
+                let mut backward = align_by_mode(aligner, y.seq(), x.seq(), opts.mode);
+                if opts.iupac {
+                    mark_matches(&mut backward, y.seq(), x.seq(), iupac_overlap);
+                }
+                verifier.check(x.id(), y.id(), &alignment, &backward);
+            }
+        }
+        let (mut alignment, gap_limit) = match opts.max_gap_len {
+            Some(max_gap_len) => {
+                let (mut alignment, gap_limit) =
+                    fit_gaps(x.seq(), y_seq, config, max_gap_len, alignment);
+                // A realigned pair's ops are rust-bio's again.
+                if opts.iupac && gap_limit != GapLimit::Ok {
+                    mark_matches(&mut alignment, x.seq(), y_seq, iupac_overlap);
+                }
+                (alignment, Some(gap_limit))
+            }
+            None => (alignment, None),
+        };
+        if opts.mask_low_complexity {
+            mask::unmatch_masked(&mut alignment, x.seq());
+        }
+        if let Some(counts) = subst_counts {
+            counts.add(x.seq(), y_seq, &alignment);
+        }
+        if let Some(conservation) = conservation {
+            conservation.add(x_index, &alignment);
+        }
+        if dotplot.is_some() {
+            dots = Some(match_coords(&alignment));
+        }
+        let mut info = AlignmentInfo::new(&alignment, config, opts);
+        info.gap_limit = gap_limit;
+        info.low_confidence = opts
+            .low_confidence
+            .map(|max| nonstandard_fraction(x.seq(), y_seq, &alignment) > max);
+        low_pid.add(info.percent_identity);
+        info
+    };
+    info.max_score = context.max_score(x_index, y_index);
+    info.frames = frames;
+    info.collapsed = context.collapsed_ids(x_index, y_index);
+    info.pair_weight = context.pair_weight(x_index, y_index);
+    info.entropy = context.entropies(x_index, y_index);
+
+    let self_hit = x_index == y_index;
+    let result = PairResult::new(x_index, y_index, &info);
+    let is_best = || {
+        best_so_far
+            .as_ref()
+            .is_none_or(|best| !self_hit && best.beats(info.percent_identity))
+    };
+    let rejected_by = config
+        .rejected_by(&result)
+        .or_else(|| (!is_best()).then_some("stream-best"));
+    if let (Some(skip_log), Some(filter)) = (skip_log, rejected_by) {
+        skip_log.log(x.id(), y.id(), filter);
+    }
+    if rejected_by.is_none() {
+        if let (Some(dotplot), Some(dots), false) = (dotplot, &dots, self_hit) {
+            dotplot.write(x.id(), y.id(), dots);
+        }
+        if let (Some(histogram), false) = (histogram, self_hit) {
+            histogram.add(info.percent_identity);
+        }
+        if let Some(grouped) = grouped {
+            grouped.add((x_index, x), (y_index, y), &info, self_hit, opts);
+        }
+        let buffers = print_to_outputs(outputs, x, y, &info, self_hit, opts, true);
+        let bin = bins.as_ref().map(|_| {
+            let mut buf = Vec::new();
+            print_alignment_info(&mut buf, x, y, &info, self_hit, opts);
+            (info.percent_identity, buf)
+        });
+        let (buffers, bin) = match (sorted, slot) {
+            (Some(sorted), _) => {
+                sorted.lock().unwrap().push((result, buffers));
+                (Vec::new(), bin)
+            }
+            (None, Some(_)) => {
+                send_block_result(buffers, bin);
+                (Vec::new(), None)
+            }
+            (None, None) => (buffers, bin),
+        };
+        if !buffers.is_empty() || bin.is_some() {
+            rows.send(Rows {
+                buffers,
+                bin,
+                finished: None,
+            })
+            .unwrap();
+        }
+    } else {
+        send_block_result(Vec::new(), None);
+    }
+
+    if let Some(clusters) = clusters {
+        clusters.add(x_index, y_index, info.percent_identity);
+    }
+    if let Some(best_hits) = best_hits {
+        best_hits.add(
+            x_index,
+            y_index,
+            info.aln_len,
+            info.num_matches,
+            info.percent_identity,
+        );
+    }
+    if let Some(pids) = pids {
+        pids.add(x_index, y_index, info.percent_identity);
+    }
+
+    let hit = stop_on_pid.is_some_and(|min| !self_hit && info.percent_identity >= min);
+    if hit && !stopped.swap(true, atomic::Ordering::Relaxed) {
+        eprintln!(
+            "found {} vs. {} with pid {:.prec$}, stopping (see --stop-on-pid)",
+            x.id(),
+            y.id(),
+            info.percent_identity,
+            prec = opts.precision
+        );
+    }
+}
+
+/// Set up the worker threads and their shared queue of pairs.
+///
+/// Workers don't write to the outputs themselves.  They send their rows to `rows`, which the
+/// writer thread drains (see `spawn_writer`), so a panicking worker can't poison an output.  A
+/// pair that panics is recorded in `context.failed`, and the worker moves on to its next pair.
+/// No pair is bound to a particular worker, so if one worker exits, the others still take the
+/// rest of the queue.
+fn set_up_workers(
+    num_threads: usize,
+    context: Arc<Context>,
+    rows: channel::Sender<Rows>,
+) -> Workers {
+    let mut thread_handles = Vec::with_capacity(num_threads);
+    let (sender, receiver) = channel::bounded::<RecordPair>(256);
+
+    (0..num_threads).for_each(|worker| {
+        let r = receiver.clone();
+        let context = context.clone();
+        let rows = rows.clone();
+        let handle = thread::spawn(move || {
+            let config = &context.config;
+            let matrix = context
+                .classes
+                .as_ref()
+                .map_or(&config.matrix, |classes| &classes.matrix);
+            let score = |a: u8, b: u8| matrix.score(a, b);
+            let mut scoring = Scoring::new(config.gap_open, config.gap_extend, &score);
+            if context.opts.repeat_aware {
+                scoring = scoring.yclip(config.gap_open);
+            }
+            scoring = match context.opts.mode {
+                AlignMode::Global => scoring,
+                AlignMode::Local => scoring.xclip(0).yclip(0),
+                AlignMode::Semiglobal => scoring.yclip(0),
+            };
+            let mut aligner = Aligner::with_scoring(scoring);
+            if context.warm_up {
+                // About the length of a typical protein.
+                let (x, y) = test_pair(300);
+                aligner.global(&x, &y);
+            }
+            let mut subst_counts = context
+                .subst_counts
+                .as_ref()
+                .map(|_| SubstCounts::default());
+
+            loop {
+                if let Some(throttle) = &context.throttle {
+                    let give_up = || {
+                        context.stopped.load(atomic::Ordering::Relaxed)
+                            || (throttle.all_sent() && r.is_empty())
+                    };
+                    if !throttle.wait(worker, give_up) {
+                        break;
+                    }
+                }
+                let Ok(pair) = r.recv() else {
+                    break;
+                };
+                if context.stopped.load(atomic::Ordering::Relaxed) {
+                    break;
+                }
+
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    handle_pair(&context, &mut aligner, &mut subst_counts, &pair, &rows)
+                }));
+
+                if result.is_ok() && context.checkpointing {
+                    let finished = Rows {
+                        buffers: Vec::new(),
+                        bin: None,
+                        finished: Some(pair.index),
+                    };
+                    rows.send(finished).unwrap();
+                }
+
+                if result.is_err() {
+                    context
+                        .failed
+                        .lock()
+                        .unwrap()
+                        .push((pair.x().id().to_string(), pair.y().id().to_string()));
+                    // Its slot still has to be filled, or the block would never be written.
+                    if let (Some(slot), Some(results)) = (pair.slot, &context.block_results) {
+                        results.send((slot, Vec::new(), None)).unwrap();
+                    }
+                }
+
+                context.processed.fetch_add(1, atomic::Ordering::Relaxed);
+                if let Some(load) = &context.worker_load {
+                    load.add(worker, pair.x().seq().len() + pair.y().seq().len());
+                }
+            }
+
+            if let (Some(counts), Some(local)) = (&context.subst_counts, &subst_counts) {
+                counts.lock().unwrap().merge(local);
+            }
+        });
+
+        thread_handles.push(handle);
+    });
+
+    Workers {
+        thread_handles,
+        sender,
+    }
+}
+
+/// Start the thread that writes the workers' rows to the outputs, until every sender is dropped.
+fn spawn_writer(
+    context: Arc<Context>,
+    rows: channel::Receiver<Rows>,
+    mut checkpoint: Option<Checkpoint>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for Rows {
+            buffers,
+            bin,
+            finished,
+        } in rows
+        {
+            write_buffers(&context.outputs, &buffers);
+            if let (Some(bins), Some((pid, buf))) = (&context.bins, bin) {
+                bins.writer(pid).lock().unwrap().write_all(&buf).unwrap();
+            }
+
+            if let (Some(checkpoint), Some(index)) = (&mut checkpoint, finished) {
+                if checkpoint.finish(index) {
+                    write_checkpoint(&context, checkpoint);
+                }
+            }
+        }
+
+        if let Some(checkpoint) = &checkpoint {
+            write_checkpoint(&context, checkpoint);
+        }
+    })
+}
+
+/// Flush all the row outputs, then record how far they've gotten.
+fn write_checkpoint(context: &Context, checkpoint: &Checkpoint) {
+    let bin_writers = context.bins.iter().flat_map(|bins| bins.writers());
+    context
+        .outputs
+        .iter()
+        .map(|output| &output.out)
+        .chain(bin_writers)
+        .for_each(|out| out.lock().unwrap().flush().unwrap());
+
+    if let Err(e) = checkpoint.write() {
+        eprintln!("warning: couldn't write the checkpoint: {e}");
+    }
+}
+
+/// Self-hits don't need alignment, so write out the equal sequence alignment info for the
+/// records in `range`.
+fn write_self_hits(records: &[Record], range: Range<usize>, context: &Context) {
+    let opts = context.opts;
+    records[range.clone()].iter().zip(range).for_each(|(r, i)| {
+        if let Some(collapsed) = &context.collapsed {
+            if collapsed.is_collapsed(i) {
+                if let Some(skip_log) = &context.skip_log {
+                    skip_log.log(r.id(), r.id(), "collapse-identical-output");
+                }
+                return;
+            }
+        }
+
+        let mut info = AlignmentInfo::identical(r.seq(), &context.config, opts);
+        if let Some(classes) = &context.classes {
+            info.score = classes.self_score(i, r.seq());
+        }
+        info.max_score = context.max_score(i, i);
+        info.collapsed = context.collapsed_ids(i, i);
+        info.pair_weight = context.pair_weight(i, i);
+        info.entropy = context.entropies(i, i);
+        let result = PairResult::new(i, i, &info);
+        if let Some(filter) = context.config.rejected_by(&result) {
+            if let Some(skip_log) = &context.skip_log {
+                skip_log.log(r.id(), r.id(), filter);
+            }
+            return;
+        }
+
+        let buffer = context.sorted.is_some();
+        let buf = print_to_outputs(&context.outputs, r, r, &info, true, opts, buffer);
+        if let Some(sorted) = &context.sorted {
+            sorted.lock().unwrap().push((result, buf));
+        }
+        if let Some(bins) = &context.bins {
+            let out = &mut *bins.writer(info.percent_identity).lock().unwrap();
+            print_alignment_info(out, r, r, &info, true, opts);
+        }
+        if let Some(grouped) = &context.grouped {
+            grouped.add((i, r), (i, r), &info, true, opts);
+        }
+    });
+}
+
+/// Align records
+///
+/// The worker threads will handle file output, unless `stable_blocks` is given, in which case
+/// pairs are sent out a block at a time and each finished block is written here.  If
+/// `include_self_hits` is true, each record is also sent to be aligned against itself.  If
+/// `cell_budget` is given, sending waits until the pair's DP cells fit in the budget.  If
+/// `keep(x_index, y_index)` is false, the pair is skipped.
+///
+/// Sending stops if the workers hang up.  Returns the number of pairs sent.
+///
+/// The sender will drop at the end of this function, so you don't have to manually close it.
+fn align_records(
+    records: &Arc<Vec<Record>>,
+    sender: RecordPairSender,
+    include_self_hits: bool,
+    stable_blocks: Option<StableBlocks>,
+    cell_budget: Option<Arc<CellBudget>>,
+    keep: impl Fn(usize, usize) -> bool,
+) -> usize {
+    // Stable blocks are in the order of x then y, so each self-hit goes before the record's
+    // other pairs, rather than all of them first.
+    let in_order = stable_blocks.is_some();
+    let self_hits = records
+        .iter()
+        .enumerate()
+        .map(|x| (x, x))
+        .filter(|_| include_self_hits && !in_order);
+    let pairs = records.iter().enumerate().flat_map(|x| {
+        let self_hit = (include_self_hits && in_order).then_some((x, x));
+        let others = records
+            .iter()
+            .enumerate()
+            .skip(x.0 + 1)
+            .map(move |y| (x, y));
+        self_hit.into_iter().chain(others)
+    });
+
+    let pairs = self_hits
+        .chain(pairs)
+        .filter(|((x_index, _), (y_index, _))| keep(*x_index, *y_index))
+        .enumerate();
+
+    let mut num_sent = 0;
+    let mut send = |(index, ((x_index, x), (y_index, y))): PairRef, slot| {
+        let reservation = cell_budget
+            .as_ref()
+            .map(|budget| budget.reserve(x.seq().len() * y.seq().len()));
+
+        num_sent += 1;
+        sender
+            .send(RecordPair {
+                index,
+                x_index,
+                y_index,
+                records: Arc::clone(records),
+                slot,
+                _reservation: reservation,
+            })
+            .is_ok()
+    };
+
+    match stable_blocks {
+        None => {
+            for pair in pairs {
+                // Workers only hang up early when --stop-on-pid finds a hit.
+                if !send(pair, None) {
+                    break;
+                }
+            }
+        }
+        Some(mut stable_blocks) => {
+            'blocks: for block in &pairs.chunks(stable_blocks.size) {
+                let block = block.collect::<Vec<_>>();
+                for (i, pair) in block.iter().enumerate() {
+                    if !send(*pair, Some(i)) {
+                        break 'blocks;
+                    }
+                }
+                stable_blocks.write_block(&block);
+            }
+            stable_blocks.finish();
+        }
+    }
+
+    num_sent
+}
+
+/// Print how many records are exact duplicates, for --exact-match-only, and with `verbose`, the
+/// ids of each group.
+fn report_exact_duplicates(records: &[Record], verbose: bool) {
+    let groups = exact_duplicates(records);
+    let num_pairs = groups
+        .iter()
+        .map(|group| group.len() * (group.len() - 1) / 2)
+        .sum::<usize>();
+
+    if verbose {
+        for group in &groups {
+            let ids = group.iter().map(|&i| records[i].id()).collect::<Vec<_>>();
+            eprintln!("exact duplicates: {}", ids.join(" "));
+        }
+    }
+    eprintln!(
+        "found {} groups of exact duplicates ({num_pairs} pairs)",
+        groups.len()
+    );
+}
+
+/// Two records to align, along with their positions in the input, numbered in sending order.
+type PairRef<'a> = (usize, ((usize, &'a Record), (usize, &'a Record)));
+
+pub fn run(cli: Cli) {
+    match cli.into_command() {
+        Command::Align(args) => {
+            if let Err(e) = make_output_dirs(&args).and_then(|()| align(args)) {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+        }
+        Command::Selftest(args) => selftest::selftest(args),
+        Command::Validate(args) => validate::validate(args),
+    }
+}
+
+/// Check that the directory of each output exists, or create it with --mkdir, so that a typo in
+/// a path fails now rather than when the file is written at the end.
+fn make_output_dirs(args: &AlignArgs) -> error::Result<()> {
+    for path in args.output_paths() {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() && !dir.exists() => dir,
+            _ => continue,
+        };
+
+        if args.mkdir {
+            fs::create_dir_all(dir).map_err(|source| Error::Io {
+                path: dir.to_path_buf(),
+                source,
+            })?;
+        } else {
+            return Err(Error::MissingDirectory {
+                dir: dir.to_path_buf(),
+                path: path.clone(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Align each input sequence to the --profile, rather than to each other.
+fn align_to_profile(args: &AlignArgs, path: &Path) -> error::Result<()> {
+    if args.score_scale() != 1 {
+        return Err(Error::usage("--profile needs integer gap penalties, since the PSSM's scores can't be scaled to match"));
+    }
+    let profile = Profile::from_file(path)?;
+    let gap_profile = args.msa_gap_profile.as_ref();
+    if let Some(gaps) = gap_profile.filter(|gaps| gaps.len() != profile.len()) {
+        return Err(Error::usage(format!(
+            "the first sequence of --msa-gap-profile has {} residues, but the profile has {} positions",
+            gaps.len(),
+            profile.len()
+        )));
+    }
+
+    let mut out = BufWriter::new(File::create(&args.out_file).unwrap());
+    let records = get_records(&args.in_file, &InputOptions::new(args))?;
+
+    writeln!(out, "id\tlen\tprofile_len\tscore\talnlen\tmatches\tpid").unwrap();
+    for record in &records {
+        let aln = profile.align(
+            record.seq(),
+            args.gap_open(),
+            args.gap_extend(),
+            gap_profile,
+        );
+        let pid = aln.num_matches as f64 / aln.aln_len as f64;
+
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{pid:.prec$}",
+            record.id(),
+            record.seq().len(),
+            profile.len(),
+            aln.score,
+            aln.aln_len,
+            aln.num_matches,
+            prec = args.precision
+        )
+        .unwrap();
+    }
+
+    Ok(())
+}
+
+/// Check that the records are what they're scored for: nucleotides with --match, and protein
+/// with a substitution matrix.
+///
+/// Scoring protein as nucleotides is an error, but nucleotides with a protein matrix is only a
+/// warning, since that was the only way to align them before --match.
+fn check_alphabet(args: &AlignArgs, records: &[Record]) -> error::Result<()> {
+    let looks_like_nucleotide = if args.iupac {
+        looks_like_iupac_nucleotide
+    } else {
+        looks_like_nucleotide
+    };
+    // Empty records are neither nucleotide nor protein.
+    let num_nonempty = records.iter().filter(|r| !r.seq().is_empty()).count();
+    let num_nucleotide = records
+        .iter()
+        .filter(|r| looks_like_nucleotide(r.seq()))
+        .count();
+
+    if args.match_score.is_some() {
+        let num_protein = num_nonempty - num_nucleotide;
+        if num_protein > 0 {
+            return Err(Error::usage(format!(
+                "--match and --mismatch are for nucleotide sequences, but {num_protein} of {} records look like protein",
+                records.len()
+            )));
+        }
+    } else if num_nucleotide > 0 && !args.translate {
+        eprintln!(
+            "warning: {num_nucleotide} of {} records look like nucleotide sequences, but they're scored with a protein matrix (see --match and --mismatch, or --translate)",
+            records.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Align each input sequence to its best window of the --reference, rather than to each other.
+///
+/// The records are split into a chunk per thread, so the rows still come out in input order.
+fn align_to_reference(args: &AlignArgs, path: &Path, window: usize) -> error::Result<()> {
+    let references = get_records(path, &InputOptions::new(args))?;
+    if references.is_empty() {
+        return Err(Error::usage(format!(
+            "the reference {} has no sequences",
+            path.display()
+        )));
+    }
+    let step = args
+        .ref_step
+        .map_or((window / 2).max(1), |step| step as usize);
+    if let Some(domains) = &args.ref_domains {
+        domains.check(&references)?;
+    }
+
+    let mut out = BufWriter::new(File::create(&args.out_file).unwrap());
+    let records = get_records(&args.in_file, &InputOptions::new(args))?;
+    check_alphabet(args, &records)?;
+    let matrix = args.matrix();
+    let chunk_size = records.len().div_ceil(args.threads()).max(1);
+
+    let rows = thread::scope(|scope| {
+        let handles = records
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let (references, matrix) = (&references, &matrix);
+                scope.spawn(move || {
+                    let score = |a: u8, b: u8| matrix.score(a, b);
+                    let mut aligner = Aligner::new(args.gap_open(), args.gap_extend(), &score);
+
+                    chunk
+                        .iter()
+                        .map(|record| {
+                            let hit =
+                                best_window(&mut aligner, record.seq(), references, window, step);
+                            let reference = &references[hit.ref_index];
+                            let aln_len = alignment_length(&hit.alignment);
+                            let num_matches = count_identities(&hit.alignment);
+                            let denominator = args.pid_denominator.denominator(
+                                aln_len,
+                                num_matches + count_mismatches(&hit.alignment),
+                                hit.alignment.xlen,
+                                hit.alignment.ylen,
+                            );
+                            let pid = percent_identity(denominator, num_matches, args.empty_pid);
+                            let domain_pids = args.ref_domains.iter().flat_map(|domains| {
+                                domains.pids(
+                                    reference.id(),
+                                    &hit.alignment,
+                                    hit.window_start,
+                                    args.empty_pid,
+                                )
+                            });
+                            let domain_pids = domain_pids
+                                .map(|pid| format!("\t{pid:.prec$}", prec = args.precision))
+                                .collect::<String>();
+
+                            format!(
+                                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{aln_len}\t{num_matches}\t{pid:.prec$}{domain_pids}",
+                                record.id(),
+                                record.seq().len(),
+                                reference.id(),
+                                reference.seq().len(),
+                                hit.window_start,
+                                hit.window_start + hit.alignment.ystart,
+                                hit.window_start + hit.alignment.yend,
+                                Score {
+                                    score: hit.alignment.score,
+                                    scale: args.score_scale()
+                                },
+                                prec = args.precision
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    let domain_columns = args
+        .ref_domains
+        .iter()
+        .flat_map(|domains| domains.names())
+        .map(|name| format!("\t{name}_pid"))
+        .collect::<String>();
+    writeln!(
+        out,
+        "id\tlen\tref\tref_len\twindow_start\tref_start\tref_end\tscore\talnlen\tmatches\tpid{domain_columns}"
+    )
+    .unwrap();
+    for row in rows {
+        writeln!(out, "{row}").unwrap();
+    }
+
+    Ok(())
+}
+
+/// How many pairs --estimate times.
+const ESTIMATE_PAIRS: usize = 300;
+
+/// Time a sample of random pairs, then print how long the whole run would take and how big its
+/// main output would be, for --estimate.
+///
+/// Every pair of records is counted, whatever the filters, and nothing is written.
+fn estimate_run(args: &AlignArgs) -> error::Result<()> {
+    let records = get_records(&args.in_file, &InputOptions::new(args))?;
+    let n = records.len();
+    let num_pairs = n * n.saturating_sub(1) / 2;
+    if num_pairs == 0 {
+        return Err(Error::usage("--estimate needs at least two records"));
+    }
+
+    let config = AlignerConfig {
+        gap_open: args.gap_open(),
+        gap_extend: args.gap_extend(),
+        matrix: args.matrix(),
+        filter: None,
+        empty_pid: args.empty_pid,
+        pid_denominator: args.pid_denominator,
+    };
+    // The ops are kept so the formats with gapped sequences can be sized too.
+    let opts = OutputOptions {
+        keep_ops: true,
+        ..OutputOptions::new(args)
+    };
+    let score = |a: u8, b: u8| config.matrix.score(a, b);
+    let mut aligner = Aligner::new(config.gap_open, config.gap_extend, &score);
+
+    // xorshift64 from a fixed state, so the sample is the same from run to run.
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    let mut random_record = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % n as u64) as usize
+    };
+
+    let num_sampled = ESTIMATE_PAIRS.min(num_pairs);
+    let (mut seconds, mut pair_bytes, mut self_bytes) = (0.0, 0, 0);
+    for _ in 0..num_sampled {
+        let (i, j) = loop {
+            let (i, j) = (random_record(), random_record());
+            if i != j {
+                break (i.min(j), i.max(j));
+            }
+        };
+        let (x, y) = (&records[i], &records[j]);
+
+        let mut buf = Vec::new();
+        let start = Instant::now();
+        let alignment = aligner.global(x.seq(), y.seq());
+        let info = AlignmentInfo::new(&alignment, &config, opts);
+        print_alignment_info(&mut buf, x, y, &info, false, opts);
+        seconds += start.elapsed().as_secs_f64();
+        pair_bytes += buf.len();
+
+        let mut buf = Vec::new();
+        let info = AlignmentInfo::identical(x.seq(), &config, opts);
+        print_alignment_info(&mut buf, x, x, &info, true, opts);
+        self_bytes += buf.len();
+    }
+
+    let per_pair = seconds / num_sampled as f64;
+    let total_seconds = per_pair * num_pairs as f64 / args.threads() as f64;
+    let total_bytes =
+        (pair_bytes as f64 * num_pairs as f64 + self_bytes as f64 * n as f64) / num_sampled as f64;
+
+    eprintln!(
+        "timed {num_sampled} of {num_pairs} pairs, at {:.3} ms per pair",
+        per_pair * 1000.0
+    );
+    eprintln!(
+        "estimated run time with {} threads: {}",
+        args.threads(),
+        human_duration(total_seconds)
+    );
+    if args.format.is_row_based() {
+        eprintln!(
+            "estimated size of {}: {}",
+            args.out_file.display(),
+            human_size(total_bytes)
+        );
+    }
+
+    Ok(())
+}
+
+/// E.g., `42.0s`, `3m 5s`, `2h 10m`, or `4d 7h`.
+fn human_duration(seconds: f64) -> String {
+    let s = seconds.round() as u64;
+    match s {
+        0..60 => format!("{seconds:.1}s"),
+        60..3600 => format!("{}m {}s", s / 60, s % 60),
+        3600..86400 => format!("{}h {}m", s / 3600, s % 3600 / 60),
+        _ => format!("{}d {}h", s / 86400, s % 86400 / 3600),
+    }
+}
+
+/// E.g., `512 B`, `1.5 MB`, or `2.3 TB`, in powers of 1000.
+fn human_size(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["KB", "MB", "GB", "TB", "PB"];
+
+    if bytes < 1000.0 {
+        return format!("{bytes:.0} B");
+    }
+    let mut size = bytes / 1000.0;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Run the all-vs-all alignment.
+fn align(args: AlignArgs) -> error::Result<()> {
+    if let Some(path) = &args.profile {
+        return align_to_profile(&args, path);
+    }
+    if let (Some(path), Some(window)) = (&args.reference, args.ref_window) {
+        return align_to_reference(&args, path, window as usize);
+    }
+    if args.estimate {
+        return estimate_run(&args);
+    }
+    if let Some(path) = args.index_file() {
+        if path.exists() {
+            return Err(Error::OutputExists(path));
+        }
+        if args.direction() == Direction::Lexical
+            || args.cluster_numbering == ClusterNumbering::SmallestId
+        {
+            return Err(Error::usage("--use-indices can't be used with --direction lexical or --cluster-numbering smallest-id, since they order the records by id"));
+        }
+    }
+    if args.early_abort && args.mode != AlignMode::Global {
+        return Err(Error::usage("--early-abort's bound on pid is for global alignments, so it can't be used with --mode local or semiglobal"));
+    }
+    if args.early_abort && args.pid_denominator != PidDenominator::AlnLen {
+        return Err(Error::usage("--early-abort's bound on pid is over the alignment length, so it can't be used with another --pid-denominator"));
+    }
+    if args.verify.is_some() && args.mode == AlignMode::Semiglobal {
+        return Err(Error::usage("--verify can't be used with --mode semiglobal, since aligning y vs. x puts y end to end instead"));
+    }
+    let mut compute_names = HashSet::new();
+    if let Some(column) = args.compute.iter().find(|c| !compute_names.insert(&c.name)) {
+        return Err(Error::usage(format!(
+            "--compute {} is given more than once",
+            column.name
+        )));
+    }
+
+    let mut matrix = args.matrix();
+    if let (Some((a, b)), false) = (matrix.asymmetric_pair(), args.allow_asymmetric) {
+        return Err(Error::usage(format!(
+            "the matrix is not symmetric ({}/{} != {}/{}), see --allow-asymmetric",
+            a as char, b as char, b as char, a as char
+        )));
+    }
+    if args.nonstandard_aa == NonstandardAa::Neutral {
+        matrix.neutralize(b"UO");
+    }
+
+    let extra_outputs = args
+        .extra_outputs
+        .iter()
+        .map(|path| Output::infer_format(path).map(|format| (path, format)))
+        .collect::<error::Result<Vec<_>>>()?;
+
+    // The outputs aren't created until the input has been checked, so a bad run leaves no files.
+    let output_formats = std::iter::once((&args.out_file, args.format))
+        .chain(extra_outputs)
+        .chain(
+            args.alignment_file
+                .iter()
+                .map(|path| (path, OutputFormat::Pairwise)),
+        )
+        .collect::<Vec<_>>();
+
+    let any_format =
+        |f: fn(OutputFormat) -> bool| output_formats.iter().any(|(_, format)| f(*format));
+
+    if args.alignment_free
+        && any_format(|f| {
+            matches!(
+                f,
+                OutputFormat::Blast6
+                    | OutputFormat::Jsonl
+                    | OutputFormat::Maf
+                    | OutputFormat::Stockholm
+                    | OutputFormat::JsonGrouped
+                    | OutputFormat::Bed
+                    | OutputFormat::Edgelist
+                    | OutputFormat::Pairwise
+            )
+        })
+    {
+        return Err(Error::usage(
+            "--alignment-free can only write the tsv, phylip-dist, and scipy-condensed formats",
+        ));
+    }
+
+    if let Some((path, _)) = output_formats.iter().find(|(path, format)| {
+        *format == OutputFormat::ScipyCondensed && output::ids_path(path).exists()
+    }) {
+        return Err(Error::OutputExists(output::ids_path(path)));
+    }
+
+    let any_row_output = any_format(OutputFormat::is_row_based);
+    let any_matrix_output =
+        any_format(|f| matches!(f, OutputFormat::PhylipDist | OutputFormat::ScipyCondensed));
+    let any_grouped_output = any_format(|f| f == OutputFormat::JsonGrouped);
+
+    if args.shard.is_some() && any_format(|f| !f.is_row_based()) {
+        return Err(Error::usage(
+            "--shard can only write row-based formats, since each shard has some of the pairs",
+        ));
+    }
+    if args.add.is_some() && any_format(|f| !f.is_row_based()) {
+        return Err(Error::usage(
+            "--add can only write row-based formats, since it only has the new pairs",
+        ));
+    }
+    if args.query.is_some() && any_format(|f| !f.is_row_based()) {
+        return Err(Error::usage(
+            "--query can only write row-based formats, since it only has the query vs. input pairs",
+        ));
+    }
+
+    if args.reversed && any_format(|f| f == OutputFormat::Bed) {
+        return Err(Error::usage("bed output can't be used with --reversed, since y's coordinates would be on the reversed sequence"));
+    }
+    if args.collapse_identical_output
+        && any_format(|f| !matches!(f, OutputFormat::Tsv | OutputFormat::Jsonl))
+    {
+        return Err(Error::usage("--collapse-identical-output can only write the tsv and jsonl formats, which have the collapsed columns"));
+    }
+    if args.translate && any_format(|f| f == OutputFormat::Bed) {
+        return Err(Error::usage("bed output can't be used with --translate, since the coordinates would be on the translated frames"));
+    }
+
+    let any_maf_output = any_format(|f| f == OutputFormat::Maf);
+    if any_maf_output && (args.ungapped || args.score_only || args.reversed) {
+        return Err(Error::usage("maf output needs the gapped alignment, so it can't be used with --ungapped, --score-only, or --reversed"));
+    }
+    let any_stockholm_output = any_format(|f| f == OutputFormat::Stockholm);
+    if any_stockholm_output && (args.ungapped || args.score_only || args.reversed) {
+        return Err(Error::usage("stockholm output needs the gapped alignment, so it can't be used with --ungapped, --score-only, or --reversed"));
+    }
+    let any_pairwise_output = any_format(|f| f == OutputFormat::Pairwise);
+    if any_pairwise_output && (args.ungapped || args.score_only || args.reversed) {
+        return Err(Error::usage("pairwise output needs the gapped alignment, so it can't be used with --ungapped, --score-only, or --reversed"));
+    }
+
+    let opts = OutputOptions {
+        keep_ops: any_maf_output || any_stockholm_output || any_pairwise_output,
+        ..OutputOptions::new(&args)
+    };
+
+    // Later shards, and the new rows of --add, skip the header, so the outputs can simply be
+    // concatenated.
+    let write_headers = args.shard.is_none_or(|shard| shard.is_first()) && args.add.is_none();
+
+    if args.bin_output.is_some() && !opts.format.is_row_based() {
+        return Err(Error::usage("--bin-output needs a row-based --format"));
+    }
+
+    let mut records = get_records(&args.in_file, &InputOptions::new(&args))?;
+    // The queries go first, so each one is x of its pairs.
+    let num_queries = match &args.query {
+        Some(path) => {
+            let queries = get_records(path, &InputOptions::new(&args))?;
+            if args.verbose {
+                eprintln!(
+                    "aligning {} queries against {} records",
+                    queries.len(),
+                    records.len()
+                );
+            }
+            let num_queries = queries.len();
+            records = queries.into_iter().chain(records).collect();
+            num_queries
+        }
+        None => 0,
+    };
+    let num_old_records = records.len();
+    if let Some(path) = &args.add {
+        records.extend(get_records(path, &InputOptions::new(&args))?);
+        if args.verbose {
+            eprintln!("adding {} new records", records.len() - num_old_records);
+        }
+    }
+    let (records, groups) = match (&args.group_by_desc, &args.pair_by_id_pattern, &args.groups) {
+        (Some(pattern), _, _) => {
+            let (records, groups) = groups::group_by_desc(records, pattern, args.skip_ungrouped);
+            (records, Some(groups))
+        }
+        (None, Some(pattern), _) => {
+            let (records, groups) = groups::group_by_id(records, pattern, args.skip_ungrouped);
+            (records, Some(groups))
+        }
+        (None, None, Some(table)) => {
+            let (records, groups) = table.group(records, args.skip_ungrouped);
+            (records, Some(groups))
+        }
+        (None, None, None) => (records, None),
+    };
+    if let (true, Some(groups)) = (args.verbose, &groups) {
+        let num_groups = groups.iter().max().map_or(0, |max| max + 1);
+        eprintln!("split {} records into {num_groups} groups", records.len());
+    }
+
+    // With --query, only the queries are x, so they're what the shards split.
+    let num_x_records = if args.query.is_some() {
+        num_queries
+    } else {
+        records.len()
+    };
+    let shard_records = match args.shard {
+        Some(shard) => {
+            let range = shard.records(num_x_records);
+            if args.verbose {
+                eprintln!(
+                    "shard {shard} has records {} to {} of {}",
+                    range.start + 1,
+                    range.end,
+                    num_x_records
+                );
+            }
+            range
+        }
+        None => 0..num_x_records,
+    };
+    // Without --add, every record is new, and with --query, only the input's records can be y.
+    let new_records = match (&args.add, &args.query) {
+        (Some(_), _) => num_old_records..records.len(),
+        (None, Some(_)) => num_queries..records.len(),
+        (None, None) => 0..records.len(),
+    };
+
+    let excluded_pairs = args
+        .exclude_pairs
+        .as_ref()
+        .map(|pairs| pairs.indices(&records));
+
+    if args.exact_match_only {
+        report_exact_duplicates(&records, args.verbose);
+    }
+
+    let conservation = match &args.conservation_ref {
+        Some(id) => {
+            let reference = records.iter().position(|r| r.id() == id).ok_or_else(|| {
+                Error::usage(format!("--conservation-ref {id} is not in the input"))
+            })?;
+            Some(Conservation::new(reference, records[reference].seq()))
+        }
+        None => None,
+    };
+
+    let clusters = args
+        .cluster
+        .map(|threshold| Clusters::new(records.len(), threshold, args.cluster_numbering));
+
+    let best_hits =
+        (args.best_hits.is_some() || args.rbh.is_some()).then(|| BestHits::new(records.len()));
+
+    if any_matrix_output && records.len() > args.matrix_max_n {
+        return Err(Error::usage(format!(
+            "the distance matrix would hold {n} x {n} pids in memory, and {n} records is over --matrix-max-n {}; write the rows instead (e.g., as tsv), or raise --matrix-max-n",
+            args.matrix_max_n,
+            n = records.len()
+        )));
+    }
+
+    let pids = (any_matrix_output || args.guide_msa.is_some() || args.matrix_binary.is_some())
+        .then(|| PidMatrix::new(records.len()));
+
+    if args.verbose {
+        eprintln!(
+            "gap penalties: open {}, extend {}",
+            -f64::from(args.gap_open()) / f64::from(args.score_scale()),
+            -f64::from(args.gap_extend()) / f64::from(args.score_scale())
+        );
+    }
+
+    let classes = match &args.class_file {
+        Some(table) => {
+            let (bonus, penalty) = (
+                i32::from(args.class_bonus) * args.score_scale(),
+                i32::from(args.class_penalty) * args.score_scale(),
+            );
+            Some(ClassScoring::new(table, &records, &matrix, bonus, penalty)?)
+        }
+        None if args.intein_motifs => {
+            let bonus = i32::from(args.intein_bonus);
+            let classes = intein::record_classes(&records);
+            Some(ClassScoring::from_classes(
+                classes,
+                &records,
+                &matrix,
+                bonus,
+                bonus,
+                Some(intein::UNCLASSED),
+            )?)
+        }
+        None => None,
+    };
+
+    check_alphabet(&args, &records)?;
+    if args.translate {
+        let num_protein = records
+            .iter()
+            .filter(|r| !r.seq().is_empty() && !looks_like_nucleotide(r.seq()))
+            .count();
+        if num_protein > 0 {
+            eprintln!("warning: --translate is for nucleotide sequences, but {num_protein} look like protein");
+        }
+    }
+
+    let outputs = output_formats
+        .into_iter()
+        .map(|(path, format)| Output::create(path, format))
+        .collect::<Vec<_>>();
+    outputs.iter().filter(|_| write_headers).for_each(|output| {
+        let out = &mut *output.out.lock().unwrap();
+        match output.format {
+            OutputFormat::Tsv => write_header(out, opts),
+            OutputFormat::Maf => write_maf_header(out),
+            _ => (),
+        }
+    });
+
+    let dotplot = args.dotplot.as_deref().map(Dotplot::new).transpose()?;
+
+    let bins = match &args.bin_output {
+        Some(dir) => {
+            let bins = Bins::new(dir, &args.bins)?;
+            if write_headers && opts.format == OutputFormat::Tsv {
+                bins.writers()
+                    .for_each(|out| write_header(&mut *out.lock().unwrap(), opts));
+            }
+            Some(bins)
+        }
+        None => None,
+    };
+
+    let collapsed = args.collapse_identical_output.then(|| {
+        let collapsed = Collapsed::new(&records);
+        if args.verbose {
+            eprintln!(
+                "collapsing {} records into earlier ones with the same sequence",
+                collapsed.num_collapsed()
+            );
+        }
+        collapsed
+    });
+
+    let entropies = args.entropy.then(|| {
+        records
+            .iter()
+            .map(|r| composition::entropy(r.seq()))
+            .collect()
+    });
+
+    let self_scores = args.max_score.then(|| {
+        records
+            .iter()
+            .map(|r| self_score(r.seq(), &matrix))
+            .collect()
+    });
+
+    let weights = args
+        .weights
+        .as_ref()
+        .map(|table| table.for_records(&records));
+
+    // Everything that looks records up by id is done, so they can be renamed.
+    let records = match args.index_file() {
+        Some(path) => index_records(records, &path),
+        None => records,
+    };
+
+    if let Some(path) = &args.composition {
+        composition::write(path, &records, args.precision);
+    }
+
+    let mut config = AlignerConfig {
+        gap_open: args.gap_open(),
+        gap_extend: args.gap_extend(),
+        matrix,
+        filter: None,
+        empty_pid: args.empty_pid,
+        pid_denominator: args.pid_denominator,
+    };
+    if let Some(max_pid) = args.max_pid {
+        config = config.filter("max-pid", move |result| result.percent_identity < max_pid);
+    }
+    if let Some(min_pid) = args.min_pid {
+        config = config.filter("min-pid", move |result| result.percent_identity >= min_pid);
+    }
+    if let Some(min_score) = args.min_score {
+        let min_score = min_score * args.score_scale();
+        config = config.filter("min-score", move |result| result.score >= min_score);
+    }
+    if args.exact_match_only {
+        let near_dup_pid = args.near_dup_pid;
+        config = config.filter("near-dup-pid", move |result| {
+            result.percent_identity >= near_dup_pid
+        });
+    }
+
+    let max_cells = args.per_alignment_timeout.map(|seconds| {
+        let max_cells = estimate_max_cells(&config, seconds);
+        if args.verbose {
+            eprintln!(
+                "per-alignment timeout allows up to an estimated {max_cells} DP cells per pair"
+            );
+        }
+        max_cells
+    });
+
+    let (block_results, stable_blocks) = match args.stable_block_size() {
+        None => (None, None),
+        Some(size) => {
+            let (s, r) = channel::unbounded();
+            (Some(s), Some((size, r)))
+        }
+    };
+
+    // Reversed, translated, and local self-hits aren't trivial, so they have to be aligned like
+    // everything else.
+    let align_self_hits = args.reversed || args.translate || args.mode == AlignMode::Local;
+    let include_self_hits = align_self_hits && !args.no_self_hits;
+
+    let context = Arc::new(Context {
+        config,
+        outputs,
+        bins,
+        opts,
+        clusters,
+        best_hits,
+        pids,
+        max_cells,
+        early_abort: args.min_pid.filter(|_| args.early_abort),
+        block_results,
+        low_pid: LowPidCounter::default(),
+        sorted: (args.sort_by.is_some() || args.min_cluster_size.is_some())
+            .then(|| Mutex::new(Vec::new())),
+        verifier: args.verify.map(Verifier::new),
+        warm_up: args.warm_up,
+        conservation,
+        dotplot,
+        classes,
+        histogram: args
+            .pid_histogram
+            .as_ref()
+            .map(|_| PidHistogram::new(args.hist_bins as usize)),
+        subst_counts: args
+            .subst_matrix
+            .as_ref()
+            .map(|_| Mutex::new(SubstCounts::default())),
+        stop_on_pid: args.stop_on_pid,
+        stopped: AtomicBool::new(false),
+        failed: Mutex::new(Vec::new()),
+        processed: AtomicUsize::new(0),
+        worker_load: args
+            .report_worker_load
+            .then(|| WorkerLoad::new(args.threads())),
+        progress: args.progress.then(|| {
+            Progress::new(progress::num_pairs(
+                shard_records.clone(),
+                new_records.clone(),
+                include_self_hits,
+            ))
+        }),
+        best_so_far: args.stream_best.then(BestSoFar::default),
+        grouped: any_grouped_output.then(|| GroupedHits::new(records.len())),
+        throttle: args
+            .control_file
+            .as_ref()
+            .map(|_| Throttle::new(args.threads())),
+        skip_log: args.log_skipped.as_deref().map(SkipLog::new),
+        translations: args
+            .translate
+            .then(|| Translations::new(&records, args.six_frame)),
+        collapsed,
+        weights,
+        self_scores,
+        entropies,
+        checkpointing: args.checkpoint_interval.is_some(),
+    });
+
+    let num_threads = args.threads();
+    let (rows, rows_receiver) = channel::bounded::<Rows>(256);
+    let checkpoint = args
+        .checkpoint_interval
+        .map(|interval| Checkpoint::new(&args.out_file, interval as usize));
+    let writer = spawn_writer(context.clone(), rows_receiver, checkpoint);
+    let Workers {
+        thread_handles,
+        sender,
+    } = set_up_workers(num_threads, context.clone(), rows);
+    let poller = args.control_file.clone().map(|path| {
+        let context = context.clone();
+        thread::spawn(move || {
+            if let Some(throttle) = &context.throttle {
+                throttle.poll(&path);
+            }
+        })
+    });
+    let reporter = context.progress.is_some().then(|| {
+        let context = context.clone();
+        thread::spawn(move || {
+            if let Some(progress) = &context.progress {
+                progress.report(&context.processed);
+            }
+        })
+    });
+
+    // Shared with every pair sent to the workers.
+    let records = Arc::new(records);
+
+    let self_hits = (!align_self_hits
+        && !args.no_self_hits
+        && !args.stream_best
+        && args.query.is_none()
+        && (any_row_output || context.grouped.is_some()))
+    .then(|| {
+        if args.add.is_some() {
+            new_records.clone()
+        } else {
+            shard_records.clone()
+        }
+    });
+    // Stable blocks write the self-hits in among the pairs instead.
+    if let (Some(self_hits), None) = (&self_hits, &stable_blocks) {
+        write_self_hits(&records, self_hits.clone(), &context);
+    }
+    let stable_blocks = stable_blocks.map(|(size, results)| StableBlocks {
+        size,
+        results,
+        context: &context,
+        self_hits: self_hits.map(|range| SelfHits {
+            records: &records,
+            context: &context,
+            next: range.start,
+            end: range.end,
+        }),
+    });
+    let num_sent = align_records(
+        &records,
+        sender,
+        include_self_hits,
+        stable_blocks,
+        args.max_cells.map(CellBudget::new),
+        |x_index, y_index| {
+            // x is always the earlier record, so its shard owns the pair, and if either record
+            // is new, y is.  Pairs of other shards, or of only old records, aren't skipped, just
+            // not this run's.
+            if !shard_records.contains(&x_index) || !new_records.contains(&y_index) {
+                return false;
+            }
+
+            let (x, y) = (&records[x_index], &records[y_index]);
+            let rejected_by = if groups.as_ref().is_some_and(|groups| {
+                x_index != y_index && (groups[x_index] == groups[y_index]) == args.between
+            }) {
+                Some(if args.between { "between" } else { "groups" })
+            } else if excluded_pairs
+                .as_ref()
+                .is_some_and(|excluded| excluded.contains(&(x_index, y_index)))
+            {
+                Some("exclude-pairs")
+            } else if args.exact_match_only && x.seq().len() != y.seq().len() {
+                Some("exact-match-only")
+            } else if context.collapsed.as_ref().is_some_and(|collapsed| {
+                collapsed.is_collapsed(x_index) || collapsed.is_collapsed(y_index)
+            }) {
+                Some("collapse-identical-output")
+            } else {
+                None
+            };
+
+            if let (Some(skip_log), Some(filter)) = (&context.skip_log, rejected_by) {
+                skip_log.log(x.id(), y.id(), filter);
+            }
+            rejected_by.is_none()
+        },
+    );
+    if let Some(throttle) = &context.throttle {
+        throttle.set_all_sent();
+    }
+    if let Some(progress) = &context.progress {
+        progress.set_total(num_sent);
+    }
+
+    // Wait for the threads to finish working, then for their rows to be written.
+    thread_handles.into_iter().for_each(|t| t.join().unwrap());
+    if let (Some(throttle), Some(poller)) = (&context.throttle, poller) {
+        throttle.finish();
+        poller.join().unwrap();
+    }
+    if let (Some(progress), Some(reporter)) = (&context.progress, reporter) {
+        progress.finish();
+        reporter.join().unwrap();
+    }
+    writer.join().unwrap();
+
+    context.low_pid.warn_if_degenerate();
+    if let Some(verifier) = &context.verifier {
+        verifier.report();
+    }
+    if let Some(load) = &context.worker_load {
+        load.report();
+    }
+    if let (Some(counts), Some(path)) = (&context.subst_counts, &args.subst_matrix) {
+        counts.lock().unwrap().write(path);
+    }
+    if let (Some(conservation), Some(path)) = (&context.conservation, &args.conservation) {
+        conservation.write(path, args.precision);
+    }
+    if let (Some(histogram), Some(path)) = (&context.histogram, &args.pid_histogram) {
+        histogram.write(path, args.precision);
+    }
+
+    if let Some(sorted) = &context.sorted {
+        let mut rows = sorted.lock().unwrap();
+        // Workers finish in any order, so put ties back in input order first.
+        rows.sort_by_key(|(result, _)| (result.x_index, result.y_index));
+        if let Some(sort_by) = args.sort_by {
+            rows.sort_by(|(a, _), (b, _)| sort_by.compare(a, b));
+        }
+
+        let large = match (&context.clusters, args.min_cluster_size) {
+            (Some(clusters), Some(min_size)) => Some(clusters.large(min_size as usize, &records)),
+            _ => None,
+        };
+        rows.iter()
+            .filter(|(result, _)| {
+                let keep = large.as_ref().is_none_or(|large| {
+                    large[result.x_index].is_some()
+                        && large[result.x_index] == large[result.y_index]
+                });
+                if let (false, Some(skip_log)) = (keep, &context.skip_log) {
+                    let (x, y) = (&records[result.x_index], &records[result.y_index]);
+                    skip_log.log(x.id(), y.id(), "min-cluster-size");
+                }
+                keep
+            })
+            .for_each(|(_, buffers)| write_buffers(&context.outputs, buffers));
+    }
+
+    if let (Some(clusters), Some(path)) = (&context.clusters, &args.cluster_out) {
+        clusters.write(path, &records);
+    }
+
+    if let Some(best_hits) = &context.best_hits {
+        if let Some(path) = &args.best_hits {
+            best_hits.write(path, &records, opts.precision);
+        }
+        if let Some(path) = &args.rbh {
+            best_hits.write_reciprocal(path, &records, opts.precision);
+        }
+    }
+
+    if let Some(grouped) = &context.grouped {
+        context
+            .outputs
+            .iter()
+            .filter(|output| output.format == OutputFormat::JsonGrouped)
+            .for_each(|output| grouped.write(&mut *output.out.lock().unwrap(), &records));
+    }
+
+    if let Some(pids) = &context.pids {
+        context
+            .outputs
+            .iter()
+            .filter(|output| output.format == OutputFormat::PhylipDist)
+            .for_each(|output| {
+                let out = &mut *output.out.lock().unwrap();
+                pids.write_phylip_distances(
+                    out,
+                    &records,
+                    opts.distance_correction,
+                    args.matrix_shape,
+                    opts.precision,
+                );
+            });
+        context
+            .outputs
+            .iter()
+            .filter(|output| output.format == OutputFormat::ScipyCondensed)
+            .for_each(|output| {
+                let out = &mut *output.out.lock().unwrap();
+                pids.write_condensed(out, opts.distance_correction, opts.precision);
+                pid_matrix::write_ids(&output.ids_path(), &records);
+            });
+
+        if let Some(path) = &args.matrix_binary {
+            pids.write_binary(path, &records);
+        }
+
+        if let Some(path) = &args.guide_msa {
+            let rows = msa::center_star(&records, pids.center(), &context.config);
+            msa::write(path, &records, &rows);
+        }
+    }
+
+    let failed = std::mem::take(&mut *context.failed.lock().unwrap());
+    // A --stop-on-pid hit leaves the rest of the queue on purpose.
+    let num_dropped = if context.stopped.load(atomic::Ordering::Relaxed) {
+        0
+    } else {
+        num_sent - context.processed.load(atomic::Ordering::Relaxed)
+    };
+    // Flush the outputs before exiting, so the pairs that did work are kept.
+    drop(context);
+    failed
+        .iter()
+        .for_each(|(x, y)| eprintln!("error: aligning {x} vs. {y} panicked"));
+    if !failed.is_empty() || num_dropped > 0 {
+        return Err(Error::MissingPairs {
+            failed: failed.len(),
+            dropped: num_dropped,
+        });
+    }
+
+    Ok(())
+}
+
+#[test]
+fn align_pair_reports_pid() {
+    let config = AlignerConfig::default();
+
+    let (alignment, pid) = align_pair(b"ACDEFGHIKL", b"ACDEFGHIKL", &config);
+    assert_eq!(alignment.operations.len(), 10);
+    assert_eq!(pid, 1.0);
+
+    let (_, pid) = align_pair(b"ACDEFGHIKL", b"ACDEFWHIKL", &config);
+    assert_eq!(pid, 0.9);
+}
+
+#[test]
+fn align_all_sorted_by_custom_key() {
+    let records = [
+        Record::with_attrs("a", None, b"ACDEFGHIKL"),
+        Record::with_attrs("b", None, b"ACDEFWHIKL"),
+        Record::with_attrs("c", None, b"ACDEFGHIKL"),
+    ];
+
+    let results = align_all_sorted_by(&records, &AlignerConfig::default(), |a, b| {
+        b.num_matches.cmp(&a.num_matches)
+    });
+
+    let pairs = results
+        .iter()
+        .map(|r| (r.x_index, r.y_index))
+        .collect::<Vec<_>>();
+    assert_eq!(pairs, vec![(0, 2), (0, 1), (1, 2)]);
+}
+
+#[test]
+fn align_all_keeps_what_the_filters_pass() {
+    let records = [
+        Record::with_attrs("a", None, b"ACDEFGHIKL"),
+        Record::with_attrs("b", None, b"ACDEFWHIKL"),
+        Record::with_attrs("c", None, b"ACDEFGHIKL"),
+    ];
+    let config = AlignerConfig::default()
+        .filter("not identical", |r| r.percent_identity < 1.0)
+        .filter("first", |r| r.x_index == 0);
+
+    let results = align_all(&records, &config);
+
+    let pairs = results
+        .iter()
+        .map(|r| (r.x_index, r.y_index))
+        .collect::<Vec<_>>();
+    assert_eq!(pairs, vec![(0, 1)]);
+
+    let identical = PairResult {
+        y_index: 2,
+        percent_identity: 1.0,
+        ..results[0]
+    };
+    assert_eq!(config.rejected_by(&identical), Some("not identical"));
+}
+
+#[test]
+fn self_score_sums_the_diagonal() {
+    let matrix = Matrix::blosum62();
+    assert_eq!(self_score(b"ACD", &matrix), 4 + 9 + 6);
+    assert_eq!(self_score(b"", &matrix), 0);
+}
+
+#[test]
+fn alignment_length_skips_clips() {
+    use AlignmentOperation::*;
+
+    let alignment = Alignment {
+        score: 0,
+        xstart: 2,
+        ystart: 0,
+        xend: 5,
+        yend: 3,
+        xlen: 7,
+        ylen: 3,
+        operations: vec![Xclip(2), Match, Subst, Match, Xclip(2)],
+        mode: AlignmentMode::Semiglobal,
+    };
+
+    assert_eq!(alignment_length(&alignment), 3);
+    assert_eq!(alignment_length_with_clips(&alignment), 5);
+}
+
+#[test]
+fn dual_ops_show_each_sequences_gaps() {
+    use AlignmentOperation::*;
+
+    let alignment = Alignment {
+        operations: vec![Match, Ins, Ins, Subst, Del, Match, Yclip(1)],
+        mode: AlignmentMode::Semiglobal,
+        ..Alignment::default()
+    };
+
+    let (x_ops, y_ops) = show_dual_ops(&alignment);
+    assert_eq!(x_ops, "MIIS-M-");
+    assert_eq!(y_ops, "M--SIMC");
+}
+
+#[test]
+fn nonstandard_columns_are_counted() {
+    use AlignmentOperation::*;
+
+    let alignment = Alignment {
+        operations: vec![Match, Subst, Ins, Match],
+        ..Alignment::default()
+    };
+
+    // The X column counts, but the gap column with the B doesn't.
+    assert_eq!(nonstandard_fraction(b"AXBC", b"AAC", &alignment), 0.25);
+}
+
+#[test]
+fn no_alignment_beats_the_max_possible_pid() {
+    let config = AlignerConfig::default();
+
+    assert_eq!(max_possible_pid(b"AACD", b"ACDDW"), 0.6);
+    assert!(max_possible_pid(b"", b"").is_nan());
+    for (x, y) in [
+        (&b"ACDEFGHIKL"[..], &b"LKIHGFEDCA"[..]),
+        (b"MKWVTFISLL", b"MKWVLLAFIS"),
+        (b"AAAAW", b"WAAAAAAAA"),
+    ] {
+        let (_, pid) = align_pair(x, y, &config);
+        assert!(pid <= max_possible_pid(x, y), "{pid}");
+    }
+}
+
+#[test]
+fn estimates_are_human_readable() {
+    assert_eq!(human_duration(42.04), "42.0s");
+    assert_eq!(human_duration(185.0), "3m 5s");
+    assert_eq!(human_duration(7800.0), "2h 10m");
+    assert_eq!(human_duration(370_800.0), "4d 7h");
+
+    assert_eq!(human_size(512.0), "512 B");
+    assert_eq!(human_size(1_500_000.0), "1.5 MB");
+    assert_eq!(human_size(2.3e12), "2.3 TB");
+}
+
+#[test]
+fn internal_alignment_length_trims_terminal_gaps() {
+    let config = AlignerConfig::default();
+
+    let (alignment, _) = align_pair(b"WWWACDEFGHIKL", b"ACDEFGHKL", &config);
+    assert_eq!(show_alignment_ops(&alignment), "IIIMMMMMMMIMM");
+    assert_eq!(alignment_length(&alignment), 13);
+    assert_eq!(internal_alignment_length(&alignment), 10);
+}
+
+#[test]
+fn internal_coords_trim_terminal_gaps_and_clips() {
+    use AlignmentOperation::*;
+
+    let alignment = Alignment {
+        operations: vec![Yclip(2), Del, Match, Ins, Subst, Match, Del, Ins],
+        ..Alignment::default()
+    };
+    let coords = Coords::internal(&alignment);
+    assert_eq!(
+        (coords.xstart, coords.xend, coords.ystart, coords.yend),
+        (0, 4, 3, 6)
+    );
+
+    let gaps_only = Alignment {
+        operations: vec![Ins, Del],
+        ..Alignment::default()
+    };
+    let coords = Coords::internal(&gaps_only);
+    assert_eq!(
+        (coords.xstart, coords.xend, coords.ystart, coords.yend),
+        (0, 0, 0, 0)
+    );
+}
+
+#[test]
+fn full_length_allows_a_tolerance() {
+    let coords = Coords {
+        xstart: 0,
+        xend: 95,
+        ystart: 10,
+        yend: 110,
+    };
+
+    assert!(coords.cover(100, 110, 0.1));
+    assert!(!coords.cover(100, 110, 0.05));
+    assert!(!Coords::full_length(10, 10).cover(10, 20, 0.0));
+}
+
+#[test]
+fn segments_are_split_by_gaps_and_clips() {
+    let config = AlignerConfig::default();
+
+    // IIIMMMMMMMIMM
+    let (alignment, _) = align_pair(b"WWWACDEFGHIKL", b"ACDEFGHKL", &config);
+    assert_eq!(count_segments(&alignment), 2);
+
+    let mut alignment = alignment;
+    alignment.operations.insert(5, AlignmentOperation::Yclip(4));
+    assert_eq!(count_segments(&alignment), 3);
+}
+
+#[test]
+fn gap_stats_are_over_the_gap_runs() {
+    use AlignmentOperation::*;
+
+    let alignment = Alignment {
+        operations: vec![Ins, Ins, Ins, Match, Del, Subst, Del, Ins, Match],
+        ..Alignment::default()
+    };
+    assert_eq!(gap_lengths(&alignment), vec![3, 1, 1, 1]);
+    assert_eq!(
+        GapStats::from_alignment(&alignment),
+        GapStats { mean: 1.5, max: 3 }
+    );
+
+    let ungapped = Alignment {
+        operations: vec![Match, Subst],
+        ..Alignment::default()
+    };
+    assert_eq!(GapStats::from_alignment(&ungapped), GapStats::default());
+}
+
+#[test]
+fn tied_tracebacks_are_reproducible() {
+    // The gap can go anywhere in the run of A's, so there are many co-optimal alignments.
+    let (x, y) = (b"MKAAAAAAWV".as_slice(), b"MKAAAAWV".as_slice());
+    let config = AlignerConfig::default();
+
+    let (expected, _) = align_pair(x, y, &config);
+    let expected = show_alignment_ops(&expected);
+
+    // A reused aligner (as in the workers) should give the same ops as a fresh one.
+    let score = |a: u8, b: u8| config.matrix.score(a, b);
+    let mut aligner = Aligner::new(config.gap_open, config.gap_extend, &score);
+    for _ in 0..3 {
+        aligner.global(b"ACDEFGHIKLMNPQRSTVWY", b"YWVTSRQPNMLKIHGFEDCA");
+        assert_eq!(show_alignment_ops(&aligner.global(x, y)), expected);
+    }
+}
+
+#[test]
+fn local_and_semiglobal_alignments_keep_their_clips() {
+    let (x, y) = (b"WWWWMKVLAAGHCRDEWWWW", b"PPPMKVLAAGHCRDEPPPPP");
+    let (x_record, y_record) = (
+        Record::with_attrs("x", None, x),
+        Record::with_attrs("y", None, y),
+    );
+    let config = AlignerConfig::default();
+    let score = |a: u8, b: u8| config.matrix.score(a, b);
+
+    let scoring = Scoring::new(config.gap_open, config.gap_extend, &score);
+    let mut aligner = Aligner::with_scoring(scoring.xclip(0).yclip(0));
+    let alignment = align_by_mode(&mut aligner, x, y, AlignMode::Local);
+    assert_mode(&x_record, &y_record, &alignment, AlignMode::Local);
+    assert_eq!(
+        Coords::from_alignment(&alignment).show(CoordBase::Zero),
+        "4\t16\t3\t15"
+    );
+    assert_eq!(
+        (alignment_length(&alignment), count_identities(&alignment)),
+        (12, 12)
+    );
+    assert_eq!(
+        Annotations::from_alignment(&alignment).x,
+        "----............----"
+    );
+
+    let scoring = Scoring::new(config.gap_open, config.gap_extend, &score);
+    let mut aligner = Aligner::with_scoring(scoring.yclip(0));
+    let alignment = align_by_mode(&mut aligner, x, y, AlignMode::Semiglobal);
+    assert_mode(&x_record, &y_record, &alignment, AlignMode::Semiglobal);
+    assert_eq!(Annotations::from_alignment(&alignment).x.len(), x.len());
+    assert_eq!(Annotations::from_alignment(&alignment).y.len(), y.len());
+}
+
+#[test]
+fn annotations_follow_each_sequence() {
+    let (alignment, _) = align_pair(b"WWWACDEFGHIKL", b"ACDEFGHKL", &AlignerConfig::default());
+    assert_eq!(show_alignment_ops(&alignment), "IIIMMMMMMMIMM");
+
+    let annotations = Annotations::from_alignment(&alignment);
+    assert_eq!(annotations.x, "---.......-..");
+    assert_eq!(annotations.y, ".........");
+}
+
+#[test]
+fn pid_denominators_count_different_columns() {
+    let pid = |pid_denominator| {
+        let config = AlignerConfig {
+            pid_denominator,
+            ..AlignerConfig::default()
+        };
+        align_pair(b"ACDEFGHIKL", b"ACDEWGHI", &config).1
+    };
+
+    // 7 matches, 1 mismatch, and 2 gaps.
+    assert_eq!(pid(PidDenominator::AlnLen), 0.7);
+    assert_eq!(pid(PidDenominator::Ungapped), 7.0 / 8.0);
+    assert_eq!(pid(PidDenominator::ShorterSeq), 7.0 / 8.0);
+}
+
+#[test]
+fn empty_alignments_get_the_empty_pid() {
+    let config = AlignerConfig::default();
+    let (alignment, pid) = align_pair(b"", b"", &config);
+    assert_eq!(alignment_length(&alignment), 0);
+    assert_eq!(pid, 0.0);
+
+    let config = AlignerConfig {
+        empty_pid: EmptyPid::One,
+        ..AlignerConfig::default()
+    };
+    assert_eq!(align_pair(b"", b"", &config).1, 1.0);
+    assert!(percent_identity(0, 0, EmptyPid::Nan).is_nan());
+    assert_eq!(percent_identity(4, 3, EmptyPid::Nan), 0.75);
+}
+
+#[test]
+fn long_gaps_are_realigned_away() {
+    use AlignmentOperation::*;
+
+    let alignment = Alignment {
+        operations: vec![Match, Ins, Ins, Match, Del, Del, Del, Ins, Match],
+        ..Alignment::default()
+    };
+    assert_eq!(longest_gap(&alignment), 3);
+
+    let config = AlignerConfig::default();
+    let (x, y) = (b"ACDEFGHIKLMNPQRSTVWY", b"ACDEFGHIKLWMNPQRSTVWY");
+    let (alignment, _) = align_pair(x, y, &config);
+    assert_eq!(
+        fit_gaps(x, y, &config, 1, alignment.clone()).1,
+        GapLimit::Ok
+    );
+
+    let y = b"ACDEFGHIKLMNPQRSTVWYACDEFGHIKLMNPQRSTVWY";
+    let (alignment, _) = align_pair(x, y, &config);
+    assert_eq!(fit_gaps(x, y, &config, 5, alignment).1, GapLimit::Exceeded);
+}
+
+#[test]
+fn only_new_bests_beat_the_best_so_far() {
+    let best = BestSoFar::default();
+
+    assert!(best.beats(0.3));
+    assert!(!best.beats(0.3));
+    assert!(!best.beats(0.1));
+    assert!(best.beats(0.8));
+    assert!(!best.beats(f64::NAN));
 }
@@ -0,0 +1,106 @@
+//! Masking low-complexity regions with X before aligning, for `--mask-low-complexity`.
+//!
+//! This is a simpler take on SEG (Wootton and Federhen 1993), with its default window and
+//! trigger cutoff: windows of 12 residues with a Shannon entropy under 2.2 bits are joined into
+//! regions, then each region is trimmed from the ends while that lowers its entropy, so that the
+//! ordinary residues around a repeat aren't masked along with it.
+
+use crate::composition::entropy;
+use bio::alignment::{Alignment, AlignmentOperation};
+
+/// The length of the windows.
+const WINDOW: usize = 12;
+
+/// Windows with less entropy than this, in bits, are masked.
+const TRIGGER: f64 = 2.2;
+
+/// Replace the residues of each low-complexity region of `seq` with X, returning how many were
+/// masked.  Sequences shorter than a window are left alone.
+pub fn mask_low_complexity(seq: &mut [u8]) -> usize {
+    let mut low = vec![false; seq.len()];
+    for (start, window) in seq.windows(WINDOW).enumerate() {
+        if entropy(window) < TRIGGER {
+            low[start..start + WINDOW].fill(true);
+        }
+    }
+
+    let mut regions = Vec::new();
+    let mut i = 0;
+    while i < seq.len() {
+        if low[i] {
+            let start = i;
+            while i < seq.len() && low[i] {
+                i += 1;
+            }
+            regions.push(trim(seq, start, i));
+        }
+        i += 1;
+    }
+
+    regions
+        .into_iter()
+        .map(|(start, end)| {
+            seq[start..end].fill(b'X');
+            end - start
+        })
+        .sum()
+}
+
+/// Drop residues from either end of `seq[start..end]` while that lowers its entropy, returning
+/// what's left.
+fn trim(seq: &[u8], mut start: usize, mut end: usize) -> (usize, usize) {
+    while end - start > 1 {
+        let current = entropy(&seq[start..end]);
+        let without_first = entropy(&seq[start + 1..end]);
+        let without_last = entropy(&seq[start..end - 1]);
+
+        if without_first < current && without_first <= without_last {
+            start += 1;
+        } else if without_last < current {
+            end -= 1;
+        } else {
+            break;
+        }
+    }
+
+    (start, end)
+}
+
+/// Make the X vs. X columns of `alignment` mismatches, so masked regions don't count as
+/// identities.  `x` is the aligned x sequence.
+pub fn unmatch_masked(alignment: &mut Alignment, x: &[u8]) {
+    let mut i = 0;
+
+    for op in alignment.operations.iter_mut() {
+        match op {
+            AlignmentOperation::Match => {
+                if x[i] == b'X' {
+                    *op = AlignmentOperation::Subst;
+                }
+                i += 1;
+            }
+            AlignmentOperation::Subst | AlignmentOperation::Ins => i += 1,
+            AlignmentOperation::Xclip(n) => i += *n,
+            AlignmentOperation::Del | AlignmentOperation::Yclip(_) => (),
+        }
+    }
+}
+
+#[test]
+fn low_complexity_windows_are_masked() {
+    let mut seq = b"MKWVTFISLLQQQQQQQQQQQQQQHCRDEFGAYNPWKTILM".to_vec();
+    assert_eq!(mask_low_complexity(&mut seq), 14);
+    // Without trimming, the windows would take 5 residues on each side too.
+    assert_eq!(&seq, b"MKWVTFISLLXXXXXXXXXXXXXXHCRDEFGAYNPWKTILM");
+
+    let mut seq = b"QQQQQ".to_vec();
+    assert_eq!(mask_low_complexity(&mut seq), 0);
+
+    use AlignmentOperation::*;
+    let mut alignment = Alignment {
+        operations: vec![Match, Del, Match, Ins, Match],
+        ..Alignment::default()
+    };
+    unmatch_masked(&mut alignment, b"AXXC");
+    assert_eq!(alignment.operations, vec![Match, Del, Subst, Ins, Match]);
+}
@@ -0,0 +1,109 @@
+//! A rough center-star multiple sequence alignment, built from the pairwise alignments.
+
+use crate::AlignerConfig;
+use bio::alignment::pairwise::Aligner;
+use bio::alignment::AlignmentOperation;
+use bio::io::fasta::{Record, Writer};
+use std::path::Path;
+
+const GAP: u8 = b'-';
+
+/// Align every record to `records[center]` and merge the pairwise alignments.
+///
+/// Each pairwise alignment is kept as is, so gaps that any sequence needs in the center are
+/// added to every other row.  Rows are in input order.
+pub fn center_star(records: &[Record], center: usize, config: &AlignerConfig) -> Vec<Vec<u8>> {
+    let score = |a: u8, b: u8| config.matrix.score(a, b);
+    let mut aligner = Aligner::new(config.gap_open, config.gap_extend, &score);
+
+    let c = records[center].seq();
+
+    let alignments = records
+        .iter()
+        .map(|record| aligner.global(c, record.seq()).operations)
+        .collect::<Vec<_>>();
+
+    // The most residues any sequence has before each center position (and after the last one).
+    let mut max_inserts = vec![0; c.len() + 1];
+    for ops in &alignments {
+        let mut p = 0;
+        let mut inserts = 0;
+        for op in ops {
+            match op {
+                AlignmentOperation::Del => inserts += 1,
+                _ => {
+                    max_inserts[p] = max_inserts[p].max(inserts);
+                    inserts = 0;
+                    p += 1;
+                }
+            }
+        }
+        max_inserts[p] = max_inserts[p].max(inserts);
+    }
+
+    records
+        .iter()
+        .zip(&alignments)
+        .map(|(record, ops)| {
+            let seq = record.seq();
+            let mut row = Vec::with_capacity(c.len() + max_inserts.iter().sum::<usize>());
+            let (mut p, mut j) = (0, 0);
+            let mut inserted = 0;
+
+            for op in ops {
+                match op {
+                    AlignmentOperation::Del => {
+                        row.push(seq[j]);
+                        j += 1;
+                        inserted += 1;
+                    }
+                    _ => {
+                        row.extend(std::iter::repeat_n(GAP, max_inserts[p] - inserted));
+                        inserted = 0;
+                        if *op == AlignmentOperation::Ins {
+                            row.push(GAP);
+                        } else {
+                            row.push(seq[j]);
+                            j += 1;
+                        }
+                        p += 1;
+                    }
+                }
+            }
+            row.extend(std::iter::repeat_n(GAP, max_inserts[p] - inserted));
+
+            row
+        })
+        .collect()
+}
+
+/// Write the MSA rows as aligned FASTA.
+pub fn write(path: &Path, records: &[Record], rows: &[Vec<u8>]) {
+    let mut writer = Writer::to_file(path).unwrap();
+
+    records.iter().zip(rows).for_each(|(record, row)| {
+        writer.write(record.id(), None, row).unwrap();
+    });
+}
+
+#[test]
+fn rows_line_up_with_the_center() {
+    let records = [
+        Record::with_attrs("center", None, b"ACDEFGHIKL"),
+        Record::with_attrs("insert", None, b"ACDEFWWWGHIKL"),
+        Record::with_attrs("delete", None, b"ACDEIKL"),
+    ];
+
+    let rows = center_star(&records, 0, &AlignerConfig::default());
+
+    assert!(rows.iter().all(|row| row.len() == 13));
+    for (record, row) in records.iter().zip(&rows) {
+        let ungapped = row
+            .iter()
+            .filter(|&&c| c != GAP)
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(ungapped, record.seq());
+    }
+    assert_eq!(rows[0], b"ACDEF---GHIKL");
+}
@@ -0,0 +1,1265 @@
+//! Output files and the row formats written to them.
+
+use crate::cli::{CoordBase, Direction, EdgeWeight, OutputFormat};
+use crate::error::{Error, Result};
+use crate::expr::Var;
+use crate::{AlignmentInfo, Coords, OutputOptions};
+use bio::io::fasta::Record;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Every column the tsv and jsonl output can have, which the `--compute` columns can't reuse.
+pub const COLUMN_NAMES: [&str; 45] = [
+    "x",
+    "y",
+    "xlen",
+    "ylen",
+    "alnlen",
+    "matches",
+    "pid",
+    "dist",
+    "kmer_cosine",
+    "kmer_dist",
+    "xstart",
+    "xend",
+    "ystart",
+    "yend",
+    "pid_over_x",
+    "pid_over_y",
+    "longer",
+    "score",
+    "score_norm",
+    "max_score",
+    "frac_max_score",
+    "edit_distance",
+    "internal_alnlen",
+    "internal_pid",
+    "pid_trimmed",
+    "segments",
+    "gap_limit",
+    "mean_gap_len",
+    "max_gap_len",
+    "len_diff",
+    "full_length",
+    "frame",
+    "low_confidence",
+    "pair_weight",
+    "x_entropy",
+    "y_entropy",
+    "offset",
+    "is_self",
+    "reversed",
+    "annotation",
+    "x_collapsed",
+    "y_collapsed",
+    "x_ops",
+    "y_ops",
+    "ops",
+];
+
+/// An alignment score, back in the units of the matrix and gap penalties.
+///
+/// Scores are multiplied by `scale` while aligning when a gap penalty has a fraction (see
+/// `AlignArgs::score_scale`), so they're written with a decimal place only then.
+#[derive(Copy, Clone)]
+pub struct Score {
+    pub score: i32,
+    pub scale: i32,
+}
+
+impl Score {
+    pub fn value(self) -> f64 {
+        f64::from(self.score) / f64::from(self.scale)
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.scale == 1 {
+            write!(f, "{}", self.score)
+        } else {
+            write!(f, "{}", self.value())
+        }
+    }
+}
+
+/// Where the ids of the scipy-condensed output at `path` go, i.e., `path` with .ids on the end.
+pub fn ids_path(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf().into_os_string();
+    path.push(".ids");
+    path.into()
+}
+
+/// One output file and its format.
+pub struct Output {
+    pub path: PathBuf,
+    pub format: OutputFormat,
+    pub out: Mutex<BufWriter<File>>,
+    /// The output is a named pipe, so rows are flushed as soon as each pair is written.
+    stream: bool,
+}
+
+impl Output {
+    /// Opening a named pipe blocks until something opens it for reading.
+    pub fn create(path: &Path, format: OutputFormat) -> Self {
+        let stream = is_named_pipe(path);
+        if stream {
+            eprintln!("waiting for a reader on {}", path.display());
+        }
+        let file = File::create(path).unwrap();
+
+        Self {
+            path: path.to_path_buf(),
+            format,
+            out: Mutex::new(BufWriter::new(file)),
+            stream,
+        }
+    }
+
+    /// Where the ids of a scipy-condensed output go, i.e., its path with .ids on the end.
+    pub fn ids_path(&self) -> PathBuf {
+        ids_path(&self.path)
+    }
+
+    /// Flush a streamed output, exiting if the reader has gone away.
+    fn flush_stream(&self, out: &mut BufWriter<File>) {
+        if !self.stream {
+            return;
+        }
+
+        match out.flush() {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {
+                eprintln!("error: the reader of the output pipe went away");
+                std::process::exit(1);
+            }
+            Err(e) => panic!("couldn't write the output: {e}"),
+        }
+    }
+
+    /// Guess the format of an `--out` file from its extension.
+    pub fn infer_format(path: &Path) -> Result<OutputFormat> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("tsv") => Ok(OutputFormat::Tsv),
+            Some("jsonl") => Ok(OutputFormat::Jsonl),
+            Some("json") => Ok(OutputFormat::JsonGrouped),
+            Some("blast6" | "m8") => Ok(OutputFormat::Blast6),
+            Some("maf") => Ok(OutputFormat::Maf),
+            Some("sto" | "stk" | "stockholm") => Ok(OutputFormat::Stockholm),
+            Some("bed") => Ok(OutputFormat::Bed),
+            Some("edges") => Ok(OutputFormat::Edgelist),
+            Some("pair") => Ok(OutputFormat::Pairwise),
+            Some("phy" | "phylip") => Ok(OutputFormat::PhylipDist),
+            Some("condensed") => Ok(OutputFormat::ScipyCondensed),
+            _ => Err(Error::UnknownFormat(path.to_path_buf())),
+        }
+    }
+}
+
+/// Is `path` an existing named pipe (FIFO)?
+pub fn is_named_pipe(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        std::fs::metadata(path).is_ok_and(|m| m.file_type().is_fifo())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// Print the rows for a pair to every row-based output.
+///
+/// If `buffer` is true, nothing is written.  Instead, the rows are returned, one buffer per
+/// output (empty for the other outputs), to be written later with `write_buffers`.
+pub fn print_to_outputs(
+    outputs: &[Output],
+    x: &Record,
+    y: &Record,
+    info: &AlignmentInfo,
+    self_hit: bool,
+    opts: OutputOptions,
+    buffer: bool,
+) -> Vec<Vec<u8>> {
+    let mut buffers = Vec::new();
+
+    for output in outputs {
+        let mut buf = Vec::new();
+        if output.format.is_row_based() {
+            let opts = OutputOptions {
+                format: output.format,
+                ..opts
+            };
+            if buffer {
+                print_alignment_info(&mut buf, x, y, info, self_hit, opts);
+            } else {
+                let out = &mut *output.out.lock().unwrap();
+                print_alignment_info(out, x, y, info, self_hit, opts);
+                output.flush_stream(out);
+            }
+        }
+        if buffer {
+            buffers.push(buf);
+        }
+    }
+
+    buffers
+}
+
+/// Write rows returned by `print_to_outputs`.
+pub fn write_buffers(outputs: &[Output], buffers: &[Vec<u8>]) {
+    outputs.iter().zip(buffers).for_each(|(output, buf)| {
+        let out = &mut *output.out.lock().unwrap();
+        out.write_all(buf).unwrap();
+        if !buf.is_empty() {
+            output.flush_stream(out);
+        }
+    });
+}
+
+/// The is_self column, if it should be shown.
+fn show_is_self(self_hit: bool, opts: OutputOptions) -> &'static str {
+    match (opts.mark_self, self_hit) {
+        (false, _) => "",
+        (true, true) => "\ttrue",
+        (true, false) => "\tfalse",
+    }
+}
+
+/// Print one line for an alignment-free comparison.
+///
+/// Only the k-mer similarity (stored as the percent identity) is meaningful.
+fn print_kmer_line(
+    out: &mut impl Write,
+    x: &Record,
+    y: &Record,
+    info: &AlignmentInfo,
+    _coords: Coords,
+    self_hit: bool,
+    opts: OutputOptions,
+) {
+    let similarity = if opts.distance {
+        1.0 - info.percent_identity
+    } else {
+        info.percent_identity
+    };
+
+    let is_self = show_is_self(self_hit, opts);
+
+    writeln!(
+        out,
+        "{}\t{}\t{}\t{}\t{similarity:.prec$}{is_self}",
+        x.id(),
+        y.id(),
+        x.seq().len(),
+        y.seq().len(),
+        prec = opts.precision
+    )
+    .unwrap();
+}
+
+/// Print one line with info for alignment.
+fn print_alignment_info_line(
+    out: &mut impl Write,
+    x: &Record,
+    y: &Record,
+    info: &AlignmentInfo,
+    coords: Coords,
+    self_hit: bool,
+    opts: OutputOptions,
+) {
+    let x_name = x.id();
+    let y_name = y.id();
+
+    let x_len = x.seq().len();
+    let y_len = y.seq().len();
+
+    let AlignmentInfo {
+        aln_len,
+        num_matches,
+        percent_identity,
+        score,
+        edit_distance,
+        internal_aln_len,
+        aln_ops,
+        ..
+    } = info;
+
+    let pid = if opts.distance {
+        opts.distance_correction.distance(*percent_identity)
+    } else {
+        *percent_identity
+    };
+
+    // Where y starts on x, for ungapped alignments.
+    let offset = if opts.ungapped {
+        format!("\t{}", coords.xstart as isize - coords.ystart as isize)
+    } else {
+        "".to_string()
+    };
+
+    let coords = match opts.coord_base {
+        None => "".to_string(),
+        Some(coord_base) => format!("\t{}", coords.show(coord_base)),
+    };
+
+    let extra_pid = if opts.extra_pid {
+        let pid_over_x = f64::from(*num_matches) / x_len as f64;
+        let pid_over_y = f64::from(*num_matches) / y_len as f64;
+        format!(
+            "\t{pid_over_x:.prec$}\t{pid_over_y:.prec$}",
+            prec = opts.precision
+        )
+    } else {
+        "".to_string()
+    };
+
+    let score_norm = if opts.score_norm {
+        let score = opts.score(*score);
+        let norm = score.value() / *aln_len as f64;
+        format!("\t{score}\t{norm:.prec$}", prec = opts.precision)
+    } else {
+        "".to_string()
+    };
+
+    let max_score = match info.max_score {
+        Some(max_score) => {
+            let frac = f64::from(*score) / f64::from(max_score);
+            let max_score = opts.score(max_score);
+            format!("\t{max_score}\t{frac:.prec$}", prec = opts.precision)
+        }
+        None => "".to_string(),
+    };
+
+    let edit_distance = if opts.edit_distance {
+        format!("\t{edit_distance}")
+    } else {
+        "".to_string()
+    };
+
+    let internal = if opts.internal_aln_len {
+        let internal_pid = f64::from(*num_matches) / f64::from(*internal_aln_len);
+        format!(
+            "\t{internal_aln_len}\t{internal_pid:.prec$}",
+            prec = opts.precision
+        )
+    } else {
+        "".to_string()
+    };
+
+    let pid_trimmed = if opts.pid_trimmed {
+        let pid_trimmed = crate::percent_identity(*internal_aln_len, *num_matches, opts.empty_pid);
+        format!("\t{pid_trimmed:.prec$}", prec = opts.precision)
+    } else {
+        "".to_string()
+    };
+
+    let segments = match info.segments {
+        Some(segments) => format!("\t{segments}"),
+        None => "".to_string(),
+    };
+
+    let gap_limit = match info.gap_limit {
+        Some(gap_limit) => format!("\t{}", gap_limit.as_str()),
+        None => "".to_string(),
+    };
+
+    let gap_stats = match info.gap_stats {
+        Some(stats) => format!(
+            "\t{:.prec$}\t{}",
+            stats.mean,
+            stats.max,
+            prec = opts.precision
+        ),
+        None => "".to_string(),
+    };
+
+    let len_diff = if opts.len_diff {
+        let longer = match x_len.cmp(&y_len) {
+            std::cmp::Ordering::Greater => "x",
+            std::cmp::Ordering::Less => "y",
+            std::cmp::Ordering::Equal => "equal",
+        };
+        format!("\t{}\t{longer}", x_len.abs_diff(y_len))
+    } else {
+        "".to_string()
+    };
+
+    let full_length = match info.full_length {
+        Some(full_length) => format!("\t{full_length}"),
+        None => "".to_string(),
+    };
+
+    let frame = match info.frames {
+        Some((x_frame, y_frame)) => format!("\t{x_frame}/{y_frame}"),
+        None => "".to_string(),
+    };
+
+    let low_confidence = match info.low_confidence {
+        Some(low_confidence) => format!("\t{low_confidence}"),
+        None => "".to_string(),
+    };
+
+    let pair_weight = match info.pair_weight {
+        Some(weight) => format!("\t{weight:.prec$}", prec = opts.precision),
+        None => "".to_string(),
+    };
+
+    let entropy = match info.entropy {
+        Some((x_entropy, y_entropy)) => format!(
+            "\t{x_entropy:.prec$}\t{y_entropy:.prec$}",
+            prec = opts.precision
+        ),
+        None => "".to_string(),
+    };
+
+    let is_self = show_is_self(self_hit, opts);
+
+    let reversed = if opts.reversed { "\ttrue" } else { "" };
+
+    let annotation = match (&info.annotations, opts.annotate_on) {
+        (Some(annotations), Some(annotate_on)) => format!("\t{}", annotations.on(annotate_on)),
+        _ => "".to_string(),
+    };
+
+    let collapsed = match &info.collapsed {
+        Some((x_ids, y_ids)) => format!("\t{x_ids}\t{y_ids}"),
+        None => "".to_string(),
+    };
+
+    let computed = opts
+        .computed
+        .iter()
+        .map(|column| {
+            let value = column.eval(|var| computed_var(var, x_len, y_len, info, opts));
+            if value.is_finite() {
+                format!("\t{value:.prec$}", prec = opts.precision)
+            } else {
+                "\tNA".to_string()
+            }
+        })
+        .collect::<String>();
+
+    let dual_ops = match &info.dual_ops {
+        Some((x_ops, y_ops)) => format!("\t{x_ops}\t{y_ops}"),
+        None => "".to_string(),
+    };
+
+    let aln_ops = match aln_ops {
+        Some(ops) if opts.show_aln_ops => format!("\t{ops}"),
+        _ => "".to_string(),
+    };
+
+    writeln!(
+        out,
+        "{x_name}\t{y_name}\t{x_len}\t{y_len}\t{aln_len}\t{num_matches}\t{pid:.prec$}{coords}{extra_pid}{score_norm}{max_score}{edit_distance}{internal}{pid_trimmed}{segments}{gap_limit}{gap_stats}{len_diff}{full_length}{frame}{low_confidence}{pair_weight}{entropy}{offset}{is_self}{reversed}{annotation}{collapsed}{computed}{dual_ops}{aln_ops}",
+        prec = opts.precision
+    )
+    .unwrap();
+}
+
+/// The value of `var` for a `--compute` column.  The lengths are passed in, since they depend on
+/// which way round the row is.
+fn computed_var(
+    var: Var,
+    x_len: usize,
+    y_len: usize,
+    info: &AlignmentInfo,
+    opts: OutputOptions,
+) -> f64 {
+    match var {
+        Var::XLen => x_len as f64,
+        Var::YLen => y_len as f64,
+        Var::AlnLen => f64::from(info.aln_len),
+        Var::Matches => f64::from(info.num_matches),
+        Var::Mismatches => f64::from(info.num_mismatches),
+        Var::Gaps => f64::from(info.num_gap_opens),
+        Var::Score => opts.score(info.score).value(),
+        Var::Pid => info.percent_identity,
+    }
+}
+
+/// Print one BED line, with y as the reference and x as the query.
+fn print_bed_line(
+    out: &mut impl Write,
+    x: &Record,
+    y: &Record,
+    info: &AlignmentInfo,
+    coords: Coords,
+    _self_hit: bool,
+    _opts: OutputOptions,
+) {
+    // BED scores are integers from 0 to 1000.  A NaN pid (see --empty-pid) scores 0.
+    let score = if info.percent_identity.is_nan() {
+        0
+    } else {
+        (1000.0 * info.percent_identity).round().clamp(0.0, 1000.0) as u32
+    };
+
+    writeln!(
+        out,
+        "{}\t{}\t{}\t{}\t{score}\t.",
+        y.id(),
+        coords.ystart,
+        coords.yend,
+        x.id()
+    )
+    .unwrap();
+}
+
+/// Print one line in BLAST's tabular (-outfmt 6) format.
+fn print_blast6_line(
+    out: &mut impl Write,
+    x: &Record,
+    y: &Record,
+    info: &AlignmentInfo,
+    coords: Coords,
+    _self_hit: bool,
+    opts: OutputOptions,
+) {
+    let pident = 100.0 * info.percent_identity;
+    let (qstart, qend) = CoordBase::One.convert(coords.xstart, coords.xend);
+    let (sstart, send) = CoordBase::One.convert(coords.ystart, coords.yend);
+
+    writeln!(
+        out,
+        "{}\t{}\t{pident:.prec$}\t{}\t{}\t{}\t{qstart}\t{qend}\t{sstart}\t{send}\t0\t{}",
+        x.id(),
+        y.id(),
+        info.aln_len,
+        info.num_mismatches,
+        info.num_gap_opens,
+        opts.score(info.score),
+        prec = opts.precision
+    )
+    .unwrap();
+}
+
+/// The aligned rows of x and y, with `-` for gaps, rebuilt from the ops.  Clipped residues are
+/// left out.
+fn gapped_rows(x: &[u8], y: &[u8], ops: &str) -> (String, String) {
+    let mut x_row = String::with_capacity(ops.len());
+    let mut y_row = String::with_capacity(ops.len());
+    let (mut i, mut j) = (0, 0);
+
+    for op in ops.chars() {
+        match op {
+            'M' | 'S' => {
+                x_row.push(char::from(x[i]));
+                y_row.push(char::from(y[j]));
+                i += 1;
+                j += 1;
+            }
+            'I' => {
+                x_row.push(char::from(x[i]));
+                y_row.push('-');
+                i += 1;
+            }
+            'D' => {
+                x_row.push('-');
+                y_row.push(char::from(y[j]));
+                j += 1;
+            }
+            'X' => i += 1,
+            'Y' => j += 1,
+            _ => unreachable!("unknown op {op}"),
+        }
+    }
+
+    (x_row, y_row)
+}
+
+/// Print one edgelist line, unless its weight is below --min-edge-weight.
+fn print_edge(
+    out: &mut impl Write,
+    x: &Record,
+    y: &Record,
+    info: &AlignmentInfo,
+    opts: OutputOptions,
+) {
+    let weight = match opts.edge_weight {
+        EdgeWeight::Pid => info.percent_identity,
+        EdgeWeight::Score => opts.score(info.score).value(),
+    };
+    if opts.min_edge_weight.is_some_and(|min| weight < min) {
+        return;
+    }
+
+    match opts.edge_weight {
+        EdgeWeight::Pid => {
+            writeln!(
+                out,
+                "{} {} {weight:.prec$}",
+                x.id(),
+                y.id(),
+                prec = opts.precision
+            )
+        }
+        EdgeWeight::Score => writeln!(out, "{} {} {}", x.id(), y.id(), opts.score(info.score)),
+    }
+    .unwrap();
+}
+
+/// Print one MAF alignment block.
+fn print_maf_block(
+    out: &mut impl Write,
+    x: &Record,
+    y: &Record,
+    info: &AlignmentInfo,
+    opts: OutputOptions,
+) {
+    // Safe because the ops are always kept when writing MAF.
+    let ops = info.aln_ops.as_deref().unwrap();
+    let coords = info.coords;
+    let (x_row, y_row) = gapped_rows(x.seq(), y.seq(), ops);
+
+    // Pad the names and numbers so the rows line up.
+    let name_width = x.id().len().max(y.id().len());
+    let s_line = |id: &str, start: usize, end: usize, len: usize, row: &str| {
+        format!(
+            "s {id:<name_width$} {start:>6} {:>6} + {len:>6} {row}",
+            end - start
+        )
+    };
+
+    writeln!(
+        out,
+        "a score={}\n{}\n{}\n",
+        opts.score(info.score),
+        s_line(x.id(), coords.xstart, coords.xend, x.seq().len(), &x_row),
+        s_line(y.id(), coords.ystart, coords.yend, y.seq().len(), &y_row),
+    )
+    .unwrap();
+}
+
+/// Print one pair as a Stockholm alignment.
+fn print_stockholm_alignment(
+    out: &mut impl Write,
+    x: &Record,
+    y: &Record,
+    info: &AlignmentInfo,
+    opts: OutputOptions,
+) {
+    // Safe because the ops are always kept when writing Stockholm.
+    let ops = info.aln_ops.as_deref().unwrap();
+    let (x_row, y_row) = gapped_rows(x.seq(), y.seq(), ops);
+    let name_width = x.id().len().max(y.id().len());
+
+    writeln!(
+        out,
+        "# STOCKHOLM 1.0\n#=GF ID {}_vs_{}\n#=GF CC score {}\n{:<name_width$} {x_row}\n{:<name_width$} {y_row}\n//",
+        x.id(),
+        y.id(),
+        opts.score(info.score),
+        x.id(),
+        y.id(),
+    )
+    .unwrap();
+}
+
+/// The columns of each block of the pairwise format.
+const PAIRWISE_WIDTH: usize = 60;
+
+/// Print one pair in the stacked pairwise view.
+fn print_pairwise_alignment(
+    out: &mut impl Write,
+    x: &Record,
+    y: &Record,
+    info: &AlignmentInfo,
+    opts: OutputOptions,
+) {
+    // Safe because the ops are always kept when writing the pairwise format.
+    let ops = info.aln_ops.as_deref().unwrap();
+    let (x_row, y_row) = gapped_rows(x.seq(), y.seq(), ops);
+    let bar = ops
+        .chars()
+        .filter_map(|op| match op {
+            'M' => Some('|'),
+            'S' => Some('.'),
+            'I' | 'D' => Some(' '),
+            _ => None,
+        })
+        .collect::<String>();
+
+    writeln!(
+        out,
+        "{} vs. {}, pid {:.prec$}, score {}\n",
+        x.id(),
+        y.id(),
+        info.percent_identity,
+        opts.score(info.score),
+        prec = opts.precision
+    )
+    .unwrap();
+
+    let name_width = x.id().len().max(y.id().len());
+    let pos_width = x.seq().len().max(y.seq().len()).to_string().len();
+    // The residues of each row before the current block.
+    let (mut x_pos, mut y_pos) = (info.coords.xstart, info.coords.ystart);
+    let row_line = |id: &str, pos: &mut usize, block: &str| {
+        let num_residues = block.bytes().filter(|&c| c != b'-').count();
+        let start = if num_residues > 0 { *pos + 1 } else { *pos };
+        *pos += num_residues;
+        format!("{id:<name_width$} {start:>pos_width$} {block} {}", *pos)
+    };
+
+    for i in (0..bar.len()).step_by(PAIRWISE_WIDTH) {
+        let end = (i + PAIRWISE_WIDTH).min(bar.len());
+        writeln!(
+            out,
+            "{}\n{:width$} {}\n{}\n",
+            row_line(x.id(), &mut x_pos, &x_row[i..end]),
+            "",
+            bar[i..end].trim_end(),
+            row_line(y.id(), &mut y_pos, &y_row[i..end]),
+            width = name_width + 1 + pos_width
+        )
+        .unwrap();
+    }
+}
+
+/// Write the line that starts a MAF file.
+pub fn write_maf_header(out: &mut impl Write) {
+    writeln!(out, "##maf version=1 program=align\n").unwrap();
+}
+
+/// Print the results of the alignment, in the chosen row-based format.
+///
+/// Filtering is up to the caller (see `AlignerConfig::keeps`).  `opts.direction` picks whether
+/// the x vs. y line, the reciprocal y vs. x line, or both are printed.  Self-hits only get one.
+pub fn print_alignment_info(
+    out: &mut impl Write,
+    x: &Record,
+    y: &Record,
+    info: &AlignmentInfo,
+    self_hit: bool,
+    opts: OutputOptions,
+) {
+    if opts.format == OutputFormat::Maf {
+        print_maf_block(out, x, y, info, opts);
+        return;
+    }
+    if opts.format == OutputFormat::Stockholm {
+        if !self_hit {
+            print_stockholm_alignment(out, x, y, info, opts);
+        }
+        return;
+    }
+    if opts.format == OutputFormat::Edgelist {
+        if !self_hit {
+            print_edge(out, x, y, info, opts);
+        }
+        return;
+    }
+    if opts.format == OutputFormat::Pairwise {
+        if !self_hit {
+            print_pairwise_alignment(out, x, y, info, opts);
+        }
+        return;
+    }
+
+    let print_line = match opts.format {
+        _ if opts.kmer_size.is_some() => print_kmer_line,
+        OutputFormat::Blast6 => print_blast6_line,
+        OutputFormat::Jsonl => print_jsonl_line,
+        OutputFormat::Bed => print_bed_line,
+        _ => print_alignment_info_line,
+    };
+    // BED intervals are only the part of the reference that the query aligned to.
+    let coords = if opts.format == OutputFormat::Bed {
+        info.internal_coords
+    } else {
+        info.coords
+    };
+
+    if self_hit {
+        print_line(out, x, y, info, coords, true, opts);
+        return;
+    }
+
+    let (forward, backward) = directions(x, y, opts);
+
+    if forward {
+        print_line(out, x, y, info, coords, false, opts);
+    }
+    if backward {
+        // Most columns only need the coords swapped, so skip the copy unless there are others.
+        if !info.has_sided_columns() {
+            print_line(out, y, x, info, coords.swap(), false, opts);
+        } else {
+            let info = info.swapped();
+            print_line(out, y, x, &info, info.coords, false, opts);
+        }
+    }
+}
+
+/// Whether to write the x vs. y row and the y vs. x row of a pair, by --direction.
+fn directions(x: &Record, y: &Record, opts: OutputOptions) -> (bool, bool) {
+    // x always comes before y in the input.
+    match opts.direction {
+        Direction::Both => (true, true),
+        Direction::InputOrder => (true, false),
+        Direction::Lexical => {
+            let forward = x.id() <= y.id();
+            (forward, !forward)
+        }
+    }
+}
+
+/// The hits of each query, for `--format json-grouped`, written once every pair is done.
+///
+/// Each hit is the same object a jsonl row would be.
+pub struct GroupedHits {
+    /// For each query (by record index), its hits' record indices and JSON objects.
+    hits: Mutex<Vec<Vec<(usize, String)>>>,
+}
+
+impl GroupedHits {
+    pub fn new(num_records: usize) -> Self {
+        Self {
+            hits: Mutex::new(vec![Vec::new(); num_records]),
+        }
+    }
+
+    /// Add the hits of records `x_index` and `y_index` to each other, by --direction.
+    pub fn add(
+        &self,
+        (x_index, x): (usize, &Record),
+        (y_index, y): (usize, &Record),
+        info: &AlignmentInfo,
+        self_hit: bool,
+        opts: OutputOptions,
+    ) {
+        let hit = |x: &Record, y: &Record, info: &AlignmentInfo, coords| {
+            let mut buf = Vec::new();
+            print_jsonl_line(&mut buf, x, y, info, coords, self_hit, opts);
+            // Safe because everything written is UTF-8.
+            String::from_utf8(buf).unwrap().trim_end().to_string()
+        };
+
+        let (forward, backward) = if self_hit {
+            (true, false)
+        } else {
+            directions(x, y, opts)
+        };
+        let forward = forward.then(|| hit(x, y, info, info.coords));
+        let backward = backward.then(|| {
+            let info = info.swapped();
+            hit(y, x, &info, info.coords)
+        });
+
+        let mut hits = self.hits.lock().unwrap();
+        if let Some(forward) = forward {
+            hits[x_index].push((y_index, forward));
+        }
+        if let Some(backward) = backward {
+            hits[y_index].push((x_index, backward));
+        }
+    }
+
+    /// Write a JSON array with an object per record, in input order, holding its hits in input
+    /// order.
+    pub fn write(&self, out: &mut impl Write, records: &[Record]) {
+        let mut hits = self.hits.lock().unwrap();
+
+        writeln!(out, "[").unwrap();
+        for (i, (record, hits)) in records.iter().zip(hits.iter_mut()).enumerate() {
+            hits.sort_by_key(|(index, _)| *index);
+            let hits = hits
+                .iter()
+                .map(|(_, hit)| hit.as_str())
+                .collect::<Vec<_>>()
+                .join(",");
+            let comma = if i + 1 < records.len() { "," } else { "" };
+
+            writeln!(
+                out,
+                "{{\"query\":{},\"hits\":[{hits}]}}{comma}",
+                json_string(record.id())
+            )
+            .unwrap();
+        }
+        writeln!(out, "]").unwrap();
+    }
+}
+
+/// Quote and escape `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+/// Format `x` as a JSON number with `prec` decimal places, or null if it's NaN or infinite,
+/// which JSON has no numbers for.
+fn json_number(x: f64, prec: usize) -> String {
+    if x.is_finite() {
+        format!("{x:.prec$}")
+    } else {
+        "null".to_string()
+    }
+}
+
+/// Print one alignment as a JSON object on its own line.
+///
+/// The keys are the TSV column names, and the same optional columns are included.
+fn print_jsonl_line(
+    out: &mut impl Write,
+    x: &Record,
+    y: &Record,
+    info: &AlignmentInfo,
+    coords: Coords,
+    self_hit: bool,
+    opts: OutputOptions,
+) {
+    let prec = opts.precision;
+    let (x_len, y_len) = (x.seq().len(), y.seq().len());
+
+    let mut fields = vec![
+        ("x", json_string(x.id())),
+        ("y", json_string(y.id())),
+        ("xlen", x_len.to_string()),
+        ("ylen", y_len.to_string()),
+        ("alnlen", info.aln_len.to_string()),
+        ("matches", info.num_matches.to_string()),
+    ];
+
+    if opts.distance {
+        let dist = opts.distance_correction.distance(info.percent_identity);
+        fields.push(("dist", json_number(dist, prec)));
+    } else {
+        fields.push(("pid", json_number(info.percent_identity, prec)));
+    }
+
+    if let Some(coord_base) = opts.coord_base {
+        let (xstart, xend) = coord_base.convert(coords.xstart, coords.xend);
+        let (ystart, yend) = coord_base.convert(coords.ystart, coords.yend);
+        fields.extend([
+            ("xstart", xstart.to_string()),
+            ("xend", xend.to_string()),
+            ("ystart", ystart.to_string()),
+            ("yend", yend.to_string()),
+        ]);
+    }
+
+    if opts.extra_pid {
+        let matches = f64::from(info.num_matches);
+        fields.push(("pid_over_x", json_number(matches / x_len as f64, prec)));
+        fields.push(("pid_over_y", json_number(matches / y_len as f64, prec)));
+    }
+
+    if opts.score_norm {
+        let score = opts.score(info.score);
+        let norm = score.value() / f64::from(info.aln_len);
+        fields.push(("score", score.to_string()));
+        fields.push(("score_norm", json_number(norm, prec)));
+    }
+
+    if let Some(max_score) = info.max_score {
+        let frac = f64::from(info.score) / f64::from(max_score);
+        fields.push(("max_score", opts.score(max_score).to_string()));
+        fields.push(("frac_max_score", json_number(frac, prec)));
+    }
+
+    if opts.edit_distance {
+        fields.push(("edit_distance", info.edit_distance.to_string()));
+    }
+
+    if opts.internal_aln_len {
+        let internal_pid = f64::from(info.num_matches) / f64::from(info.internal_aln_len);
+        fields.push(("internal_alnlen", info.internal_aln_len.to_string()));
+        fields.push(("internal_pid", json_number(internal_pid, prec)));
+    }
+
+    if opts.pid_trimmed {
+        let pid_trimmed =
+            crate::percent_identity(info.internal_aln_len, info.num_matches, opts.empty_pid);
+        fields.push(("pid_trimmed", json_number(pid_trimmed, prec)));
+    }
+
+    if let Some(segments) = info.segments {
+        fields.push(("segments", segments.to_string()));
+    }
+
+    if let Some(gap_limit) = info.gap_limit {
+        fields.push(("gap_limit", json_string(gap_limit.as_str())));
+    }
+
+    if let Some(stats) = info.gap_stats {
+        fields.push(("mean_gap_len", json_number(stats.mean, prec)));
+        fields.push(("max_gap_len", stats.max.to_string()));
+    }
+
+    if opts.len_diff {
+        let longer = match x_len.cmp(&y_len) {
+            std::cmp::Ordering::Greater => "x",
+            std::cmp::Ordering::Less => "y",
+            std::cmp::Ordering::Equal => "equal",
+        };
+        fields.push(("len_diff", x_len.abs_diff(y_len).to_string()));
+        fields.push(("longer", json_string(longer)));
+    }
+
+    if let Some(full_length) = info.full_length {
+        fields.push(("full_length", full_length.to_string()));
+    }
+
+    if let Some((x_frame, y_frame)) = info.frames {
+        fields.push(("frame", json_string(&format!("{x_frame}/{y_frame}"))));
+    }
+
+    if let Some(low_confidence) = info.low_confidence {
+        fields.push(("low_confidence", low_confidence.to_string()));
+    }
+
+    if let Some(weight) = info.pair_weight {
+        fields.push(("pair_weight", json_number(weight, prec)));
+    }
+
+    if let Some((x_entropy, y_entropy)) = info.entropy {
+        fields.push(("x_entropy", json_number(x_entropy, prec)));
+        fields.push(("y_entropy", json_number(y_entropy, prec)));
+    }
+
+    if opts.ungapped {
+        let offset = coords.xstart as isize - coords.ystart as isize;
+        fields.push(("offset", offset.to_string()));
+    }
+
+    if opts.mark_self {
+        fields.push(("is_self", self_hit.to_string()));
+    }
+
+    if opts.reversed {
+        fields.push(("reversed", "true".to_string()));
+    }
+
+    if let (Some(annotations), Some(annotate_on)) = (&info.annotations, opts.annotate_on) {
+        fields.push(("annotation", json_string(annotations.on(annotate_on))));
+    }
+
+    if let Some((x_ids, y_ids)) = &info.collapsed {
+        fields.push(("x_collapsed", json_string(x_ids)));
+        fields.push(("y_collapsed", json_string(y_ids)));
+    }
+
+    for column in opts.computed {
+        let value = column.eval(|var| computed_var(var, x_len, y_len, info, opts));
+        fields.push((&column.name, json_number(value, prec)));
+    }
+
+    if let Some((x_ops, y_ops)) = &info.dual_ops {
+        fields.push(("x_ops", json_string(x_ops)));
+        fields.push(("y_ops", json_string(y_ops)));
+    }
+
+    if let (Some(ops), true) = (&info.aln_ops, opts.show_aln_ops) {
+        fields.push(("ops", json_string(ops)));
+    }
+
+    let object = fields
+        .iter()
+        .map(|(key, value)| format!("\"{key}\":{value}"))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    writeln!(out, "{{{object}}}").unwrap();
+}
+
+/// Write the TSV header line, which mirrors the optional columns of `print_alignment_info_line`.
+pub fn write_header(out: &mut impl Write, opts: OutputOptions) {
+    if opts.kmer_size.is_some() {
+        let similarity = if opts.distance {
+            "kmer_dist"
+        } else {
+            "kmer_cosine"
+        };
+        let is_self = if opts.mark_self { "\tis_self" } else { "" };
+        writeln!(out, "x\ty\txlen\tylen\t{similarity}{is_self}").unwrap();
+        return;
+    }
+
+    let coords = match opts.coord_base {
+        None => "",
+        Some(_) => "\txstart\txend\tystart\tyend",
+    };
+
+    let extra_pid = if opts.extra_pid {
+        "\tpid_over_x\tpid_over_y"
+    } else {
+        ""
+    };
+
+    let score_norm = if opts.score_norm {
+        "\tscore\tscore_norm"
+    } else {
+        ""
+    };
+
+    let max_score = if opts.max_score {
+        "\tmax_score\tfrac_max_score"
+    } else {
+        ""
+    };
+
+    let edit_distance = if opts.edit_distance {
+        "\tedit_distance"
+    } else {
+        ""
+    };
+
+    let internal = if opts.internal_aln_len {
+        "\tinternal_alnlen\tinternal_pid"
+    } else {
+        ""
+    };
+
+    let pid_trimmed = if opts.pid_trimmed {
+        "\tpid_trimmed"
+    } else {
+        ""
+    };
+
+    let segments = if opts.repeat_aware { "\tsegments" } else { "" };
+
+    let gap_limit = if opts.max_gap_len.is_some() {
+        "\tgap_limit"
+    } else {
+        ""
+    };
+
+    let gap_stats = if opts.gap_stats {
+        "\tmean_gap_len\tmax_gap_len"
+    } else {
+        ""
+    };
+
+    let len_diff = if opts.len_diff {
+        "\tlen_diff\tlonger"
+    } else {
+        ""
+    };
+
+    let full_length = if opts.full_length.is_some() {
+        "\tfull_length"
+    } else {
+        ""
+    };
+
+    let frame = if opts.translate { "\tframe" } else { "" };
+
+    let low_confidence = if opts.low_confidence.is_some() {
+        "\tlow_confidence"
+    } else {
+        ""
+    };
+
+    let pair_weight = if opts.pair_weight {
+        "\tpair_weight"
+    } else {
+        ""
+    };
+
+    let entropy = if opts.entropy {
+        "\tx_entropy\ty_entropy"
+    } else {
+        ""
+    };
+
+    let offset = if opts.ungapped { "\toffset" } else { "" };
+
+    let is_self = if opts.mark_self { "\tis_self" } else { "" };
+
+    let reversed = if opts.reversed { "\treversed" } else { "" };
+
+    let annotation = if opts.annotate_on.is_some() {
+        "\tannotation"
+    } else {
+        ""
+    };
+
+    let collapsed = if opts.collapse_identical {
+        "\tx_collapsed\ty_collapsed"
+    } else {
+        ""
+    };
+
+    let computed = opts
+        .computed
+        .iter()
+        .map(|column| format!("\t{}", column.name))
+        .collect::<String>();
+
+    let dual_ops = if opts.dual_ops { "\tx_ops\ty_ops" } else { "" };
+
+    let pid = if opts.distance { "dist" } else { "pid" };
+
+    writeln!(
+        out,
+        "x\ty\txlen\tylen\talnlen\tmatches\t{pid}{coords}{extra_pid}{score_norm}{max_score}{edit_distance}{internal}{pid_trimmed}{segments}{gap_limit}{gap_stats}{len_diff}{full_length}{frame}{low_confidence}{pair_weight}{entropy}{offset}{is_self}{reversed}{annotation}{collapsed}{computed}{dual_ops}"
+    )
+    .unwrap();
+}
+
+#[test]
+fn gapped_rows_follow_the_ops() {
+    let (x_row, y_row) = gapped_rows(b"ACDEF", b"YACEFW", "DMMIMMD");
+    assert_eq!(x_row, "-ACDEF-");
+    assert_eq!(y_row, "YAC-EFW");
+
+    let (x_row, y_row) = gapped_rows(b"ACDE", b"WWACDE", "YYMMMM");
+    assert_eq!(x_row, "ACDE");
+    assert_eq!(y_row, "ACDE");
+}
+
+#[test]
+fn non_finite_json_numbers_are_null() {
+    assert_eq!(json_number(0.5, 2), "0.50");
+    assert_eq!(json_number(f64::NAN, 2), "null");
+    assert_eq!(json_number(f64::INFINITY, 2), "null");
+}
+
+#[test]
+fn grouped_hits_are_nested_in_input_order() {
+    let records = vec![
+        Record::with_attrs("a", None, b"ACDE"),
+        Record::with_attrs("b", None, b"ACDE"),
+    ];
+    let grouped = GroupedHits::new(records.len());
+    {
+        let mut hits = grouped.hits.lock().unwrap();
+        hits[0].push((1, "{\"target\":\"b\"}".to_string()));
+        hits[0].push((0, "{\"target\":\"a\"}".to_string()));
+    }
+
+    let mut out = Vec::new();
+    grouped.write(&mut out, &records);
+
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "[\n\
+         {\"query\":\"a\",\"hits\":[{\"target\":\"a\"},{\"target\":\"b\"}]},\n\
+         {\"query\":\"b\",\"hits\":[]}\n\
+         ]\n"
+    );
+}
@@ -0,0 +1,108 @@
+//! Lists of record pairs read from a file, for `--exclude-pairs`.
+
+use crate::error::{Error, Result};
+use bio::io::fasta::Record;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// Pairs of ids, one pair per line, separated by whitespace.
+#[derive(Clone)]
+pub struct PairList {
+    pairs: Vec<(String, String)>,
+}
+
+impl PairList {
+    pub fn from_text(text: &str) -> Result<Self> {
+        let pairs = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(
+                |(i, line)| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                    [x, y] => Ok((x.to_string(), y.to_string())),
+                    _ => Err(Error::parse(format!(
+                        "line {} should have two ids, separated by whitespace",
+                        i + 1
+                    ))),
+                },
+            )
+            .collect::<Result<_>>()?;
+
+        Ok(Self { pairs })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::from_text(&text).map_err(|e| Error::InvalidFile {
+            kind: "pair list",
+            path: path.to_path_buf(),
+            source: Box::new(e),
+        })
+    }
+
+    /// The pairs as record indices, earlier record first, so either order in the file matches.
+    ///
+    /// Ids that aren't in `records` are skipped, with a warning.
+    pub fn indices(&self, records: &[Record]) -> HashSet<(usize, usize)> {
+        let mut ids: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, record) in records.iter().enumerate() {
+            ids.entry(record.id()).or_default().push(i);
+        }
+
+        let mut unknown = HashSet::new();
+        let mut indices = HashSet::new();
+        for (x, y) in &self.pairs {
+            match (ids.get(x.as_str()), ids.get(y.as_str())) {
+                (Some(xs), Some(ys)) => {
+                    for &i in xs {
+                        for &j in ys {
+                            indices.insert((i.min(j), i.max(j)));
+                        }
+                    }
+                }
+                (xs, ys) => {
+                    if xs.is_none() {
+                        unknown.insert(x.as_str());
+                    }
+                    if ys.is_none() {
+                        unknown.insert(y.as_str());
+                    }
+                }
+            }
+        }
+
+        if !unknown.is_empty() {
+            let mut unknown = unknown.into_iter().collect::<Vec<_>>();
+            unknown.sort_unstable();
+            eprintln!(
+                "warning: skipped the pairs of {} ids that aren't in the input, e.g., {}",
+                unknown.len(),
+                unknown[0]
+            );
+        }
+
+        indices
+    }
+}
+
+#[test]
+fn pairs_are_matched_in_either_order() {
+    let records = vec![
+        Record::with_attrs("a", None, b"ACDE"),
+        Record::with_attrs("b", None, b"ACDE"),
+        Record::with_attrs("c", None, b"ACDE"),
+    ];
+    assert!(PairList::from_text("a b c\n").is_err());
+
+    let pairs = PairList::from_text("# x y\nc a\na\tb\nb nope\n").unwrap();
+    let indices = pairs.indices(&records);
+
+    assert_eq!(indices.len(), 2);
+    assert!(indices.contains(&(0, 2)));
+    assert!(indices.contains(&(0, 1)));
+}
@@ -0,0 +1,212 @@
+//! All pairwise percent identities, collected for the matrix output formats.
+
+use crate::cli::{DistanceCorrection, MatrixShape};
+use bio::io::fasta::Record;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// The first bytes of a binary matrix file, which also give the version of the layout.
+const BINARY_MAGIC: &[u8; 8] = b"ALNPID01";
+
+/// An N x N matrix of percent identities, filled in as alignments finish.
+pub struct PidMatrix {
+    n: usize,
+    pids: Mutex<Vec<f64>>,
+}
+
+impl PidMatrix {
+    pub fn new(n: usize) -> Self {
+        let mut pids = vec![f64::NAN; n * n];
+        (0..n).for_each(|i| pids[i * n + i] = 1.0);
+
+        Self {
+            n,
+            pids: Mutex::new(pids),
+        }
+    }
+
+    /// Set the percent identity of records `i` and `j`, in both directions.
+    pub fn add(&self, i: usize, j: usize, percent_identity: f64) {
+        let mut pids = self.pids.lock().unwrap();
+
+        pids[i * self.n + j] = percent_identity;
+        pids[j * self.n + i] = percent_identity;
+    }
+
+    /// The record with the highest total pid to all the others, i.e., the most central one.
+    ///
+    /// Missing pids (e.g., skipped pairs) count as 0.  Ties go to the first record.
+    pub fn center(&self) -> usize {
+        let pids = self.pids.lock().unwrap();
+
+        (0..self.n)
+            .map(|i| {
+                let row = &pids[i * self.n..(i + 1) * self.n];
+                row.iter().filter(|pid| !pid.is_nan()).sum::<f64>()
+            })
+            .enumerate()
+            .fold((0, f64::NEG_INFINITY), |best, (i, total)| {
+                if total > best.1 {
+                    (i, total)
+                } else {
+                    best
+                }
+            })
+            .0
+    }
+
+    /// Write the pids in the binary layout described at `--matrix-binary`.
+    pub fn write_binary(&self, path: &Path, records: &[Record]) {
+        let mut out = BufWriter::new(File::create(path).unwrap());
+        write_binary(&mut out, records, &self.pids.lock().unwrap());
+    }
+
+    /// Write distances (1 - pid, unless corrected) as a PHYLIP distance matrix, with the cells
+    /// of `shape`.
+    ///
+    /// Names are padded to 10 characters as PHYLIP expects, but longer names are not truncated,
+    /// so use a "relaxed" PHYLIP reader if your ids are long.
+    pub fn write_phylip_distances<W: Write>(
+        &self,
+        out: &mut W,
+        records: &[Record],
+        correction: DistanceCorrection,
+        shape: MatrixShape,
+        precision: usize,
+    ) {
+        let pids = self.pids.lock().unwrap();
+
+        writeln!(out, "{}", self.n).unwrap();
+        records.iter().enumerate().for_each(|(i, record)| {
+            write!(out, "{:<10}", record.id()).unwrap();
+            shape.columns(i, self.n).for_each(|j| {
+                let dist = correction.distance(pids[i * self.n + j]);
+                write!(out, " {dist:.precision$}").unwrap()
+            });
+            writeln!(out).unwrap();
+        });
+    }
+
+    /// Write distances (1 - pid, unless corrected) one per line, in the order of scipy's
+    /// condensed matrices: the upper triangle, row by row, without the diagonal.
+    pub fn write_condensed<W: Write>(
+        &self,
+        out: &mut W,
+        correction: DistanceCorrection,
+        precision: usize,
+    ) {
+        let pids = self.pids.lock().unwrap();
+
+        (0..self.n).for_each(|i| {
+            (i + 1..self.n).for_each(|j| {
+                let dist = correction.distance(pids[i * self.n + j]);
+                writeln!(out, "{dist:.precision$}").unwrap()
+            })
+        });
+    }
+}
+
+/// Write the ids of `records`, one per line, e.g., for the rows of a scipy-condensed matrix.
+pub fn write_ids(path: &Path, records: &[Record]) {
+    let mut out = BufWriter::new(File::create(path).unwrap());
+    records
+        .iter()
+        .for_each(|record| writeln!(out, "{}", record.id()).unwrap());
+}
+
+fn write_binary(out: &mut impl Write, records: &[Record], pids: &[f64]) {
+    let ids_len = records.iter().map(|r| 4 + r.id().len()).sum::<usize>();
+    let header_len = BINARY_MAGIC.len() + 8 + 8 + ids_len;
+    let offset = header_len.next_multiple_of(8);
+
+    out.write_all(BINARY_MAGIC).unwrap();
+    out.write_all(&(records.len() as u64).to_le_bytes())
+        .unwrap();
+    out.write_all(&(offset as u64).to_le_bytes()).unwrap();
+    for record in records {
+        let id = record.id().as_bytes();
+        out.write_all(&(id.len() as u32).to_le_bytes()).unwrap();
+        out.write_all(id).unwrap();
+    }
+    out.write_all(&vec![0; offset - header_len]).unwrap();
+
+    for &pid in pids {
+        out.write_all(&(pid as f32).to_le_bytes()).unwrap();
+    }
+}
+
+#[test]
+fn matrices_have_the_chosen_shape() {
+    let records = vec![
+        Record::with_attrs("a", None, b"ACDE"),
+        Record::with_attrs("b", None, b"ACDE"),
+        Record::with_attrs("c", None, b"ACDE"),
+    ];
+    let pids = PidMatrix::new(3);
+    pids.add(0, 1, 0.5);
+    pids.add(0, 2, 0.25);
+    pids.add(1, 2, 0.75);
+
+    let write = |shape| {
+        let mut out = Vec::new();
+        pids.write_phylip_distances(&mut out, &records, DistanceCorrection::None, shape, 2);
+        String::from_utf8(out).unwrap()
+    };
+
+    assert_eq!(
+        write(MatrixShape::Lower),
+        "3\na         \nb          0.50\nc          0.75 0.25\n"
+    );
+    assert_eq!(
+        write(MatrixShape::Upper),
+        "3\na          0.50 0.75\nb          0.25\nc         \n"
+    );
+    assert_eq!(
+        write(MatrixShape::Full),
+        "3\na          0.00 0.50 0.75\nb          0.50 0.00 0.25\nc          0.75 0.25 0.00\n"
+    );
+}
+
+#[test]
+fn condensed_matrices_follow_scipy_order() {
+    let pids = PidMatrix::new(4);
+    pids.add(0, 1, 0.9);
+    pids.add(0, 2, 0.8);
+    pids.add(0, 3, 0.7);
+    pids.add(1, 2, 0.6);
+    pids.add(2, 3, 0.4);
+
+    let mut out = Vec::new();
+    pids.write_condensed(&mut out, DistanceCorrection::None, 1);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "0.1\n0.2\n0.3\n0.4\nNaN\n0.6\n"
+    );
+}
+
+#[test]
+fn binary_matrices_follow_the_layout() {
+    let records = vec![
+        Record::with_attrs("a", None, b"ACDE"),
+        Record::with_attrs("bc", None, b"ACDE"),
+    ];
+    let pids = PidMatrix::new(2);
+    pids.add(0, 1, 0.5);
+
+    let mut out = Vec::new();
+    write_binary(&mut out, &records, &pids.pids.lock().unwrap());
+
+    let u64_at = |i: usize| u64::from_le_bytes(out[i..i + 8].try_into().unwrap());
+    let f32_at = |i: usize| f32::from_le_bytes(out[i..i + 4].try_into().unwrap());
+    assert_eq!(&out[..8], BINARY_MAGIC);
+    assert_eq!(u64_at(8), 2);
+    // 24 bytes of header, then 4 + 1 and 4 + 2 bytes of ids, padded to 40.
+    assert_eq!(u64_at(16), 40);
+    assert_eq!(&out[24..29], b"\x01\0\0\0a");
+    assert_eq!(&out[29..35], b"\x02\0\0\0bc");
+    assert_eq!(out.len(), 40 + 4 * 4);
+    let cells = (0..4).map(|k| f32_at(40 + 4 * k)).collect::<Vec<_>>();
+    assert_eq!(cells, vec![1.0, 0.5, 0.5, 1.0]);
+}
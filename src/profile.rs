@@ -0,0 +1,257 @@
+//! Aligning sequences against a position-specific scoring matrix (PSSM).
+
+use crate::error::{Error, Result};
+use crate::gap_profile::GapProfile;
+use std::fs;
+use std::path::Path;
+
+/// Scores for each position of a profile.
+pub struct Profile {
+    /// The consensus residue of each position.
+    consensus: Vec<u8>,
+    /// Indexed by `position * 256 + residue`.  Residues not in the PSSM get the position's
+    /// lowest score.
+    scores: Vec<i32>,
+}
+
+impl Profile {
+    /// Parse a PSSM in the ASCII format written by PSI-BLAST (`-out_ascii_pssm`).
+    ///
+    /// The header is the first line made up of only single letters, and the first 20 of them are
+    /// the residues.  Each position is a line like `1 M -1 -2 ...`, i.e., the position, the
+    /// consensus residue, and then a score for each residue.  Anything after those scores (e.g.,
+    /// PSI-BLAST's weighted percentages) and any other lines are ignored.
+    pub fn from_ascii_pssm(text: &str) -> Result<Self> {
+        let mut lines = text.lines();
+
+        let residues = lines
+            .by_ref()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            .find(|fields| {
+                fields.len() >= 20
+                    && fields
+                        .iter()
+                        .all(|f| f.len() == 1 && f.as_bytes()[0].is_ascii_alphabetic())
+            })
+            .ok_or_else(|| Error::parse("PSSM has no header line of residues"))?
+            .into_iter()
+            .take(20)
+            .map(|f| f.as_bytes()[0].to_ascii_uppercase())
+            .collect::<Vec<u8>>();
+
+        let mut consensus = Vec::new();
+        let mut scores = Vec::new();
+        for line in lines {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let is_position = fields.len() >= 2 + residues.len()
+                && fields[0].parse::<usize>().is_ok()
+                && fields[1].len() == 1;
+            if !is_position {
+                continue;
+            }
+
+            let row = fields[2..2 + residues.len()]
+                .iter()
+                .map(|f| {
+                    f.parse::<i32>().map_err(|_| {
+                        Error::parse(format!("bad score '{f}' at position {}", fields[0]))
+                    })
+                })
+                .collect::<Result<Vec<i32>>>()?;
+
+            // Safe because rows always have 20 scores.
+            let lowest = *row.iter().min().unwrap();
+            let mut position = vec![lowest; 256];
+            for (&residue, score) in residues.iter().zip(row) {
+                position[usize::from(residue)] = score;
+                position[usize::from(residue.to_ascii_lowercase())] = score;
+            }
+
+            consensus.push(fields[1].as_bytes()[0].to_ascii_uppercase());
+            scores.extend(position);
+        }
+
+        if consensus.is_empty() {
+            return Err(Error::parse("PSSM has no positions"));
+        }
+
+        Ok(Self { consensus, scores })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::from_ascii_pssm(&text).map_err(|e| Error::InvalidFile {
+            kind: "profile",
+            path: path.to_path_buf(),
+            source: Box::new(e),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.consensus.len()
+    }
+
+    fn score(&self, position: usize, residue: u8) -> i32 {
+        self.scores[position * 256 + usize::from(residue)]
+    }
+
+    /// Globally align `seq` to the profile with affine gaps (same convention as rust-bio: a gap
+    /// of length k scores `gap_open + k * gap_extend`).
+    ///
+    /// With a `gap_profile` of the same length, each gap opens with its own penalty, by the
+    /// position it skips or the boundary it's inserted at (see `GapProfile::gap_opens`).
+    pub fn align(
+        &self,
+        seq: &[u8],
+        gap_open: i32,
+        gap_extend: i32,
+        gap_profile: Option<&GapProfile>,
+    ) -> ProfileAlignment {
+        // Plenty below any real score, but with room to add penalties without overflowing.
+        const NEG_INF: i32 = i32::MIN / 2;
+        // Which matrix a cell's best score came from.
+        const FROM_M: u8 = 0;
+        const FROM_X: u8 = 1;
+        const FROM_Y: u8 = 2;
+
+        let n = seq.len();
+        let m = self.len();
+        let idx = |i: usize, j: usize| i * (m + 1) + j;
+        // Opening a gap that skips position j, and one inserted before position j.
+        let (deletion_opens, insertion_opens) = match gap_profile {
+            Some(gaps) => gaps.gap_opens(gap_open),
+            None => (vec![gap_open; m], vec![gap_open; m + 1]),
+        };
+
+        // M: seq[i - 1] aligned to position j - 1.  X: gap in the profile.  Y: gap in seq.
+        let mut mat = vec![NEG_INF; (n + 1) * (m + 1)];
+        let mut x = vec![NEG_INF; (n + 1) * (m + 1)];
+        let mut y = vec![NEG_INF; (n + 1) * (m + 1)];
+        let mut trace = vec![[FROM_M; 3]; (n + 1) * (m + 1)];
+
+        mat[idx(0, 0)] = 0;
+        for i in 1..=n {
+            x[idx(i, 0)] = insertion_opens[0] + i as i32 * gap_extend;
+            trace[idx(i, 0)][1] = FROM_X;
+        }
+        for j in 1..=m {
+            y[idx(0, j)] = deletion_opens[0] + j as i32 * gap_extend;
+            trace[idx(0, j)][2] = FROM_Y;
+        }
+
+        let best = |scores: [i32; 3]| {
+            let mut from = FROM_M;
+            for k in [FROM_X, FROM_Y] {
+                if scores[usize::from(k)] > scores[usize::from(from)] {
+                    from = k;
+                }
+            }
+            (scores[usize::from(from)], from)
+        };
+
+        for i in 1..=n {
+            for j in 1..=m {
+                let diag = idx(i - 1, j - 1);
+                let (s, from) = best([mat[diag], x[diag], y[diag]]);
+                mat[idx(i, j)] = s + self.score(j - 1, seq[i - 1]);
+                trace[idx(i, j)][0] = from;
+
+                let up = idx(i - 1, j);
+                let open = insertion_opens[j];
+                let (s, from) = best([
+                    mat[up] + open + gap_extend,
+                    x[up] + gap_extend,
+                    y[up] + open + gap_extend,
+                ]);
+                x[idx(i, j)] = s;
+                trace[idx(i, j)][1] = from;
+
+                let left = idx(i, j - 1);
+                let open = deletion_opens[j - 1];
+                let (s, from) = best([
+                    mat[left] + open + gap_extend,
+                    x[left] + open + gap_extend,
+                    y[left] + gap_extend,
+                ]);
+                y[idx(i, j)] = s;
+                trace[idx(i, j)][2] = from;
+            }
+        }
+
+        let end = idx(n, m);
+        let (score, mut state) = best([mat[end], x[end], y[end]]);
+
+        let (mut i, mut j) = (n, m);
+        let mut aln_len = 0;
+        let mut num_matches = 0;
+        while i > 0 || j > 0 {
+            let from = trace[idx(i, j)][usize::from(state)];
+            match state {
+                FROM_M => {
+                    if seq[i - 1].to_ascii_uppercase() == self.consensus[j - 1] {
+                        num_matches += 1;
+                    }
+                    i -= 1;
+                    j -= 1;
+                }
+                FROM_X => i -= 1,
+                _ => j -= 1,
+            }
+            aln_len += 1;
+            state = from;
+        }
+
+        ProfileAlignment {
+            score,
+            aln_len,
+            num_matches,
+        }
+    }
+}
+
+/// The result of aligning a sequence to a profile.
+pub struct ProfileAlignment {
+    pub score: i32,
+    pub aln_len: usize,
+    /// Positions where the sequence has the profile's consensus residue.
+    pub num_matches: usize,
+}
+
+#[test]
+fn sequence_aligns_to_its_own_profile() {
+    let residues = "A R N D C Q E G H I L K M F P S T W Y V";
+    // Consensus MKV, scoring its own residue 5 and everything else -1.
+    let rows = "MKV"
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let scores = residues
+                .split(' ')
+                .map(|r| if r == c.to_string() { "5" } else { "-1" })
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("{} {c} {scores}", i + 1)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let profile = Profile::from_ascii_pssm(&format!("\n{residues}\n{rows}\n")).unwrap();
+
+    let aln = profile.align(b"MKV", -10, -1, None);
+    assert_eq!((aln.score, aln.aln_len, aln.num_matches), (15, 3, 3));
+
+    // One extra residue costs a gap of length 1.
+    let aln = profile.align(b"MKAV", -10, -1, None);
+    assert_eq!((aln.score, aln.aln_len, aln.num_matches), (4, 4, 3));
+
+    // Unless the alignment already has residues there, which halves the gap open.
+    let gaps = GapProfile::from_text(">ref\nMK-V\n>a\nM--V\n>b\nMKAV\n").unwrap();
+    let aln = profile.align(b"MKAV", -10, -1, Some(&gaps));
+    assert_eq!((aln.score, aln.aln_len, aln.num_matches), (9, 4, 3));
+    // And half the alignment skips K, so skipping it costs half as much too.
+    let aln = profile.align(b"MV", -10, -1, Some(&gaps));
+    assert_eq!((aln.score, aln.aln_len, aln.num_matches), (4, 3, 2));
+}
@@ -0,0 +1,88 @@
+//! Reporting how many pairs have been aligned so far on stderr, for `--progress`.
+
+use std::ops::Range;
+use std::sync::atomic::{self, AtomicBool, AtomicUsize};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the progress line is rewritten.
+const INTERVAL: Duration = Duration::from_millis(500);
+
+/// The number of pairs with x in `x_records` and y in `y_records` after it (or the same record,
+/// with `self_hits`), which is how many pairs at most are sent to the workers.
+pub fn num_pairs(x_records: Range<usize>, y_records: Range<usize>, self_hits: bool) -> usize {
+    x_records
+        .map(|x| {
+            let first = y_records.start.max(x + usize::from(!self_hits));
+            y_records.end.saturating_sub(first)
+        })
+        .sum()
+}
+
+pub struct Progress {
+    /// An upper bound until every pair has been sent, since the filters skip some.
+    total: AtomicUsize,
+    /// Set once the workers are done, to stop reporting.
+    finished: AtomicBool,
+    start: Instant,
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Self {
+        Self {
+            total: AtomicUsize::new(total),
+            finished: AtomicBool::new(false),
+            start: Instant::now(),
+        }
+    }
+
+    /// Set the total to the number of pairs that were actually sent.
+    pub fn set_total(&self, total: usize) {
+        self.total.store(total, atomic::Ordering::Relaxed);
+    }
+
+    pub fn finish(&self) {
+        self.finished.store(true, atomic::Ordering::Relaxed);
+    }
+
+    /// Rewrite the progress line with the count of `processed` pairs every `INTERVAL`, until
+    /// `finish` is called, then end it with how long the run took.
+    pub fn report(&self, processed: &AtomicUsize) {
+        let line = || {
+            line(
+                processed.load(atomic::Ordering::Relaxed),
+                self.total.load(atomic::Ordering::Relaxed),
+            )
+        };
+
+        while !self.finished.load(atomic::Ordering::Relaxed) {
+            eprint!("\r{}", line());
+            thread::sleep(INTERVAL);
+        }
+        eprintln!("\r{} in {:.1}s", line(), self.start.elapsed().as_secs_f64());
+    }
+}
+
+fn line(done: usize, total: usize) -> String {
+    let percent = if total == 0 {
+        100.0
+    } else {
+        100.0 * done as f64 / total as f64
+    };
+
+    format!("aligned {done} of {total} pairs ({percent:.1}%)")
+}
+
+#[test]
+fn pairs_are_counted_up_front() {
+    // All vs. all of 4 records.
+    assert_eq!(num_pairs(0..4, 0..4, false), 6);
+    assert_eq!(num_pairs(0..4, 0..4, true), 10);
+    // A shard with the first 2 records as x.
+    assert_eq!(num_pairs(0..2, 0..4, false), 5);
+    // --add of records 3 and 4 to 3 old ones.
+    assert_eq!(num_pairs(0..5, 3..5, false), 7);
+
+    assert_eq!(line(3, 6), "aligned 3 of 6 pairs (50.0%)");
+    assert_eq!(line(0, 0), "aligned 0 of 0 pairs (100.0%)");
+}
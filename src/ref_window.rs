@@ -0,0 +1,81 @@
+//! Aligning sequences to windows of a long reference, for `--reference`.
+
+use bio::alignment::pairwise::{Aligner, MatchFunc};
+use bio::alignment::Alignment;
+use bio::io::fasta::Record;
+
+/// The start of each window of length `window`, `step` apart, over a sequence of length `len`.
+///
+/// The last window is moved back to end at the end of the sequence, so the whole sequence is
+/// covered.  A sequence no longer than `window` has one window at 0.
+pub fn window_starts(len: usize, window: usize, step: usize) -> Vec<usize> {
+    let last = len.saturating_sub(window);
+    let mut starts = (0..last).step_by(step).collect::<Vec<_>>();
+    starts.push(last);
+
+    starts
+}
+
+/// The best window of the references for one query.
+pub struct WindowHit {
+    pub ref_index: usize,
+    pub window_start: usize,
+    /// The alignment of the query to the window, so y's coordinates are within the window.
+    pub alignment: Alignment,
+}
+
+/// Align `query` end to end within each window of each reference, keeping the best score.
+///
+/// Ties go to the earlier window.
+pub fn best_window<F: MatchFunc>(
+    aligner: &mut Aligner<F>,
+    query: &[u8],
+    references: &[Record],
+    window: usize,
+    step: usize,
+) -> WindowHit {
+    let mut best: Option<WindowHit> = None;
+
+    for (ref_index, reference) in references.iter().enumerate() {
+        let seq = reference.seq();
+        for window_start in window_starts(seq.len(), window, step) {
+            let window_end = (window_start + window).min(seq.len());
+            let alignment = aligner.semiglobal(query, &seq[window_start..window_end]);
+            if best
+                .as_ref()
+                .is_none_or(|hit| alignment.score > hit.alignment.score)
+            {
+                best = Some(WindowHit {
+                    ref_index,
+                    window_start,
+                    alignment,
+                });
+            }
+        }
+    }
+
+    // Safe because there's always at least one reference, with at least one window.
+    best.unwrap()
+}
+
+#[test]
+fn windows_cover_the_whole_reference() {
+    assert_eq!(window_starts(10, 4, 2), vec![0, 2, 4, 6]);
+    assert_eq!(window_starts(11, 4, 3), vec![0, 3, 6, 7]);
+    assert_eq!(window_starts(3, 4, 2), vec![0]);
+}
+
+#[test]
+fn the_window_holding_the_query_is_best() {
+    let score = |a: u8, b: u8| if a == b { 5 } else { -4 };
+    let mut aligner = Aligner::new(-10, -1, &score);
+    let references = [
+        Record::with_attrs("r1", None, b"GGGGGGGGGGGGGGGG"),
+        Record::with_attrs("r2", None, b"GGGGGGGGGGMKWHCRGGGGGGGG"),
+    ];
+
+    let hit = best_window(&mut aligner, b"MKWHCR", &references, 8, 4);
+    assert_eq!(hit.ref_index, 1);
+    assert_eq!(hit.window_start, 8);
+    assert_eq!(hit.window_start + hit.alignment.ystart, 10);
+}
@@ -0,0 +1,162 @@
+//! Linear-memory global alignment, for `--score-only`.
+//!
+//! Instead of keeping a traceback matrix, each DP cell carries the counts of the best path that
+//! ends in it, so only two rows are ever kept.
+
+use crate::scoring::Matrix;
+
+/// Plenty below any real score, but with room to add penalties without overflowing.
+const NEG_INF: i32 = i32::MIN / 2;
+
+/// The best path ending in a cell, and its counts.
+#[derive(Copy, Clone)]
+struct Path {
+    score: i32,
+    len: u32,
+    num_matches: u32,
+    num_mismatches: u32,
+    num_gap_opens: u32,
+}
+
+impl Path {
+    const NONE: Path = Path {
+        score: NEG_INF,
+        len: 0,
+        num_matches: 0,
+        num_mismatches: 0,
+        num_gap_opens: 0,
+    };
+
+    /// Extend the path by one aligned pair.
+    fn pair(self, score: i32, is_match: bool) -> Self {
+        Self {
+            score: self.score + score,
+            len: self.len + 1,
+            num_matches: self.num_matches + u32::from(is_match),
+            num_mismatches: self.num_mismatches + u32::from(!is_match),
+            ..self
+        }
+    }
+
+    /// Extend the path by one gap column, `open`ing a new gap or not.
+    fn gap(self, penalty: i32, open: bool) -> Self {
+        Self {
+            score: self.score + penalty,
+            len: self.len + 1,
+            num_gap_opens: self.num_gap_opens + u32::from(open),
+            ..self
+        }
+    }
+}
+
+/// The best of the paths, preferring the earlier ones on ties.
+fn best(paths: [Path; 3]) -> Path {
+    paths
+        .into_iter()
+        .reduce(|best, p| if p.score > best.score { p } else { best })
+        .unwrap()
+}
+
+/// The score and counts of a global alignment.
+pub struct ScoreOnly {
+    pub score: i32,
+    pub aln_len: usize,
+    pub num_matches: usize,
+    pub num_mismatches: usize,
+    pub num_gap_opens: usize,
+}
+
+/// Globally align `x` and `y` with affine gaps (same convention as rust-bio: a gap of length k
+/// scores `gap_open + k * gap_extend`), using memory linear in the length of `y`.
+///
+/// The score is the same as rust-bio's.  When several alignments tie for the best score, the
+/// counts may come from a different one than rust-bio's traceback would pick.
+pub fn align(x: &[u8], y: &[u8], matrix: &Matrix, gap_open: i32, gap_extend: i32) -> ScoreOnly {
+    let m = y.len();
+    let open = gap_open + gap_extend;
+
+    // M: x[i - 1] aligned to y[j - 1].  X: x[i - 1] aligned to a gap.  Y: y[j - 1] to a gap.
+    let mut prev_m = vec![Path::NONE; m + 1];
+    let mut prev_x = vec![Path::NONE; m + 1];
+    let mut prev_y = vec![Path::NONE; m + 1];
+
+    prev_m[0] = Path {
+        score: 0,
+        ..Path::NONE
+    };
+    for j in 1..=m {
+        prev_y[j] = if j == 1 {
+            prev_m[0].gap(open, true)
+        } else {
+            prev_y[j - 1].gap(gap_extend, false)
+        };
+    }
+
+    let mut cur_m = vec![Path::NONE; m + 1];
+    let mut cur_x = vec![Path::NONE; m + 1];
+    let mut cur_y = vec![Path::NONE; m + 1];
+
+    for (i, &a) in x.iter().enumerate() {
+        cur_m[0] = Path::NONE;
+        cur_x[0] = if i == 0 {
+            prev_m[0].gap(open, true)
+        } else {
+            prev_x[0].gap(gap_extend, false)
+        };
+        cur_y[0] = Path::NONE;
+
+        for (j, &b) in y.iter().enumerate().map(|(j, b)| (j + 1, b)) {
+            cur_m[j] = best([prev_m[j - 1], prev_x[j - 1], prev_y[j - 1]])
+                .pair(matrix.score(a, b), a == b);
+
+            cur_x[j] = best([
+                prev_m[j].gap(open, true),
+                prev_x[j].gap(gap_extend, false),
+                prev_y[j].gap(open, true),
+            ]);
+
+            cur_y[j] = best([
+                cur_m[j - 1].gap(open, true),
+                cur_x[j - 1].gap(open, true),
+                cur_y[j - 1].gap(gap_extend, false),
+            ]);
+        }
+
+        std::mem::swap(&mut prev_m, &mut cur_m);
+        std::mem::swap(&mut prev_x, &mut cur_x);
+        std::mem::swap(&mut prev_y, &mut cur_y);
+    }
+
+    let end = best([prev_m[m], prev_x[m], prev_y[m]]);
+
+    ScoreOnly {
+        score: end.score,
+        aln_len: end.len as usize,
+        num_matches: end.num_matches as usize,
+        num_mismatches: end.num_mismatches as usize,
+        num_gap_opens: end.num_gap_opens as usize,
+    }
+}
+
+#[test]
+fn score_matches_rust_bio() {
+    use bio::alignment::pairwise::Aligner;
+
+    let matrix = Matrix::blosum62();
+    let score = |a: u8, b: u8| matrix.score(a, b);
+    let mut aligner = Aligner::new(-10, -1, &score);
+
+    let pairs: [(&[u8], &[u8]); 4] = [
+        (b"ACDEFGHIKL", b"ACDEFGHIKL"),
+        (b"WWWACDEFGHIKL", b"ACDEFGHKL"),
+        (b"MKVLAAGIW", b"MKWLAGGIWPP"),
+        (b"A", b"WWWWW"),
+    ];
+    for (x, y) in pairs {
+        let expected = aligner.global(x, y);
+        let actual = align(x, y, &matrix, -10, -1);
+
+        assert_eq!(actual.score, expected.score);
+        assert_eq!(actual.aln_len, expected.operations.len());
+    }
+}
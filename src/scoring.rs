@@ -0,0 +1,437 @@
+//! Substitution matrices used to score aligned residues.
+
+use crate::error::{Error, Result};
+use bio::scores::{blosum62, pam120, pam200, pam250, pam40};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Residues scored by the rust-bio matrices.
+const BIO_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ*";
+
+/// The 20 standard amino acids.
+const STANDARD_AA: &[u8] = b"ARNDCQEGHILKMFPSTWYV";
+
+/// The IUPAC nucleotide codes, with the bases each stands for as bits (A, C, G, and T are 1, 2,
+/// 4, and 8).
+const IUPAC_CODES: [(u8, u8); 16] = [
+    (b'A', 0b0001),
+    (b'C', 0b0010),
+    (b'G', 0b0100),
+    (b'T', 0b1000),
+    (b'U', 0b1000),
+    (b'R', 0b0101),
+    (b'Y', 0b1010),
+    (b'S', 0b0110),
+    (b'W', 0b1001),
+    (b'K', 0b1100),
+    (b'M', 0b0011),
+    (b'B', 0b1110),
+    (b'D', 0b1101),
+    (b'H', 0b1011),
+    (b'V', 0b0111),
+    (b'N', 0b1111),
+];
+
+type ScoreFn = fn(u8, u8) -> i32;
+
+/// Is `residue` one of the 20 standard amino acids, in either case?
+pub fn is_standard(residue: u8) -> bool {
+    STANDARD_AA.contains(&residue.to_ascii_uppercase())
+}
+
+/// Could IUPAC nucleotide codes `a` and `b` be the same base, e.g., R (A or G) and A?
+pub fn iupac_overlap(a: u8, b: u8) -> bool {
+    let bases = |code: u8| {
+        IUPAC_CODES
+            .iter()
+            .find(|(c, _)| *c == code.to_ascii_uppercase())
+            .map_or(0, |(_, bases)| *bases)
+    };
+
+    bases(a) & bases(b) != 0
+}
+
+/// Built-in matrices, by name.
+const BUILTINS: [(&str, ScoreFn); 5] = [
+    ("blosum62", blosum62),
+    ("pam40", pam40),
+    ("pam120", pam120),
+    ("pam200", pam200),
+    ("pam250", pam250),
+];
+
+/// A substitution matrix, stored as a lookup table over all byte pairs.
+///
+/// Residues that the matrix doesn't define score the same as the lowest score in the matrix, so
+/// an odd character in the input discourages alignment rather than crashing the aligner.
+#[derive(Clone, Debug)]
+pub struct Matrix {
+    scores: Vec<i32>,
+}
+
+impl Matrix {
+    fn index(a: u8, b: u8) -> usize {
+        usize::from(a) * 256 + usize::from(b)
+    }
+
+    /// Build a matrix from scores for the residues in `alphabet`.
+    pub(crate) fn from_fn<F: Fn(u8, u8) -> i32>(alphabet: &[u8], f: F) -> Self {
+        let min = alphabet
+            .iter()
+            .flat_map(|&a| alphabet.iter().map(move |&b| (a, b)))
+            .map(|(a, b)| f(a, b))
+            .min()
+            .unwrap_or(0);
+
+        let mut scores = vec![min; 256 * 256];
+        for &a in alphabet {
+            for &b in alphabet {
+                scores[Self::index(a, b)] = f(a, b);
+            }
+        }
+
+        Self { scores }
+    }
+
+    /// The default matrix.
+    pub fn blosum62() -> Self {
+        Self::from_fn(BIO_ALPHABET, blosum62)
+    }
+
+    /// A nucleotide matrix, scoring `score` for the same base (with U the same as T) and
+    /// `mismatch` for anything else, including N.
+    pub fn match_mismatch(score: i32, mismatch: i32) -> Self {
+        let base = |c: u8| if c == b'U' { b'T' } else { c };
+
+        Self::from_fn(
+            b"ACGTU",
+            |a, b| {
+                if base(a) == base(b) {
+                    score
+                } else {
+                    mismatch
+                }
+            },
+        )
+    }
+
+    /// A nucleotide matrix like `match_mismatch`, but also scoring `score` for IUPAC ambiguity
+    /// codes that could be the same base (see `iupac_overlap`).
+    pub fn iupac(score: i32, mismatch: i32) -> Self {
+        let alphabet = IUPAC_CODES.map(|(code, _)| code);
+
+        Self::from_fn(&alphabet, |a, b| {
+            if iupac_overlap(a, b) {
+                score
+            } else {
+                mismatch
+            }
+        })
+    }
+
+    /// Look up one of the rust-bio matrices by name (case-insensitive).
+    pub fn builtin(name: &str) -> Option<Self> {
+        BUILTINS
+            .iter()
+            .find(|(builtin, _)| builtin.eq_ignore_ascii_case(name))
+            .map(|(_, f)| Self::from_fn(BIO_ALPHABET, f))
+    }
+
+    /// Parse a matrix in the NCBI format used by BLAST and EMBOSS.
+    ///
+    /// Lines starting with `#` are comments.  The first other line lists the residues of the
+    /// columns, and each following line is a residue followed by its scores against each
+    /// column.  The matrix may be asymmetric, in which case the row is the first residue given
+    /// to `score` (see `asymmetric_pair`).
+    pub fn from_ncbi(text: &str) -> Result<Self> {
+        let mut lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+        let header = lines
+            .next()
+            .ok_or_else(|| Error::parse("matrix has no header line"))?;
+        let columns = header
+            .split_whitespace()
+            .map(parse_residue)
+            .collect::<Result<Vec<u8>>>()?;
+
+        let mut entries = HashMap::with_capacity(columns.len() * columns.len());
+        let mut rows = Vec::with_capacity(columns.len());
+        for line in lines {
+            let mut fields = line.split_whitespace();
+            // Safe because empty lines were filtered out.
+            let row = parse_residue(fields.next().unwrap())?;
+
+            let scores = fields
+                .map(|field| {
+                    field.parse::<i32>().map_err(|_| {
+                        Error::parse(format!("bad score '{field}' in row {}", row as char))
+                    })
+                })
+                .collect::<Result<Vec<i32>>>()?;
+
+            if scores.len() != columns.len() {
+                return Err(Error::parse(format!(
+                    "row {} has {} scores but there are {} columns",
+                    row as char,
+                    scores.len(),
+                    columns.len()
+                )));
+            }
+
+            rows.push(row);
+            entries.extend(columns.iter().zip(scores).map(|(&col, s)| ((row, col), s)));
+        }
+
+        if rows.len() != columns.len() || rows.iter().any(|row| !columns.contains(row)) {
+            return Err(Error::parse("matrix rows must match its columns"));
+        }
+
+        // Safe because every row and column pair was checked above.
+        Ok(Self::from_fn(&columns, |a, b| entries[&(a, b)]))
+    }
+
+    /// Read an NCBI format matrix file (see `from_ncbi`).
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::from_ncbi(&text).map_err(|e| Error::InvalidFile {
+            kind: "matrix",
+            path: path.to_path_buf(),
+            source: Box::new(e),
+        })
+    }
+
+    /// A built-in matrix if `name` is one, otherwise a matrix file.
+    pub fn from_name_or_file(name: &str) -> Result<Self> {
+        match Self::builtin(name) {
+            Some(matrix) => Ok(matrix),
+            None => Self::from_file(Path::new(name)),
+        }
+    }
+
+    /// The weighted sum of several matrices, rounded to the nearest integer.
+    pub fn blend(weighted: &[(Matrix, f64)]) -> Self {
+        let scores = (0..256 * 256)
+            .map(|i| {
+                let score: f64 = weighted
+                    .iter()
+                    .map(|(matrix, weight)| weight * f64::from(matrix.scores[i]))
+                    .sum();
+
+                score.round() as i32
+            })
+            .collect();
+
+        Self { scores }
+    }
+
+    /// Score the given residues as 0 against everything (including themselves).
+    pub fn neutralize(&mut self, residues: &[u8]) {
+        self.fill(residues, 0);
+    }
+
+    /// Score the given residues as `score` against everything (including themselves).
+    pub fn fill(&mut self, residues: &[u8], score: i32) {
+        for &a in residues {
+            for b in 0..=255 {
+                self.scores[Self::index(a, b)] = score;
+                self.scores[Self::index(b, a)] = score;
+            }
+        }
+    }
+
+    /// Multiply every score by `factor`.
+    pub fn scale(&mut self, factor: i32) {
+        self.scores.iter_mut().for_each(|score| *score *= factor);
+    }
+
+    /// Replace the scores of the pairs in `table`, in both orders.
+    pub fn apply(&mut self, table: &ScoreTable) {
+        for &(a, b, score) in &table.entries {
+            self.scores[Self::index(a, b)] = score;
+            self.scores[Self::index(b, a)] = score;
+        }
+    }
+
+    /// A pair of residues that scores differently in the two orders, if there is one.
+    pub fn asymmetric_pair(&self) -> Option<(u8, u8)> {
+        (0..=255u8)
+            .flat_map(|a| (a..=255).map(move |b| (a, b)))
+            .find(|&(a, b)| self.score(a, b) != self.score(b, a))
+    }
+
+    /// The average score of each of the 20 standard amino acids against itself.
+    pub fn mean_self_score(&self) -> f64 {
+        let total: i32 = STANDARD_AA.iter().map(|&a| self.score(a, a)).sum();
+
+        f64::from(total) / STANDARD_AA.len() as f64
+    }
+
+    /// The score of `a` (from x, the row) against `b` (from y, the column).
+    #[inline]
+    pub fn score(&self, a: u8, b: u8) -> i32 {
+        self.scores[Self::index(a, b)]
+    }
+}
+
+/// Scores for a few residue pairs, to override those of a base matrix.
+#[derive(Clone, Debug)]
+pub struct ScoreTable {
+    entries: Vec<(u8, u8, i32)>,
+}
+
+impl ScoreTable {
+    /// Parse lines like `A C 5`, i.e., two residues and their score.
+    ///
+    /// Lines starting with `#` are comments.  If a pair shows up more than once (in either
+    /// order), the last one wins.
+    pub fn from_text(text: &str) -> Result<Self> {
+        let entries = text
+            .lines()
+            .map(str::trim)
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(
+                |(i, line)| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                    [a, b, score] => {
+                        let score = score.parse::<i32>().map_err(|_| {
+                            Error::parse(format!("bad score '{score}' on line {}", i + 1))
+                        })?;
+
+                        Ok((parse_residue(a)?, parse_residue(b)?, score))
+                    }
+                    _ => Err(Error::parse(format!(
+                        "line {} should have two residues and a score",
+                        i + 1
+                    ))),
+                },
+            )
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { entries })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::from_text(&text).map_err(|e| Error::InvalidFile {
+            kind: "score table",
+            path: path.to_path_buf(),
+            source: Box::new(e),
+        })
+    }
+}
+
+fn parse_residue(s: &str) -> Result<u8> {
+    match s.as_bytes() {
+        [c] => Ok(c.to_ascii_uppercase()),
+        _ => Err(Error::parse(format!(
+            "expected a single residue, got '{s}'"
+        ))),
+    }
+}
+
+#[test]
+fn ncbi_matrix_round_trips_blosum62() {
+    let alphabet = "ARNDCQEGHILKMFPSTWYVBZX*";
+    let header = alphabet
+        .chars()
+        .map(|c| format!("  {c}"))
+        .collect::<String>();
+    let rows = alphabet
+        .bytes()
+        .map(|a| {
+            let scores = alphabet
+                .bytes()
+                .map(|b| format!(" {:>2}", blosum62(a, b)))
+                .collect::<String>();
+            format!("{}{scores}", a as char)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let text = format!("# BLOSUM62\n{header}\n{rows}\n");
+
+    let matrix = Matrix::from_ncbi(&text).unwrap();
+    let expected = Matrix::blosum62();
+
+    for a in alphabet.bytes() {
+        for b in alphabet.bytes() {
+            assert_eq!(matrix.score(a, b), expected.score(a, b));
+        }
+    }
+}
+
+#[test]
+fn score_table_overrides_pairs_in_both_orders() {
+    let table = ScoreTable::from_text("# tweaks\nW c -6\nA A 9\n").unwrap();
+
+    let mut matrix = Matrix::blosum62();
+    matrix.apply(&table);
+
+    assert_eq!(matrix.score(b'W', b'C'), -6);
+    assert_eq!(matrix.score(b'C', b'W'), -6);
+    assert_eq!(matrix.score(b'A', b'A'), 9);
+    assert_eq!(matrix.score(b'A', b'R'), blosum62(b'A', b'R'));
+
+    assert!(ScoreTable::from_text("A C\n").is_err());
+}
+
+#[test]
+fn filled_residues_score_the_same_against_everything() {
+    let mut matrix = Matrix::blosum62();
+    matrix.fill(b"*", -20);
+
+    assert_eq!(matrix.score(b'*', b'*'), -20);
+    assert_eq!(matrix.score(b'W', b'*'), -20);
+    assert_eq!(matrix.score(b'*', b'x'), -20);
+    assert_eq!(matrix.score(b'W', b'W'), blosum62(b'W', b'W'));
+}
+
+#[test]
+fn nucleotide_matrix_scores_matches_and_mismatches() {
+    let matrix = Matrix::match_mismatch(2, -3);
+    assert_eq!(matrix.score(b'A', b'A'), 2);
+    assert_eq!(matrix.score(b'T', b'U'), 2);
+    assert_eq!(matrix.score(b'A', b'G'), -3);
+    assert_eq!(matrix.score(b'N', b'N'), -3);
+    assert_eq!(matrix.asymmetric_pair(), None);
+}
+
+#[test]
+fn iupac_codes_match_the_bases_they_stand_for() {
+    let matrix = Matrix::iupac(2, -3);
+    assert_eq!(matrix.score(b'R', b'A'), 2);
+    assert_eq!(matrix.score(b'R', b'G'), 2);
+    assert_eq!(matrix.score(b'R', b'C'), -3);
+    assert_eq!(matrix.score(b'R', b'Y'), -3);
+    assert_eq!(matrix.score(b'S', b'K'), 2);
+    assert_eq!(matrix.score(b'N', b'T'), 2);
+    assert_eq!(matrix.score(b'Y', b'U'), 2);
+    assert_eq!(matrix.score(b'A', b'X'), -3);
+    assert_eq!(matrix.asymmetric_pair(), None);
+}
+
+#[test]
+fn blosum62_mean_self_score() {
+    assert_eq!(Matrix::blosum62().mean_self_score(), 5.8);
+}
+
+#[test]
+fn asymmetric_matrices_are_detected() {
+    assert_eq!(Matrix::blosum62().asymmetric_pair(), None);
+
+    let matrix = Matrix::from_ncbi("   A  C\nA  4 -1\nC -3  9\n").unwrap();
+    assert_eq!(matrix.score(b'A', b'C'), -1);
+    assert_eq!(matrix.score(b'C', b'A'), -3);
+    assert_eq!(matrix.asymmetric_pair(), Some((b'A', b'C')));
+}
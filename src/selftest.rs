@@ -0,0 +1,106 @@
+//! The `selftest` subcommand: an all-vs-all on synthetic data.
+
+use crate::cli::{Cli, Command, SelftestArgs};
+use bio::io::fasta::Writer;
+use clap::Parser;
+use std::fs;
+use std::time::Instant;
+
+const AMINO_ACIDS: &[u8] = b"ACDEFGHIKLMNPQRSTVWY";
+
+/// Pairs with a known percent identity, added to the random sequences as a correctness check.
+const KNOWN_PAIRS: [(&[u8], &[u8], f64); 2] = [
+    (b"MKVLAAGIVGLLLAQ", b"MKVLAAGIVGLLLAQ", 1.0),
+    (b"ACDEFGHIKL", b"ACDEFWHIKL", 0.9),
+];
+
+/// A tiny LCG so we don't need a random number crate.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+}
+
+/// Write the random and known sequences, run the normal `align` on them, and check the output.
+///
+/// Exits with an error if any known pair has the wrong pid.
+pub fn selftest(args: SelftestArgs) {
+    let dir = std::env::temp_dir().join(format!("align-selftest-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let in_file = dir.join("seqs.fasta");
+    let out_file = dir.join("out.tsv");
+
+    let mut rng = Lcg(args.seed);
+    let mut writer = Writer::to_file(&in_file).unwrap();
+    for i in 0..args.num_seqs() {
+        let seq: Vec<u8> = (0..args.seq_len())
+            .map(|_| AMINO_ACIDS[rng.next() as usize % AMINO_ACIDS.len()])
+            .collect();
+        writer.write(&format!("random_{i}"), None, &seq).unwrap();
+    }
+    for (i, (x, y, _)) in KNOWN_PAIRS.iter().enumerate() {
+        writer.write(&format!("known_{i}_x"), None, x).unwrap();
+        writer.write(&format!("known_{i}_y"), None, y).unwrap();
+    }
+    drop(writer);
+
+    let cli = Cli::parse_from([
+        "align".as_ref(),
+        "align".as_ref(),
+        "--threads".as_ref(),
+        args.threads.to_string().as_ref(),
+        in_file.as_os_str(),
+        out_file.as_os_str(),
+    ]);
+    let Command::Align(align_args) = cli.into_command() else {
+        unreachable!()
+    };
+
+    let num_seqs = args.num_seqs() + 2 * KNOWN_PAIRS.len();
+    let num_pairs = num_seqs * (num_seqs - 1) / 2;
+
+    let start = Instant::now();
+    if let Err(e) = crate::align(align_args) {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    println!(
+        "aligned {num_pairs} pairs ({num_seqs} sequences) with {} threads in {elapsed:.2}s ({:.1} pairs/s)",
+        args.threads,
+        num_pairs as f64 / elapsed
+    );
+
+    let output = fs::read_to_string(&out_file).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let mut ok = true;
+    for (i, (_, _, expected)) in KNOWN_PAIRS.iter().enumerate() {
+        let (x, y) = (format!("known_{i}_x"), format!("known_{i}_y"));
+        let pid = output
+            .lines()
+            .map(|line| line.split('\t').collect::<Vec<_>>())
+            .find(|fields| fields[0] == x && fields[1] == y)
+            .map(|fields| fields[6].parse::<f64>().unwrap());
+
+        match pid {
+            Some(pid) if (pid - expected).abs() < 1e-4 => println!("{x} vs. {y}: ok"),
+            _ => {
+                println!("{x} vs. {y}: expected pid {expected}, got {pid:?}");
+                ok = false;
+            }
+        }
+    }
+
+    if !ok {
+        eprintln!("error: selftest failed");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,87 @@
+//! Splitting the all-vs-all into shards, for `--shard`.
+
+use crate::error::{Error, Result};
+use std::ops::Range;
+
+/// Shard `index` (1-based) of `count`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Shard {
+    index: usize,
+    count: usize,
+}
+
+impl Shard {
+    /// Parses `I/N`, with `1 <= I <= N`.
+    pub fn parse(s: &str) -> Result<Self> {
+        let bad = || Error::parse(format!("'{s}' should look like I/N, e.g., 2/8"));
+
+        let (index, count) = s.split_once('/').ok_or_else(bad)?;
+        let index: usize = index.trim().parse().map_err(|_| bad())?;
+        let count: usize = count.trim().parse().map_err(|_| bad())?;
+
+        if index == 0 || index > count {
+            return Err(Error::parse(format!(
+                "shard {index} should be between 1 and {count}"
+            )));
+        }
+
+        Ok(Self { index, count })
+    }
+
+    pub fn is_first(&self) -> bool {
+        self.index == 1
+    }
+
+    /// The records whose pairs (with themselves and every later record) are in this shard.
+    ///
+    /// Record i starts `num_records - i` pairs (counting its self-hit), so the ranges are picked
+    /// to split the pairs about evenly rather than the records.  Together the shards cover every
+    /// record exactly once.
+    pub fn records(&self, num_records: usize) -> Range<usize> {
+        self.boundary(self.index - 1, num_records)..self.boundary(self.index, num_records)
+    }
+
+    /// The first record of shard `k + 1`, i.e., the first after `k` shards' worth of pairs.
+    fn boundary(&self, k: usize, n: usize) -> usize {
+        let (n, k, count) = (n as u64, k as u64, self.count as u64);
+        let total = n * (n + 1) / 2;
+        // Pairs started by the first x records.
+        let pairs_before = |x: u64| x * n - x * x.saturating_sub(1) / 2;
+
+        (0..=n)
+            .find(|&x| pairs_before(x) * count >= k * total)
+            .unwrap() as usize
+    }
+}
+
+impl std::fmt::Display for Shard {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+#[test]
+fn shards_partition_the_records() {
+    assert!(Shard::parse("0/3").is_err());
+    assert!(Shard::parse("4/3").is_err());
+    assert!(Shard::parse("3").is_err());
+
+    for n in [0, 1, 2, 7, 100] {
+        for count in [1, 2, 3, 8] {
+            let ranges = (1..=count)
+                .map(|i| Shard::parse(&format!("{i}/{count}")).unwrap().records(n))
+                .collect::<Vec<_>>();
+
+            assert_eq!(ranges[0].start, 0);
+            assert_eq!(ranges[count - 1].end, n);
+            ranges
+                .windows(2)
+                .for_each(|w| assert_eq!(w[0].end, w[1].start));
+        }
+    }
+
+    // 100 records start 5050 pairs, so each of 2 shards should get about half.
+    let first = Shard::parse("1/2").unwrap().records(100);
+    let pairs = first.map(|x| 100 - x).sum::<usize>();
+    assert!(pairs.abs_diff(2525) < 100);
+}
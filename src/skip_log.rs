@@ -0,0 +1,29 @@
+//! A log of the pairs that were left out of the rows, and the filter that left out each one,
+//! for `--log-skipped`.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+pub struct SkipLog {
+    out: Mutex<BufWriter<File>>,
+}
+
+impl SkipLog {
+    /// Create the log at `path`, with its header.
+    pub fn new(path: &Path) -> Self {
+        let mut out = BufWriter::new(File::create(path).unwrap());
+        writeln!(out, "x\ty\tfilter").unwrap();
+
+        Self {
+            out: Mutex::new(out),
+        }
+    }
+
+    /// Log that the pair of `x_id` and `y_id` was skipped by `filter`, e.g., the flag's name.
+    pub fn log(&self, x_id: &str, y_id: &str, filter: &str) {
+        let out = &mut *self.out.lock().unwrap();
+        writeln!(out, "{x_id}\t{y_id}\t{filter}").unwrap();
+    }
+}
@@ -0,0 +1,119 @@
+//! Counts of which residues were aligned to which, summed over all alignments.
+
+use bio::alignment::{Alignment, AlignmentOperation};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// The residues counted, in the usual BLOSUM order.  Anything else is ignored.
+const RESIDUES: &[u8; 20] = b"ARNDCQEGHILKMFPSTWYV";
+
+/// A 20 x 20 count of aligned residue pairs, with x residues as rows and y residues as columns.
+///
+/// Each worker keeps its own, and they are merged when the workers finish.
+#[derive(Clone)]
+pub struct SubstCounts {
+    /// Row (or column) of each byte in the table, if it's one of the `RESIDUES`.
+    index: [Option<u8>; 256],
+    counts: Vec<u64>,
+}
+
+impl Default for SubstCounts {
+    fn default() -> Self {
+        let mut index = [None; 256];
+        for (i, &residue) in RESIDUES.iter().enumerate() {
+            index[usize::from(residue)] = Some(i as u8);
+        }
+
+        Self {
+            index,
+            counts: vec![0; RESIDUES.len() * RESIDUES.len()],
+        }
+    }
+}
+
+impl SubstCounts {
+    fn count(&mut self, a: u8, b: u8) {
+        if let (Some(i), Some(j)) = (self.index[usize::from(a)], self.index[usize::from(b)]) {
+            self.counts[usize::from(i) * RESIDUES.len() + usize::from(j)] += 1;
+        }
+    }
+
+    /// Count the Match and Subst columns of an alignment of `x` and `y`.
+    pub fn add(&mut self, x: &[u8], y: &[u8], alignment: &Alignment) {
+        let (mut i, mut j) = (alignment.xstart, alignment.ystart);
+
+        for op in &alignment.operations {
+            match op {
+                AlignmentOperation::Match | AlignmentOperation::Subst => {
+                    self.count(x[i], y[j]);
+                    i += 1;
+                    j += 1;
+                }
+                AlignmentOperation::Ins => i += 1,
+                AlignmentOperation::Del => j += 1,
+                AlignmentOperation::Xclip(n) => i += n,
+                AlignmentOperation::Yclip(n) => j += n,
+            }
+        }
+    }
+
+    /// Count two copies of `seq` aligned end to end.
+    pub fn add_identical(&mut self, seq: &[u8]) {
+        seq.iter().for_each(|&c| self.count(c, c));
+    }
+
+    pub fn merge(&mut self, other: &SubstCounts) {
+        self.counts
+            .iter_mut()
+            .zip(&other.counts)
+            .for_each(|(a, b)| *a += b);
+    }
+
+    /// Write the counts as a TSV table, with a header row and column of residues.
+    pub fn write(&self, path: &Path) {
+        let mut out = BufWriter::new(File::create(path).unwrap());
+
+        let header = RESIDUES
+            .iter()
+            .map(|&c| format!("\t{}", c as char))
+            .collect::<String>();
+        writeln!(out, "x\\y{header}").unwrap();
+
+        for (&residue, row) in RESIDUES.iter().zip(self.counts.chunks(RESIDUES.len())) {
+            let row = row.iter().map(|n| format!("\t{n}")).collect::<String>();
+            writeln!(out, "{}{row}", residue as char).unwrap();
+        }
+    }
+}
+
+#[test]
+fn aligned_columns_are_counted() {
+    use AlignmentOperation::*;
+
+    let alignment = Alignment {
+        score: 0,
+        xstart: 0,
+        ystart: 0,
+        xend: 4,
+        yend: 3,
+        xlen: 4,
+        ylen: 3,
+        operations: vec![Match, Ins, Subst, Match],
+        mode: bio::alignment::AlignmentMode::Global,
+    };
+
+    let mut counts = SubstCounts::default();
+    counts.add(b"ACDE", b"AKE", &alignment);
+    counts.add_identical(b"AX");
+
+    let at = |a: u8, b: u8| {
+        let (i, j) = (counts.index[usize::from(a)], counts.index[usize::from(b)]);
+        counts.counts[usize::from(i.unwrap()) * RESIDUES.len() + usize::from(j.unwrap())]
+    };
+    assert_eq!(at(b'A', b'A'), 2);
+    assert_eq!(at(b'D', b'K'), 1);
+    assert_eq!(at(b'E', b'E'), 1);
+    assert_eq!(at(b'C', b'K'), 0);
+    assert_eq!(counts.counts.iter().sum::<u64>(), 4);
+}
@@ -0,0 +1,109 @@
+//! Pausing and resuming workers from a control file, for `--control-file`.
+
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{self, AtomicBool, AtomicUsize};
+use std::thread;
+use std::time::Duration;
+
+/// How often the control file is read.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a paused worker checks whether it can go on.
+const WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The number of workers allowed to take pairs.  The others wait, by worker index.
+pub struct Throttle {
+    num_workers: usize,
+    active: AtomicUsize,
+    /// Set once every pair has been sent, so paused workers can quit when the queue is empty.
+    all_sent: AtomicBool,
+    /// Set once the workers are done, to stop polling.
+    finished: AtomicBool,
+}
+
+impl Throttle {
+    /// All workers start out active.
+    pub fn new(num_workers: usize) -> Self {
+        Self {
+            num_workers,
+            active: AtomicUsize::new(num_workers),
+            all_sent: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    /// Block worker `worker` while it's paused.
+    ///
+    /// Returns false, without waiting any more, once `give_up` is true.
+    pub fn wait(&self, worker: usize, give_up: impl Fn() -> bool) -> bool {
+        while worker >= self.active.load(atomic::Ordering::Relaxed) {
+            if give_up() {
+                return false;
+            }
+            thread::sleep(WAIT_INTERVAL);
+        }
+
+        true
+    }
+
+    pub fn set_all_sent(&self) {
+        self.all_sent.store(true, atomic::Ordering::Relaxed);
+    }
+
+    pub fn all_sent(&self) -> bool {
+        self.all_sent.load(atomic::Ordering::Relaxed)
+    }
+
+    pub fn finish(&self) {
+        self.finished.store(true, atomic::Ordering::Relaxed);
+    }
+
+    /// Set the number of active workers, at most the number of workers.  Returns the new number.
+    fn set_active(&self, active: usize) -> usize {
+        let active = active.min(self.num_workers);
+        self.active.store(active, atomic::Ordering::Relaxed);
+        active
+    }
+
+    /// Read the number of active workers from `path` every second, until `finish` is called.
+    ///
+    /// A missing file leaves the number as it was, and so does one that isn't a number, with a
+    /// warning.
+    pub fn poll(&self, path: &Path) {
+        let mut last = None;
+
+        while !self.finished.load(atomic::Ordering::Relaxed) {
+            if let Ok(text) = fs::read_to_string(path) {
+                let text = text.trim().to_string();
+                if last.as_ref() != Some(&text) {
+                    match text.parse::<usize>() {
+                        Ok(n) => {
+                            let active = self.set_active(n);
+                            eprintln!("control file: {active} of {} workers active", self.num_workers);
+                        }
+                        Err(_) => eprintln!(
+                            "warning: ignoring {}, which should hold a number of threads, not {text:?}",
+                            path.display()
+                        ),
+                    }
+                    last = Some(text);
+                }
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+#[test]
+fn only_workers_below_the_active_count_go_on() {
+    let throttle = Throttle::new(4);
+    assert_eq!(throttle.set_active(9), 4);
+    assert!(throttle.wait(3, || false));
+
+    assert_eq!(throttle.set_active(1), 1);
+    assert!(throttle.wait(0, || false));
+    // Worker 1 is paused, so it only gets out by giving up.
+    assert!(!throttle.wait(1, || true));
+}
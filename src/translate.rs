@@ -0,0 +1,138 @@
+//! Translating nucleotide sequences in each reading frame, for `--translate`.
+
+use bio::io::fasta::Record;
+use std::fmt;
+
+/// The standard genetic code, with the codons in TCAG order, e.g., TTT, TTC, TTA, TTG, TCT, ...
+const STANDARD_CODE: &[u8; 64] =
+    b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+
+/// A reading frame: 1, 2, or 3 for the forward strand, and -1, -2, or -3 for the reverse
+/// complement, starting at that (1-based) position of the strand.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frame(i8);
+
+impl Frame {
+    /// The forward frames, then the reverse ones if `six_frame` is true.
+    pub fn all(six_frame: bool) -> Vec<Self> {
+        let reverse = if six_frame { &[-1, -2, -3][..] } else { &[] };
+
+        [1, 2, 3].iter().chain(reverse).map(|&i| Frame(i)).collect()
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:+}", self.0)
+    }
+}
+
+/// Where `base` is in TCAG order, or `None` for an ambiguous base.  U counts as T.
+fn base_index(base: u8) -> Option<usize> {
+    match base.to_ascii_uppercase() {
+        b'T' | b'U' => Some(0),
+        b'C' => Some(1),
+        b'A' => Some(2),
+        b'G' => Some(3),
+        _ => None,
+    }
+}
+
+fn complement(base: u8) -> u8 {
+    match base.to_ascii_uppercase() {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' | b'U' => b'A',
+        _ => b'N',
+    }
+}
+
+/// Translate `seq` in `frame` with the standard code.
+///
+/// Stops are `*`, codons with an ambiguous base are `X`, and a partial codon at the end is
+/// dropped.
+pub fn translate(seq: &[u8], frame: Frame) -> Vec<u8> {
+    let strand = if frame.0 > 0 {
+        seq.to_vec()
+    } else {
+        seq.iter().rev().map(|&base| complement(base)).collect()
+    };
+    let start = (frame.0.unsigned_abs() as usize - 1).min(strand.len());
+
+    strand[start..]
+        .chunks_exact(3)
+        .map(|codon| {
+            codon
+                .iter()
+                .try_fold(0, |i, &base| Some(i * 4 + base_index(base)?))
+                .map_or(b'X', |i| STANDARD_CODE[i])
+        })
+        .collect()
+}
+
+/// A frame and the translation in it.
+type Translated<'a> = (Frame, &'a [u8]);
+
+/// The translations of each record in each frame.
+pub struct Translations {
+    /// `seqs[i]` has the frames of record `i`, in the order of `Frame::all`.
+    seqs: Vec<Vec<(Frame, Vec<u8>)>>,
+}
+
+impl Translations {
+    pub fn new(records: &[Record], six_frame: bool) -> Self {
+        let frames = Frame::all(six_frame);
+        let seqs = records
+            .iter()
+            .map(|record| {
+                frames
+                    .iter()
+                    .map(|&frame| (frame, translate(record.seq(), frame)))
+                    .collect()
+            })
+            .collect();
+
+        Self { seqs }
+    }
+
+    /// The pair of frames of records `x_index` and `y_index` whose translations have the best
+    /// `score`, and those translations.  Ties go to the earlier frames.
+    pub fn best_frames(
+        &self,
+        x_index: usize,
+        y_index: usize,
+        mut score: impl FnMut(&[u8], &[u8]) -> i32,
+    ) -> (Translated<'_>, Translated<'_>) {
+        let mut best: Option<(i32, _)> = None;
+
+        for (x_frame, x_seq) in &self.seqs[x_index] {
+            for (y_frame, y_seq) in &self.seqs[y_index] {
+                let s = score(x_seq, y_seq);
+                if best.as_ref().is_none_or(|(best_score, _)| s > *best_score) {
+                    best = Some((s, ((*x_frame, &x_seq[..]), (*y_frame, &y_seq[..]))));
+                }
+            }
+        }
+
+        // Safe because there are always at least the three forward frames.
+        best.unwrap().1
+    }
+}
+
+#[test]
+fn frames_are_translated_with_the_standard_code() {
+    // M K * in frame 1, and the reverse complement is CTTACTTCAT.
+    let seq = b"ATGAAGTAAG";
+    assert_eq!(translate(seq, Frame(1)), b"MK*");
+    assert_eq!(translate(seq, Frame(2)), b"*SK");
+    assert_eq!(translate(seq, Frame(-1)), b"LTS");
+    assert_eq!(translate(seq, Frame(-3)), b"YF");
+    assert_eq!(translate(b"AUGNCC", Frame(1)), b"MX");
+    assert_eq!(translate(b"A", Frame(3)), b"");
+
+    let frames = Frame::all(true);
+    assert_eq!(frames.len(), 6);
+    assert_eq!(frames[3].to_string(), "-1");
+    assert_eq!(frames[0].to_string(), "+1");
+}
@@ -0,0 +1,55 @@
+//! Ungapped alignment: the best diagonal, with no indels.
+
+use crate::scoring::Matrix;
+
+/// The best diagonal found by `best_diagonal`.
+pub struct Diagonal {
+    /// Where `y[0]` lines up on `x`.  Negative if y starts before x.
+    pub offset: isize,
+    pub score: i32,
+    /// Number of overlapping positions.
+    pub len: usize,
+    pub num_matches: usize,
+}
+
+/// Slide `y` along `x` and find the offset whose overlap scores best.
+///
+/// The whole overlap is scored, not just its best segment.  Ties go to the smallest offset.  The
+/// overlap has to be at least one residue, so this is `None` only if either sequence is empty.
+pub fn best_diagonal(x: &[u8], y: &[u8], matrix: &Matrix) -> Option<Diagonal> {
+    let (n, m) = (x.len() as isize, y.len() as isize);
+
+    ((1 - m)..n)
+        .map(|offset| {
+            let x_start = offset.max(0) as usize;
+            let y_start = (-offset).max(0) as usize;
+            let len = (x.len() - x_start).min(y.len() - y_start);
+
+            let pairs = x[x_start..x_start + len]
+                .iter()
+                .zip(&y[y_start..y_start + len]);
+
+            let (score, num_matches) = pairs.fold((0, 0), |(score, matches), (&a, &b)| {
+                (score + matrix.score(a, b), matches + usize::from(a == b))
+            });
+
+            Diagonal {
+                offset,
+                score,
+                len,
+                num_matches,
+            }
+        })
+        .reduce(|best, d| if d.score > best.score { d } else { best })
+}
+
+#[test]
+fn finds_the_shifted_copy() {
+    let matrix = Matrix::blosum62();
+
+    let d = best_diagonal(b"WWACDEFGH", b"ACDEFGH", &matrix).unwrap();
+    assert_eq!((d.offset, d.len, d.num_matches), (2, 7, 7));
+
+    let d = best_diagonal(b"ACDEFGH", b"WWACDEFGH", &matrix).unwrap();
+    assert_eq!((d.offset, d.len, d.num_matches), (-2, 7, 7));
+}
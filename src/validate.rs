@@ -0,0 +1,225 @@
+//! The `validate` subcommand: check the input sequences without aligning them.
+
+use crate::cli::ValidateArgs;
+use crate::input::{fasta_files, open_fasta};
+use bio::io::fasta::Record;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The residues BLOSUM62 scores properly.
+const STANDARD_AA: &[u8] = b"ACDEFGHIKLMNPQRSTVWY";
+
+/// Residues that are common in nucleotide sequences.
+const NUCLEOTIDES: &[u8] = b"ACGTUN";
+
+/// The nucleotides, along with the rest of the IUPAC ambiguity codes (see --iupac).
+const IUPAC_NUCLEOTIDES: &[u8] = b"ACGTUNRYSWKMBDHV";
+
+/// Sequences with at least this fraction of nucleotide letters are probably DNA or RNA.
+const NUCLEOTIDE_FRACTION: f64 = 0.9;
+
+/// Something wrong with the input.
+enum Issue {
+    Unreadable { file: PathBuf, error: String },
+    Empty { id: String },
+    DuplicateId { id: String, count: usize },
+    Nonstandard { id: String, residues: String },
+    Nucleotide { id: String },
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Issue::Unreadable { file, error } => {
+                write!(f, "can't read {}: {error}", file.display())
+            }
+            Issue::Empty { id } => write!(f, "sequence {id} is empty"),
+            Issue::DuplicateId { id, count } => write!(f, "id {id} is used {count} times"),
+            Issue::Nonstandard { id, residues } => {
+                write!(f, "sequence {id} has nonstandard residues {residues}")
+            }
+            Issue::Nucleotide { id } => write!(f, "sequence {id} looks like DNA or RNA"),
+        }
+    }
+}
+
+/// Check one sequence on its own.
+fn check_record(record: &Record, issues: &mut Vec<Issue>) {
+    let id = record.id().to_string();
+    let seq = record.seq().to_ascii_uppercase();
+
+    if seq.is_empty() {
+        issues.push(Issue::Empty { id });
+        return;
+    }
+
+    let mut nonstandard = seq
+        .iter()
+        .copied()
+        .filter(|c| !STANDARD_AA.contains(c))
+        .collect::<Vec<_>>();
+    if !nonstandard.is_empty() {
+        nonstandard.sort_unstable();
+        nonstandard.dedup();
+        issues.push(Issue::Nonstandard {
+            id: id.clone(),
+            residues: String::from_utf8_lossy(&nonstandard).into_owned(),
+        });
+    }
+
+    if looks_like_nucleotide(&seq) {
+        issues.push(Issue::Nucleotide { id });
+    }
+}
+
+/// Whether `seq` is probably DNA or RNA rather than protein.
+pub fn looks_like_nucleotide(seq: &[u8]) -> bool {
+    mostly_from(seq, NUCLEOTIDES)
+}
+
+/// Whether `seq` is probably DNA or RNA, counting the IUPAC ambiguity codes as nucleotides too.
+///
+/// Most of the codes are amino acids as well, so this is only for input that's meant to be
+/// nucleotides, i.e., with --iupac.
+pub fn looks_like_iupac_nucleotide(seq: &[u8]) -> bool {
+    mostly_from(seq, IUPAC_NUCLEOTIDES)
+}
+
+/// Whether at least `NUCLEOTIDE_FRACTION` of `seq` is from `alphabet`.  False for an empty `seq`.
+fn mostly_from(seq: &[u8], alphabet: &[u8]) -> bool {
+    if seq.is_empty() {
+        return false;
+    }
+
+    let num_nucleotides = seq
+        .iter()
+        .filter(|c| alphabet.contains(&c.to_ascii_uppercase()))
+        .count();
+
+    num_nucleotides as f64 >= NUCLEOTIDE_FRACTION * seq.len() as f64
+}
+
+/// Read every record of `file` that parses, noting the first one that doesn't.
+fn read_file(file: &Path, records: &mut Vec<Record>, issues: &mut Vec<Issue>) {
+    let reader = match open_fasta(file) {
+        Ok(reader) => reader,
+        Err(e) => {
+            issues.push(Issue::Unreadable {
+                file: file.to_path_buf(),
+                error: e.to_string(),
+            });
+            return;
+        }
+    };
+
+    for record in reader.records() {
+        match record {
+            Ok(record) => records.push(record),
+            Err(e) => {
+                issues.push(Issue::Unreadable {
+                    file: file.to_path_buf(),
+                    error: e.to_string(),
+                });
+                return;
+            }
+        }
+    }
+}
+
+/// All the problems with the records in `path` (a file or directory).
+fn find_issues(path: &Path, recursive: bool) -> (usize, Vec<Issue>) {
+    let files = if path.is_dir() {
+        match fasta_files(path, recursive) {
+            Ok(files) => files,
+            Err(e) => {
+                let issue = Issue::Unreadable {
+                    file: path.to_path_buf(),
+                    error: e.to_string(),
+                };
+                return (0, vec![issue]);
+            }
+        }
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut records = Vec::new();
+    let mut issues = Vec::new();
+    files
+        .iter()
+        .for_each(|file| read_file(file, &mut records, &mut issues));
+
+    records
+        .iter()
+        .for_each(|record| check_record(record, &mut issues));
+
+    let mut id_counts: HashMap<&str, usize> = HashMap::new();
+    for record in &records {
+        *id_counts.entry(record.id()).or_default() += 1;
+    }
+    let mut duplicates = id_counts
+        .into_iter()
+        .filter(|&(_, count)| count > 1)
+        .collect::<Vec<_>>();
+    duplicates.sort_unstable();
+    issues.extend(
+        duplicates
+            .into_iter()
+            .map(|(id, count)| Issue::DuplicateId {
+                id: id.to_string(),
+                count,
+            }),
+    );
+
+    (records.len(), issues)
+}
+
+/// Print a report of the problems with the input.  Exits with an error if there are any.
+pub fn validate(args: ValidateArgs) {
+    let (num_records, issues) = find_issues(&args.in_file, args.recursive);
+
+    issues.iter().for_each(|issue| println!("{issue}"));
+    println!(
+        "checked {num_records} records, found {} issues",
+        issues.len()
+    );
+
+    if !issues.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+#[test]
+fn problems_are_found() {
+    let dir = std::env::temp_dir().join(format!("align-validate-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("seqs.fasta");
+    std::fs::write(
+        &path,
+        ">a\nMKVLAAGIW\n>b\nACGTACGTAC\n>a\nMKUL\n>c\n\n>d\nMKVL\n",
+    )
+    .unwrap();
+
+    let (num_records, issues) = find_issues(&path, false);
+    let issues = issues.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(num_records, 5);
+    assert_eq!(
+        issues,
+        vec![
+            "sequence b looks like DNA or RNA",
+            "sequence a has nonstandard residues U",
+            "sequence c is empty",
+            "id a is used 2 times",
+        ]
+    );
+}
+
+#[test]
+fn empty_sequences_do_not_look_like_nucleotides() {
+    assert!(!looks_like_nucleotide(b""));
+    assert!(!looks_like_iupac_nucleotide(b""));
+    assert!(looks_like_nucleotide(b"ACGTN"));
+    assert!(looks_like_iupac_nucleotide(b"RYKMACGT"));
+}
@@ -0,0 +1,60 @@
+//! The `--verify` check that global alignment gives the same result in both directions.
+
+use crate::{alignment_length, count_identities};
+use bio::alignment::Alignment;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Re-aligns a sample of pairs as y vs. x and counts any that disagree with x vs. y.
+pub struct Verifier {
+    fraction: f64,
+    checked: AtomicUsize,
+    asymmetric: AtomicUsize,
+}
+
+impl Verifier {
+    pub fn new(fraction: f64) -> Self {
+        Self {
+            fraction,
+            checked: AtomicUsize::new(0),
+            asymmetric: AtomicUsize::new(0),
+        }
+    }
+
+    /// Should this pair be checked?
+    ///
+    /// The sample is based on a hash of the indices, so it's the same from run to run regardless
+    /// of the number of threads.
+    pub fn sampled(&self, x_index: usize, y_index: usize) -> bool {
+        // splitmix64's finalizer
+        let mut h = ((x_index as u64) << 32 | y_index as u64).wrapping_add(0x9e3779b97f4a7c15);
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+        h ^= h >> 31;
+
+        (h as f64 / u64::MAX as f64) < self.fraction
+    }
+
+    /// Compare the x vs. y alignment with the y vs. x one, and warn if they differ.
+    pub fn check(&self, x_id: &str, y_id: &str, forward: &Alignment, backward: &Alignment) {
+        self.checked.fetch_add(1, Ordering::Relaxed);
+
+        let forward = (count_identities(forward), alignment_length(forward));
+        let backward = (count_identities(backward), alignment_length(backward));
+
+        if forward != backward {
+            self.asymmetric.fetch_add(1, Ordering::Relaxed);
+            eprintln!(
+                "warning: {x_id} vs. {y_id} has {} matches over {} columns, but {y_id} vs. {x_id} has {} over {}",
+                forward.0, forward.1, backward.0, backward.1
+            );
+        }
+    }
+
+    pub fn report(&self) {
+        eprintln!(
+            "verified {} pairs, {} asymmetric",
+            self.checked.load(Ordering::Relaxed),
+            self.asymmetric.load(Ordering::Relaxed)
+        );
+    }
+}
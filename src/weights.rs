@@ -0,0 +1,85 @@
+//! Per-record weights, e.g., from sequence quality, for the pair_weight column of `--weights`.
+
+use crate::error::{Error, Result};
+use bio::io::fasta::Record;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// A table of `id<TAB>weight` lines.
+#[derive(Clone)]
+pub struct WeightTable {
+    weights: HashMap<String, f64>,
+}
+
+impl WeightTable {
+    /// Blank lines and lines starting with `#` are skipped.  Weights can't be negative or NaN.
+    pub fn from_text(text: &str) -> Result<Self> {
+        let weights = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| {
+                let (id, weight) = line.split_once('\t').ok_or_else(|| {
+                    Error::parse(format!(
+                        "line {} should have an id and a weight, separated by a tab",
+                        i + 1
+                    ))
+                })?;
+                match weight.trim().parse::<f64>() {
+                    Ok(weight) if weight >= 0.0 => Ok((id.trim().to_string(), weight)),
+                    _ => Err(Error::parse(format!(
+                        "line {} has a bad weight '{}', which should be a number of at least 0",
+                        i + 1,
+                        weight.trim()
+                    ))),
+                }
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { weights })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::from_text(&text).map_err(|e| Error::InvalidFile {
+            kind: "weight table",
+            path: path.to_path_buf(),
+            source: Box::new(e),
+        })
+    }
+
+    /// The weight of each record, by id.  Records that aren't in the table get a weight of 1,
+    /// with a warning.
+    pub fn for_records(&self, records: &[Record]) -> Vec<f64> {
+        let weights = records
+            .iter()
+            .map(|r| self.weights.get(r.id()).copied())
+            .collect::<Vec<_>>();
+
+        let num_missing = weights.iter().filter(|w| w.is_none()).count();
+        if num_missing > 0 {
+            eprintln!("warning: {num_missing} records aren't in the weight table, so they get a weight of 1");
+        }
+
+        weights.into_iter().map(|w| w.unwrap_or(1.0)).collect()
+    }
+}
+
+#[test]
+fn records_are_weighted_by_the_table() {
+    let records = vec![
+        Record::with_attrs("a", None, b"ACDE"),
+        Record::with_attrs("b", None, b"ACDE"),
+    ];
+    let table = WeightTable::from_text("# id\tweight\na\t0.5\n\nc\t2\n").unwrap();
+    assert_eq!(table.for_records(&records), vec![0.5, 1.0]);
+
+    assert!(WeightTable::from_text("a 0.5\n").is_err());
+    assert!(WeightTable::from_text("a\t-1\n").is_err());
+    assert!(WeightTable::from_text("a\thigh\n").is_err());
+}
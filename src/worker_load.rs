@@ -0,0 +1,62 @@
+//! How many pairs and residues each worker aligned, for `--report-worker-load`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub struct WorkerLoad {
+    /// The pairs and residues of each worker, by worker index.
+    counts: Vec<(AtomicU64, AtomicU64)>,
+}
+
+impl WorkerLoad {
+    pub fn new(num_workers: usize) -> Self {
+        Self {
+            counts: (0..num_workers)
+                .map(|_| (AtomicU64::new(0), AtomicU64::new(0)))
+                .collect(),
+        }
+    }
+
+    /// Count a pair with `residues` residues (of both sequences) for `worker`.
+    pub fn add(&self, worker: usize, residues: usize) {
+        let (pairs, total) = &self.counts[worker];
+        pairs.fetch_add(1, Ordering::Relaxed);
+        total.fetch_add(residues as u64, Ordering::Relaxed);
+    }
+
+    /// A line per worker, with its share of all the residues.
+    fn lines(&self) -> Vec<String> {
+        let residues = |i: usize| self.counts[i].1.load(Ordering::Relaxed);
+        let total = (0..self.counts.len()).map(residues).sum::<u64>().max(1);
+
+        (0..self.counts.len())
+            .map(|i| {
+                format!(
+                    "worker {i}: {} pairs, {} residues ({:.1}% of residues)",
+                    self.counts[i].0.load(Ordering::Relaxed),
+                    residues(i),
+                    100.0 * residues(i) as f64 / total as f64
+                )
+            })
+            .collect()
+    }
+
+    pub fn report(&self) {
+        self.lines().iter().for_each(|line| eprintln!("{line}"));
+    }
+}
+
+#[test]
+fn each_worker_gets_its_share() {
+    let load = WorkerLoad::new(2);
+    load.add(0, 30);
+    load.add(0, 30);
+    load.add(1, 20);
+
+    assert_eq!(
+        load.lines(),
+        vec![
+            "worker 0: 2 pairs, 60 residues (75.0% of residues)",
+            "worker 1: 1 pairs, 20 residues (25.0% of residues)",
+        ]
+    );
+}